@@ -0,0 +1,97 @@
+// Baseline timings for the hot paths, on synthetic files of 100 to 10,000 text rows.
+// Run with `cargo bench`; compare against a saved baseline before and after an optimization.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use cfg_bin_text_editor::cfgbin::{synthetic, CfgBin, SaveOptions};
+use cfg_bin_text_editor::crc32;
+
+// (blocks, rows per block)
+const SIZES: [(usize, usize); 3] = [(10, 10), (50, 20), (200, 50)];
+
+fn open_and_save(c: &mut Criterion) {
+    let mut open = c.benchmark_group("open");
+    for (blocks, items) in SIZES {
+        let data = synthetic::build(blocks, items).save().unwrap();
+        open.bench_with_input(
+            BenchmarkId::from_parameter(blocks * items),
+            &data,
+            |b, data| b.iter(|| CfgBin::open(black_box(data)).unwrap()),
+        );
+    }
+    open.finish();
+
+    let mut save = c.benchmark_group("save");
+    for (blocks, items) in SIZES {
+        let cfg = synthetic::build(blocks, items);
+        save.bench_with_input(
+            BenchmarkId::from_parameter(blocks * items),
+            &cfg,
+            |b, cfg| b.iter(|| black_box(cfg).save().unwrap()),
+        );
+    }
+    save.finish();
+
+    // Re-saving with both tables prebuilt, as an editor does after a numeric edit.
+    let mut cached = c.benchmark_group("save_cached");
+    for (blocks, items) in SIZES {
+        let cfg = synthetic::build(blocks, items);
+        let options = SaveOptions::default();
+        let strings = cfg.string_tables(false);
+        let keys = cfg.key_table(&options).unwrap();
+        cached.bench_with_input(
+            BenchmarkId::from_parameter(blocks * items),
+            &cfg,
+            |b, cfg| {
+                b.iter(|| {
+                    black_box(cfg)
+                        .save_with_key_table(&strings, &keys, &options)
+                        .unwrap()
+                })
+            },
+        );
+    }
+    cached.finish();
+}
+
+fn extract_and_update(c: &mut Criterion) {
+    let mut extract = c.benchmark_group("extract_texts");
+    for (blocks, items) in SIZES {
+        let cfg = synthetic::build(blocks, items);
+        extract.bench_with_input(
+            BenchmarkId::from_parameter(blocks * items),
+            &cfg,
+            |b, cfg| b.iter(|| black_box(cfg).extract_texts()),
+        );
+    }
+    extract.finish();
+
+    let mut update = c.benchmark_group("update_texts");
+    for (blocks, items) in SIZES {
+        let mut cfg = synthetic::build(blocks, items);
+        let mut texts = cfg.extract_texts();
+        for text in &mut texts {
+            text.value.push('!');
+        }
+        // Applying the same texts again is idempotent, so one tree serves every iteration.
+        update.bench_function(BenchmarkId::from_parameter(blocks * items), |b| {
+            b.iter(|| cfg.update_texts(black_box(&texts)))
+        });
+    }
+    update.finish();
+}
+
+// One CRC per entry name and key, as `save` computes them; 50,000 names of a large file.
+fn crc32_names(c: &mut Criterion) {
+    let names: Vec<String> = (0..50_000).map(|n| format!("TEXT_INFO_{}", n)).collect();
+    c.bench_function("crc32_50k_names", |b| {
+        b.iter(|| {
+            names.iter().fold(0u32, |acc, name| {
+                acc ^ crc32::compute(black_box(name.as_bytes()))
+            })
+        })
+    });
+}
+
+criterion_group!(benches, open_and_save, extract_and_update, crc32_names);
+criterion_main!(benches);
@@ -1,13 +1,23 @@
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::convert::Infallible;
+use std::io;
+use std::ops::Range;
 
 use anyhow::{anyhow, bail, Context, Result};
-use encoding_rs::SHIFT_JIS;
-use serde::{Deserialize, Serialize};
+use encoding_rs::{SHIFT_JIS, UTF_16LE};
+use serde::ser::SerializeSeq;
+use serde::{Deserialize, Serialize, Serializer};
 use serde_json::Value;
 
 use crate::crc32;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+pub mod nnk;
+// Fixture builder for the benchmarks and the CLI tests; not part of the supported API.
+#[doc(hidden)]
+pub mod synthetic;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum VarType {
     String,
     Int,
@@ -15,7 +25,12 @@ pub enum VarType {
     Unknown,
 }
 
-#[derive(Debug, Clone)]
+/// Serializes externally tagged, e.g. `{"Int": 5}` or `{"String": null}`. A float carries its
+/// bit pattern next to its shortest decimal, `{"Float": {"value": 0.1, "bits": "0x3DCCCCCD"}}`,
+/// so NaN payloads, infinities (`"value": null`) and `-0.0` read back exactly. On input `bits`
+/// is used unless `value` is given and differs from it, so editing only `value` takes effect.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(into = "VarValueRepr", try_from = "VarValueRepr")]
 pub enum VarValue {
     String(Option<String>),
     Int(i32),
@@ -23,15 +38,150 @@ pub enum VarValue {
     Unknown(i32),
 }
 
-#[derive(Debug, Clone)]
+/// Floats compare by bit pattern, as stored in the file: a NaN equals the same NaN, and `0.0`
+/// differs from `-0.0`. This makes equality exact for round-trip checks.
+impl PartialEq for VarValue {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (VarValue::String(a), VarValue::String(b)) => a == b,
+            (VarValue::Int(a), VarValue::Int(b)) => a == b,
+            (VarValue::Float(a), VarValue::Float(b)) => a.to_bits() == b.to_bits(),
+            (VarValue::Unknown(a), VarValue::Unknown(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for VarValue {}
+
+#[derive(Serialize, Deserialize)]
+enum VarValueRepr {
+    String(Option<String>),
+    Int(i32),
+    Float(FloatRepr),
+    Unknown(i32),
+}
+
+#[derive(Serialize, Deserialize)]
+struct FloatRepr {
+    #[serde(default)]
+    value: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bits: Option<String>,
+}
+
+impl From<VarValue> for VarValueRepr {
+    fn from(value: VarValue) -> Self {
+        match value {
+            VarValue::String(s) => VarValueRepr::String(s),
+            VarValue::Int(n) => VarValueRepr::Int(n),
+            VarValue::Float(f) => VarValueRepr::Float(FloatRepr {
+                value: f.is_finite().then_some(f),
+                bits: Some(format!("0x{:08X}", f.to_bits())),
+            }),
+            VarValue::Unknown(n) => VarValueRepr::Unknown(n),
+        }
+    }
+}
+
+impl TryFrom<VarValueRepr> for VarValue {
+    type Error = String;
+
+    fn try_from(repr: VarValueRepr) -> std::result::Result<Self, String> {
+        Ok(match repr {
+            VarValueRepr::String(s) => VarValue::String(s),
+            VarValueRepr::Int(n) => VarValue::Int(n),
+            VarValueRepr::Float(FloatRepr {
+                bits: Some(bits),
+                value,
+            }) => {
+                let hex = bits.strip_prefix("0x").unwrap_or(&bits);
+                let bits = u32::from_str_radix(hex, 16)
+                    .map_err(|_| format!("Invalid float bits {:?}", bits))?;
+                let exact = f32::from_bits(bits);
+                // Equal numbers keep the bits, which tell -0.0 from 0.0.
+                match value {
+                    Some(f) if f != exact => VarValue::Float(f),
+                    _ => VarValue::Float(exact),
+                }
+            }
+            VarValueRepr::Float(FloatRepr { value: Some(f), .. }) => VarValue::Float(f),
+            VarValueRepr::Float(_) => return Err("A float needs a value or bits".to_string()),
+            VarValueRepr::Unknown(n) => VarValue::Unknown(n),
+        })
+    }
+}
+
+impl VarType {
+    pub fn name(self) -> &'static str {
+        match self {
+            VarType::String => "string",
+            VarType::Int => "int",
+            VarType::Float => "float",
+            VarType::Unknown => "unknown",
+        }
+    }
+
+    /// Whether `text` reads as a value of this type, as `CfgBin::update_values` parses it.
+    pub fn accepts(self, text: &str) -> bool {
+        VarValue::default_of_type(self).with_text(text).is_some()
+    }
+}
+
+impl VarValue {
+    /// The value as extracted for `var_type` slots: strings as is (null reads as ""), numbers
+    /// in decimal, floats in their shortest form that parses back to the same value. `None`
+    /// if the value has another type.
+    pub fn text_of_type(&self, var_type: VarType) -> Option<String> {
+        match (self, var_type) {
+            (VarValue::String(s), VarType::String) => Some(s.clone().unwrap_or_default()),
+            (VarValue::Int(n), VarType::Int) | (VarValue::Unknown(n), VarType::Unknown) => {
+                Some(n.to_string())
+            }
+            (VarValue::Float(f), VarType::Float) => Some(f.to_string()),
+            _ => None,
+        }
+    }
+
+    pub fn has_type(&self, var_type: VarType) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(&Self::default_of_type(var_type))
+    }
+
+    // `text` parsed as a value of this value's type (the inverse of `text_of_type`)
+    fn with_text(&self, text: &str) -> Option<VarValue> {
+        match self {
+            VarValue::String(_) => Some(VarValue::String(Some(text.to_string()))),
+            VarValue::Int(_) => text.trim().parse().ok().map(VarValue::Int),
+            VarValue::Float(_) => text.trim().parse().ok().map(VarValue::Float),
+            VarValue::Unknown(_) => text.trim().parse().ok().map(VarValue::Unknown),
+        }
+    }
+
+    fn default_of_type(var_type: VarType) -> VarValue {
+        match var_type {
+            VarType::String => VarValue::String(None),
+            VarType::Int => VarValue::Int(0),
+            VarType::Float => VarValue::Float(0.0),
+            VarType::Unknown => VarValue::Unknown(0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Variable {
     pub var_type: VarType,
     pub value: VarValue,
 }
 
-#[derive(Debug, Clone)]
+/// Equality compares names, variables (see `VarValue`), children and `end_terminator`.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Entry {
+    /// Display name: `open` gives each row its key plus an `_N` occurrence suffix (`SLOT_3_0`
+    /// is the first row keyed `SLOT_3`).
     pub name: String,
+    /// Key name the row was read with. `save` writes it when set; `None` makes `name` itself
+    /// the key, as for hand-built entries.
+    pub source_name: Option<String>,
     pub variables: Vec<Variable>,
     pub children: Vec<Entry>,
     pub end_terminator: bool,
@@ -39,20 +189,57 @@ pub struct Entry {
 
 impl Entry {
     fn get_name(&self) -> String {
-        let parts: Vec<&str> = self.name.split('_').collect();
-        if parts.len() > 1 {
-            parts[..parts.len() - 1].join("_")
-        } else {
-            self.name.clone()
-        }
+        self.source_name
+            .clone()
+            .unwrap_or_else(|| self.name.clone())
     }
 
-    fn count(&self) -> i32 {
-        let mut total = 1 + if self.end_terminator { 1 } else { 0 };
-        for child in &self.children {
-            total += child.count();
-        }
-        total
+    fn is_block(&self) -> bool {
+        let name = self.get_name();
+        let token = name.rsplit('_').next().unwrap_or(&name);
+        self.end_terminator && is_begin_token(token)
+    }
+
+    // No children and nothing but null or empty strings (see `CfgBin::strip_empty_entries`).
+    fn is_empty_leaf(&self) -> bool {
+        self.children.is_empty()
+            && !self.is_block()
+            && !self.variables.is_empty()
+            && self.variables.iter().all(|var| match &var.value {
+                VarValue::String(value) => value.as_deref().unwrap_or("").is_empty(),
+                _ => false,
+            })
+    }
+
+    /// Tree nodes in this subtree, the entry itself included.
+    pub fn node_count(&self) -> usize {
+        1 + self.children.iter().map(Entry::node_count).sum::<usize>()
+    }
+
+    /// END rows `save` writes for this subtree: one per entry with `end_terminator` set.
+    /// They are not tree nodes; the parser folds them into their block's `end_terminator`.
+    pub fn end_terminator_count(&self) -> usize {
+        usize::from(self.end_terminator)
+            + self
+                .children
+                .iter()
+                .map(Entry::end_terminator_count)
+                .sum::<usize>()
+    }
+
+    /// Rows `save` writes for this subtree, `node_count() + end_terminator_count()`; summed over
+    /// the top-level entries this is the header's `entries_count`.
+    pub fn serialized_count(&self) -> usize {
+        self.node_count() + self.end_terminator_count()
+    }
+
+    fn max_depth(&self) -> usize {
+        1 + self
+            .children
+            .iter()
+            .map(|child| child.max_depth())
+            .max()
+            .unwrap_or(0)
     }
 
     fn get_unique_keys(&self) -> Vec<String> {
@@ -69,11 +256,7 @@ impl Entry {
             }
         }
         if self.end_terminator {
-            let end_name = if current_name.starts_with("PTREE") {
-                "_PTREE".to_string()
-            } else {
-                current_name.replace("BEGIN", "END").replace("BEG", "END")
-            };
+            let end_name = end_name_for(&current_name);
             if !keys.contains(&end_name) {
                 keys.push(end_name);
             }
@@ -86,8 +269,13 @@ impl Entry {
         let groups = (types.len() as f64 / 4.0).ceil() as usize;
         for i in 0..groups {
             let mut type_desc: u8 = 0;
-            for j in (4 * i)..std::cmp::min(4 * (i + 1), types.len()) {
-                let tag = match types[j] {
+            for (j, var_type) in types
+                .iter()
+                .enumerate()
+                .take(std::cmp::min(4 * (i + 1), types.len()))
+                .skip(4 * i)
+            {
+                let tag = match var_type {
                     VarType::String => 0,
                     VarType::Int => 1,
                     VarType::Float => 2,
@@ -104,57 +292,73 @@ impl Entry {
         bytes
     }
 
+    // `string_offset` yields the string-table offset for each string value, in traversal order;
+    // `row_key` sees the key name and CRC of every row written, END rows included.
     fn encode_entry(
         &self,
-        strings_table: &HashMap<String, i32>,
+        string_offset: &mut dyn FnMut(&str) -> i32,
+        row_key: &mut dyn FnMut(&str, u32),
         encoding: &CfgBinEncoding,
-    ) -> Vec<u8> {
+        endian: Endian,
+    ) -> Result<Vec<u8>> {
         let mut buf = Vec::new();
         let entry_name = self.get_name();
-        let crc = crc32::compute(&encode_string_bytes(&entry_name, encoding));
+        let crc = key_crc(&entry_name, encoding);
+        row_key(&entry_name, crc);
 
-        buf.extend_from_slice(&crc.to_le_bytes());
+        buf.extend_from_slice(&endian.u32_bytes(crc));
 
         let types: Vec<VarType> = self.variables.iter().map(|v| v.var_type).collect();
-        buf.push(types.len() as u8);
+        let Ok(count) = u8::try_from(types.len()) else {
+            bail!(
+                "Entry {} has {} variables; a row holds at most {}",
+                self.name,
+                types.len(),
+                MAX_VARIABLES
+            );
+        };
+        buf.push(count);
         buf.extend_from_slice(&Self::encode_types(&types));
 
         for var in &self.variables {
             match &var.value {
                 VarValue::String(Some(s)) => {
-                    if let Some(&offset) = strings_table.get(s) {
-                        buf.extend_from_slice(&offset.to_le_bytes());
-                    } else {
-                        buf.extend_from_slice(&(-1i32).to_le_bytes());
-                    }
+                    buf.extend_from_slice(&endian.i32_bytes(string_offset(s)));
                 }
                 VarValue::String(None) => {
-                    buf.extend_from_slice(&(-1i32).to_le_bytes());
+                    buf.extend_from_slice(&endian.i32_bytes(-1));
                 }
-                VarValue::Int(v) => buf.extend_from_slice(&v.to_le_bytes()),
-                VarValue::Float(v) => buf.extend_from_slice(&v.to_le_bytes()),
-                VarValue::Unknown(v) => buf.extend_from_slice(&v.to_le_bytes()),
+                VarValue::Int(v) => buf.extend_from_slice(&endian.i32_bytes(*v)),
+                VarValue::Float(v) => buf.extend_from_slice(&endian.f32_bytes(*v)),
+                VarValue::Unknown(v) => buf.extend_from_slice(&endian.i32_bytes(*v)),
             }
         }
 
         for child in &self.children {
-            buf.extend_from_slice(&child.encode_entry(strings_table, encoding));
+            buf.extend_from_slice(&child.encode_entry(string_offset, row_key, encoding, endian)?);
         }
 
         if self.end_terminator {
-            let end_name = if entry_name.starts_with("PTREE") {
-                "_PTREE".to_string()
-            } else {
-                self.get_name()
-                    .replace("BEGIN", "END")
-                    .replace("BEG", "END")
-            };
-            let end_crc = crc32::compute(&encode_string_bytes(&end_name, encoding));
-            buf.extend_from_slice(&end_crc.to_le_bytes());
+            let end_name = end_name_for(&entry_name);
+            let end_crc = key_crc(&end_name, encoding);
+            row_key(&end_name, end_crc);
+            buf.extend_from_slice(&endian.u32_bytes(end_crc));
             buf.extend_from_slice(&[0x00, 0xFF, 0xFF, 0xFF]);
         }
 
-        buf
+        Ok(buf)
+    }
+
+    // Every string value in traversal order, repeats included.
+    fn collect_string_references(&self, out: &mut Vec<String>) {
+        for var in &self.variables {
+            if let VarValue::String(Some(s)) = &var.value {
+                out.push(s.clone());
+            }
+        }
+        for child in &self.children {
+            child.collect_string_references(out);
+        }
     }
 
     fn collect_strings(&self) -> Vec<String> {
@@ -177,82 +381,625 @@ impl Entry {
     }
 }
 
+// PTREE blocks are the one begin/end pair that doesn't follow the *_BEGIN/*_END naming: a block
+// opens with an entry named "PTREE" and is closed by one named "_PTREE". Every PTREE rule lives in
+// the helpers below:
+// - parse: a name is a PTREE begin when its type token is "PTREE" and it isn't a "_PTREE" end
+//   (`process_entries` checks the token, `is_ptree_end` the rest);
+// - parse: a "_PTREE" end closes the innermost open PTREE block (`innermost_open_ptree`), so
+//   nested PTREE blocks unwind in order regardless of their occurrence suffixes;
+// - save: the terminator written after a PTREE block's children is "_PTREE" (`end_name_for`).
+const PTREE_BEGIN: &str = "PTREE";
+const PTREE_END: &str = "_PTREE";
+
+fn is_ptree_end(name: &str) -> bool {
+    name.contains(PTREE_END)
+}
+
+// Occurrence-suffixed name ("PTREE_3") of the deepest PTREE block still open, if any.
+fn innermost_open_ptree(depth: &[(String, usize)]) -> Option<String> {
+    depth
+        .iter()
+        .filter(|(k, _)| {
+            k.rsplit_once('_')
+                .is_some_and(|(base, _)| base == PTREE_BEGIN)
+        })
+        .max_by_key(|(_, v)| *v)
+        .map(|(k, _)| k.clone())
+}
+
+/// Key name of the terminator row written after a block's children (`begin_name` without
+/// occurrence suffix). Only the trailing type token is rewritten, so `STARTUP_MENU_START` is
+/// closed by `STARTUP_MENU_END` and `MENUBEG` by `MENUEND`.
+fn end_name_for(begin_name: &str) -> String {
+    if begin_name.starts_with(PTREE_BEGIN) {
+        return PTREE_END.to_string();
+    }
+    let (head, token) = match begin_name.rsplit_once('_') {
+        Some((head, token)) => (&begin_name[..head.len() + 1], token),
+        None => ("", begin_name),
+    };
+    ["BEGIN", "BEG", "START"]
+        .iter()
+        .find_map(|begin| token.strip_suffix(begin))
+        .map_or_else(
+            || begin_name.to_string(),
+            |stem| format!("{}{}END", head, stem),
+        )
+}
+
+// Whether a name's type token (the last `_` part of a base name) opens a block on parse.
+fn is_begin_token(token: &str) -> bool {
+    let token = token.to_lowercase();
+    token.ends_with("beg")
+        || token.ends_with("begin")
+        || token.ends_with("start")
+        || token.ends_with("ptree")
+}
+
+// `end_name_for`, or None when it can't derive a real END name: it only rewrites the upper-case
+// BEGIN/BEG/START tokens, so e.g. "LIST_Begin" would be closed by itself.
+fn derived_end_name(begin_name: &str) -> Option<String> {
+    let end_name = end_name_for(begin_name);
+    let derived = end_name != begin_name && end_name.contains("END");
+    (end_name == PTREE_END || derived).then_some(end_name)
+}
+
+// A block's END name must be derivable from its begin name.
+fn check_end_terminators(entries: &[Entry]) -> Result<()> {
+    for entry in entries {
+        let name = entry.get_name();
+        if entry.is_block() && !is_ptree_end(&name) && derived_end_name(&name).is_none() {
+            bail!(
+                "Cannot derive the END terminator name for entry {}: expected an upper-case BEGIN, BEG or START token",
+                entry.name
+            );
+        }
+        check_end_terminators(&entry.children)?;
+    }
+    Ok(())
+}
+
+/// Fill byte of each 16-byte alignment gap, captured at `open` so `save` reproduces files that
+/// don't pad with 0xFF (some regions use 0x00).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Padding {
+    /// Between the entries and the string table
+    pub entries: u8,
+    /// Between the string table and the key table
+    pub strings: u8,
+    /// After the key rows and after the key strings
+    pub key_table: u8,
+    /// After the footer
+    pub footer: u8,
+}
+
+impl Default for Padding {
+    fn default() -> Self {
+        Padding {
+            entries: 0xFF,
+            strings: 0xFF,
+            key_table: 0xFF,
+            footer: 0xFF,
+        }
+    }
+}
+
+// First byte of the gap `start..end`, if the gap is not empty.
+fn pad_byte_at(data: &[u8], start: usize, end: usize) -> Option<u8> {
+    (start < end.min(data.len())).then(|| data[start])
+}
+
+/// Byte order of the integers and floats in the header, entry rows, key table and footer.
+/// Strings and type descriptors are plain bytes and read the same either way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Endian {
+    /// Every file seen so far
+    #[default]
+    Little,
+    /// For titles from big-endian consoles
+    Big,
+}
+
+impl Endian {
+    fn read_u32(self, data: &[u8], pos: usize) -> u32 {
+        let bytes = [data[pos], data[pos + 1], data[pos + 2], data[pos + 3]];
+        match self {
+            Endian::Little => u32::from_le_bytes(bytes),
+            Endian::Big => u32::from_be_bytes(bytes),
+        }
+    }
+
+    fn read_i32(self, data: &[u8], pos: usize) -> i32 {
+        self.read_u32(data, pos) as i32
+    }
+
+    fn read_f32(self, data: &[u8], pos: usize) -> f32 {
+        f32::from_bits(self.read_u32(data, pos))
+    }
+
+    fn read_u16(self, data: &[u8], pos: usize) -> u16 {
+        let bytes = [data[pos], data[pos + 1]];
+        match self {
+            Endian::Little => u16::from_le_bytes(bytes),
+            Endian::Big => u16::from_be_bytes(bytes),
+        }
+    }
+
+    fn u32_bytes(self, v: u32) -> [u8; 4] {
+        match self {
+            Endian::Little => v.to_le_bytes(),
+            Endian::Big => v.to_be_bytes(),
+        }
+    }
+
+    fn i32_bytes(self, v: i32) -> [u8; 4] {
+        self.u32_bytes(v as u32)
+    }
+
+    fn f32_bytes(self, v: f32) -> [u8; 4] {
+        self.u32_bytes(v.to_bits())
+    }
+
+    fn u16_bytes(self, v: u16) -> [u8; 2] {
+        match self {
+            Endian::Little => v.to_le_bytes(),
+            Endian::Big => v.to_be_bytes(),
+        }
+    }
+}
+
+/// Layout of the records in the string table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StringFormat {
+    /// Bytes followed by a 0x00 terminator (every file seen so far)
+    #[default]
+    NullTerminated,
+    /// u32 byte length (in the file's `Endian`) followed by the bytes, without terminator
+    LengthPrefixed,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum CfgBinEncoding {
     Utf8,
     ShiftJis,
+    /// Strings and key names as UTF-16LE code units, ended by a 16-bit zero. Detected for
+    /// footer value `FOOTER_ENCODING_UTF16LE` when the string table looks like UTF-16LE.
+    Utf16Le,
+}
+
+impl CfgBinEncoding {
+    /// What ends a null-terminated record: one 0x00 byte, or two for UTF-16.
+    pub fn terminator(self) -> &'static [u8] {
+        match self {
+            CfgBinEncoding::Utf16Le => &[0, 0],
+            CfgBinEncoding::Utf8 | CfgBinEncoding::ShiftJis => &[0],
+        }
+    }
+}
+
+/// Structural metrics of one top-level entry, as reported by `info`.
+#[derive(Debug, Clone)]
+pub struct EntryStats {
+    pub name: String,
+    pub child_count: usize,
+    // Nesting depth including the entry itself (a childless entry has depth 1).
+    pub max_depth: usize,
+    // Serialized rows under this entry, end terminators included (see `Entry::serialized_count`).
+    pub total_count: usize,
 }
 
 pub struct CfgBin {
     pub encoding: CfgBinEncoding,
-    // Raw footer encoding (u16 at file_end - 0x0A). Some files use values like 0x0100/0x0101 for UTF-8.
+    // Raw footer encoding (u16 after the footer magic). Some files use values like 0x0100/0x0101 for UTF-8.
     pub footer_encoding: u16,
     pub entries: Vec<Entry>,
+    // Key names in the source key-table row order (empty when built in memory).
+    pub key_order: Vec<String>,
+    // Entry CRCs missing from the key table, replaced by `UNKNOWN_0x<crc>` placeholder names.
+    // Only populated with `OpenOptions::ignore_unknown_crc`. `save` writes a placeholder's CRC
+    // back to its rows and, as in the source, leaves it out of the key table.
+    pub unresolved_crcs: Vec<u32>,
+    // String table layout used to read (and, by `save`, write) the strings.
+    pub string_format: StringFormat,
+    // String offsets whose read ran past the string table or decoded to control characters;
+    // many of them suggest the file uses the other `StringFormat`.
+    pub suspect_string_offsets: Vec<i32>,
+    // CRC stored for each key name in the source key table (empty when built in memory).
+    pub original_key_crcs: BTreeMap<String, u32>,
+    // The source key-string blob, reused by `SaveOptions::preserve_key_strings`.
+    pub original_key_strings: KeyStrings,
+    // Alignment fill bytes used by `save`.
+    pub padding: Padding,
+    // CRCs of every entry row in the source, including end terminators.
+    pub referenced_crcs: BTreeSet<u32>,
+    // Bytes left between the last parsed row and the string table beyond alignment padding;
+    // non-zero suggests the entry count stopped parsing early.
+    pub unparsed_entry_bytes: usize,
+    // Byte order read by `open` and written by `save`.
+    pub endian: Endian,
+    // The footer u16s before and after the encoding, kept from the source so variants that
+    // don't use the usual FOOTER_UNK1/FOOTER_UNK2 survive a save.
+    pub footer_unk1: u16,
+    pub footer_unk2: u16,
+    // File address of the offset field of every string variable, by text index (as numbered
+    // by `extract_texts`). Describes the source file, so entries added or removed since `open`
+    // make it stale; empty when built in memory. Bridges standard texts to `nnk` addresses.
+    pub string_field_addresses: Vec<u32>,
+    // Source bytes of the string values that re-encoding wouldn't reproduce; see
+    // `OriginalStrings`. None when built in memory or when every string encodes back as read.
+    pub original_strings: Option<OriginalStrings>,
 }
 
-fn read_i32(data: &[u8], pos: usize) -> i32 {
-    i32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+/// String-table bytes of the values whose canonical encoding differs from the source (Shift-JIS
+/// has a few characters with two valid codes). While `CfgBin::encoding` is still `encoding`,
+/// `save` writes a value listed here with its source bytes, so unedited strings stay
+/// byte-identical; other values are encoded as usual. A value read from several different byte
+/// sequences keeps the first.
+#[derive(Debug, Clone)]
+pub struct OriginalStrings {
+    pub encoding: CfgBinEncoding,
+    pub bytes: HashMap<String, Vec<u8>>,
+}
+
+/// Options for `CfgBin::open_with_options`.
+#[derive(Debug, Clone, Default)]
+pub struct OpenOptions {
+    /// Name entries whose CRC is not in the key table `UNKNOWN_0x<crc>` instead of failing.
+    pub ignore_unknown_crc: bool,
+    /// String table layout; null-terminated unless a game is known to differ.
+    pub string_format: StringFormat,
+    /// Parse this many entry rows instead of the header's `entries_count`.
+    pub entries_count: Option<usize>,
+    /// Decode strings with this encoding instead of the one the footer names.
+    pub encoding: Option<CfgBinEncoding>,
+    /// Switch to the other encoding when it decodes the strings with clearly fewer anomalies
+    /// (see `EncodingScores::better_alternative`). Ignored when `encoding` is set.
+    pub auto_encoding: bool,
+    /// Byte order of the file's integer fields.
+    pub endian: Endian,
+}
+
+/// Non-ASCII strings of a file and how many of them look mis-decoded under each encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncodingScores {
+    pub strings: usize,
+    pub shift_jis_anomalies: usize,
+    pub utf8_anomalies: usize,
+}
+
+impl EncodingScores {
+    // Share of mis-decoded strings above which the detected encoding is considered wrong.
+    const MISDECODED_SHARE: f64 = 0.25;
+
+    /// Mis-decoded strings under `encoding`; UTF-16 isn't scored and counts 0.
+    pub fn anomalies(&self, encoding: CfgBinEncoding) -> usize {
+        match encoding {
+            CfgBinEncoding::ShiftJis => self.shift_jis_anomalies,
+            CfgBinEncoding::Utf8 => self.utf8_anomalies,
+            CfgBinEncoding::Utf16Le => 0,
+        }
+    }
+
+    /// The other encoding, if `detected` mis-decodes a high share of the strings and the
+    /// other one does better.
+    pub fn better_alternative(&self, detected: CfgBinEncoding) -> Option<CfgBinEncoding> {
+        let other = match detected {
+            CfgBinEncoding::ShiftJis => CfgBinEncoding::Utf8,
+            CfgBinEncoding::Utf8 => CfgBinEncoding::ShiftJis,
+            CfgBinEncoding::Utf16Le => return None,
+        };
+        let bad = self.anomalies(detected);
+        let share = bad as f64 / self.strings.max(1) as f64;
+        (share >= Self::MISDECODED_SHARE && self.anomalies(other) < bad).then_some(other)
+    }
+}
+
+/// Whether `s` shows typical signs of bytes decoded with the wrong encoding: replacement
+/// characters, control characters other than line breaks and tabs, private-use characters,
+/// or (for Shift-JIS, where UTF-8 bytes land there) half-width katakana.
+pub fn looks_misdecoded(s: &str, encoding: CfgBinEncoding) -> bool {
+    s.chars().any(|c| {
+        c == '\u{FFFD}'
+            || (c.is_control() && !matches!(c, '\n' | '\r' | '\t'))
+            || ('\u{E000}'..='\u{F8FF}').contains(&c)
+            || (encoding == CfgBinEncoding::ShiftJis && ('\u{FF61}'..='\u{FF9F}').contains(&c))
+    })
+}
+
+/// Options for `CfgBin::save_with_options`.
+#[derive(Debug, Clone, Default)]
+pub struct SaveOptions {
+    /// Explicit key-table order. Listed keys that are still in use are written first, in
+    /// this order; keys not listed follow in traversal order. Pass `CfgBin::key_order` to
+    /// reproduce the source file's order for games that don't tolerate reordering.
+    pub key_order: Option<Vec<String>>,
+    /// Write a separate string-table record for every string reference instead of sharing one
+    /// record per distinct value, for games that expect each reference to own its copy.
+    pub no_dedup_strings: bool,
+    /// Write the source key-string blob unchanged and point each key at its original offset, so
+    /// an unedited file keeps its key-string layout. Keys new to the file are appended. Ignored
+    /// once `encoding` differs from the source's, as the blob holds the old encoding's bytes.
+    pub preserve_key_strings: bool,
 }
 
-fn read_u32(data: &[u8], pos: usize) -> u32 {
-    u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+// Little-endian read, for the paths that only support little-endian files (nnk).
+fn read_i32(data: &[u8], pos: usize) -> i32 {
+    Endian::Little.read_i32(data, pos)
 }
 
-fn read_u16(data: &[u8], pos: usize) -> u16 {
-    u16::from_le_bytes([data[pos], data[pos + 1]])
+// The string table and key table ranges the header of `data` describes; entry rows fill
+// 0x10..string_table.start.
+fn regions(data: &[u8], endian: Endian) -> Result<(Range<usize>, Range<usize>)> {
+    if data.len() < 16 {
+        bail!("cfg.bin is too small");
+    }
+    let string_table = checked_range(
+        data.len(),
+        endian.read_i32(data, 4),
+        endian.read_i32(data, 8),
+        "String table",
+    )?;
+    if string_table.start < 0x10 {
+        bail!(
+            "String table offset 0x{:X} is before entries start",
+            string_table.start
+        );
+    }
+    let key_table_offset = round_up(string_table.end, 16);
+    if key_table_offset + 16 > data.len() {
+        bail!(
+            "Key table header at 0x{:X} is out of bounds",
+            key_table_offset
+        );
+    }
+    let key_table = checked_range(
+        data.len(),
+        key_table_offset as i32,
+        endian.read_i32(data, key_table_offset),
+        "Key table",
+    )?;
+    Ok((string_table, key_table))
 }
 
-fn read_f32(data: &[u8], pos: usize) -> f32 {
-    f32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+// Validates an offset/length pair read from the file and returns it as a range within `data_len`.
+fn checked_range(data_len: usize, offset: i32, length: i32, what: &str) -> Result<Range<usize>> {
+    if offset < 0 || length < 0 {
+        bail!("Invalid {}: offset {}, length {}", what, offset, length);
+    }
+    let start = offset as usize;
+    let end = start
+        .checked_add(length as usize)
+        .filter(|&end| end <= data_len)
+        .with_context(|| {
+            format!(
+                "{} (0x{:X} + 0x{:X}) is out of bounds (size 0x{:X})",
+                what, offset, length, data_len
+            )
+        })?;
+    Ok(start..end)
 }
 
-fn decode_string(data: &[u8], encoding: &CfgBinEncoding) -> String {
+/// Decodes string-table bytes; invalid sequences become U+FFFD.
+pub fn decode_string(data: &[u8], encoding: &CfgBinEncoding) -> String {
     match encoding {
         CfgBinEncoding::Utf8 => String::from_utf8_lossy(data).to_string(),
         CfgBinEncoding::ShiftJis => {
             let (cow, _, _) = SHIFT_JIS.decode(data);
             cow.to_string()
         }
+        CfgBinEncoding::Utf16Le => UTF_16LE.decode_without_bom_handling(data).0.into_owned(),
     }
 }
 
-fn encode_string_bytes(s: &str, encoding: &CfgBinEncoding) -> Vec<u8> {
+/// The bytes `save` writes for `s` (and hashes for a key name's CRC), without a terminator.
+/// Shift-JIS writes characters it lacks as `&#NNNN;` references; check with `can_encode`.
+pub fn encode_string_bytes(s: &str, encoding: &CfgBinEncoding) -> Vec<u8> {
     match encoding {
         CfgBinEncoding::Utf8 => s.as_bytes().to_vec(),
         CfgBinEncoding::ShiftJis => {
             let (cow, _, _) = SHIFT_JIS.encode(s);
             cow.to_vec()
         }
+        // encoding_rs only decodes UTF-16.
+        CfgBinEncoding::Utf16Le => s.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+    }
+}
+
+/// Whether `s` can be written in `encoding` as is. Shift-JIS lacks many characters, which the
+/// encoder would otherwise replace with `&#NNNN;` references.
+pub fn can_encode(s: &str, encoding: CfgBinEncoding) -> bool {
+    match encoding {
+        CfgBinEncoding::Utf8 | CfgBinEncoding::Utf16Le => true,
+        CfgBinEncoding::ShiftJis => !SHIFT_JIS.encode(s).2,
+    }
+}
+
+/// Candidate key names that hash to the same CRC32 in `encoding`, sorted by CRC. Repeated
+/// names count once; each collision lists its names in input order.
+pub fn crc_collisions<'a>(
+    names: impl IntoIterator<Item = &'a str>,
+    encoding: CfgBinEncoding,
+) -> Vec<CrcCollision> {
+    let mut by_crc: BTreeMap<u32, Vec<String>> = BTreeMap::new();
+    for name in names {
+        let crc = crc32::compute(&encode_string_bytes(name, &encoding));
+        let names = by_crc.entry(crc).or_default();
+        if !names.iter().any(|n| n == name) {
+            names.push(name.to_string());
+        }
     }
+    by_crc
+        .into_iter()
+        .filter(|(_, names)| names.len() > 1)
+        .map(|(crc, names)| CrcCollision { crc, names })
+        .collect()
 }
 
-fn read_null_terminated_string_at(
+/// Decodes the string starting at `offset` up to the encoding's terminator (or the end of
+/// `data`); None if `offset` is past the end.
+pub fn read_null_terminated_string_at(
     data: &[u8],
     offset: usize,
     encoding: &CfgBinEncoding,
 ) -> Option<String> {
+    null_terminated_bytes_at(data, offset, encoding).map(|bytes| decode_string(bytes, encoding))
+}
+
+// The bytes from `offset` up to the terminator. UTF-16 ends at a zero code unit, so the search
+// steps two bytes at a time from `offset`.
+fn null_terminated_bytes_at<'a>(
+    data: &'a [u8],
+    offset: usize,
+    encoding: &CfgBinEncoding,
+) -> Option<&'a [u8]> {
     if offset >= data.len() {
         return None;
     }
 
-    let rel_end = data[offset..].iter().position(|&b| b == 0);
-    let end = match rel_end {
-        Some(i) => offset + i,
-        None => data.len(),
+    let rest = &data[offset..];
+    let unit = encoding.terminator().len();
+    let end = rest
+        .chunks(unit)
+        .position(|c| c == encoding.terminator())
+        .map_or(rest.len(), |i| i * unit);
+
+    Some(&rest[..end])
+}
+
+const PLACEHOLDER_PREFIX: &str = "UNKNOWN_0x";
+
+// Name given to an entry whose CRC is missing from the key table (`OpenOptions::ignore_unknown_crc`).
+fn placeholder_name(crc: u32) -> String {
+    format!("{}{:08X}", PLACEHOLDER_PREFIX, crc)
+}
+
+fn placeholder_crc(name: &str) -> Option<u32> {
+    u32::from_str_radix(name.strip_prefix(PLACEHOLDER_PREFIX)?, 16).ok()
+}
+
+// CRC a key name is stored under; placeholders carry their original CRC.
+fn key_crc(name: &str, encoding: &CfgBinEncoding) -> u32 {
+    placeholder_crc(name).unwrap_or_else(|| crc32::compute(&encode_string_bytes(name, encoding)))
+}
+
+// Length-prefixed record: u32 byte length, then the bytes, no terminator.
+fn length_prefixed_bytes_at(data: &[u8], offset: usize, endian: Endian) -> Option<&[u8]> {
+    let start = offset.checked_add(4)?;
+    if start > data.len() {
+        return None;
+    }
+    let len = endian.read_u32(data, offset) as usize;
+    let end = start.checked_add(len)?.min(data.len());
+    Some(&data[start..end])
+}
+
+// A read looks wrong when it runs off the end of the table (no terminator / length too long) or
+// decodes to control characters, both typical of the string table using the other layout.
+fn is_suspect_string_read(
+    data: &[u8],
+    offset: usize,
+    format: StringFormat,
+    endian: Endian,
+    encoding: &CfgBinEncoding,
+    text: &str,
+) -> bool {
+    let overran = match format {
+        StringFormat::NullTerminated => {
+            let terminator = encoding.terminator();
+            !data[offset.min(data.len())..]
+                .chunks(terminator.len())
+                .any(|c| c == terminator)
+        }
+        StringFormat::LengthPrefixed => {
+            offset + 4 > data.len()
+                || offset + 4 + endian.read_u32(data, offset) as usize > data.len()
+        }
     };
+    overran
+        || text
+            .chars()
+            .any(|c| c.is_control() && !matches!(c, '\t' | '\n' | '\r'))
+}
+
+// Reads string variables from the string table blob, caching by offset and remembering offsets
+// whose read looked wrong (see `CfgBin::suspect_string_offsets`).
+struct StringTableReader<'a> {
+    data: &'a [u8],
+    encoding: CfgBinEncoding,
+    format: StringFormat,
+    endian: Endian,
+    cache: HashMap<i32, Option<String>>,
+    suspect_offsets: Vec<i32>,
+    // Values whose bytes differ from their canonical encoding (`OriginalStrings::bytes`).
+    original_bytes: HashMap<String, Vec<u8>>,
+}
+
+impl<'a> StringTableReader<'a> {
+    fn new(data: &'a [u8], encoding: CfgBinEncoding, format: StringFormat, endian: Endian) -> Self {
+        StringTableReader {
+            data,
+            encoding,
+            format,
+            endian,
+            cache: HashMap::new(),
+            suspect_offsets: Vec::new(),
+            original_bytes: HashMap::new(),
+        }
+    }
 
-    Some(decode_string(&data[offset..end], encoding))
+    fn read(&mut self, offset: i32) -> Option<String> {
+        if offset < 0 {
+            return None;
+        }
+        if let Some(v) = self.cache.get(&offset) {
+            return v.clone();
+        }
+        let pos = offset as usize;
+        let bytes = match self.format {
+            StringFormat::NullTerminated => {
+                null_terminated_bytes_at(self.data, pos, &self.encoding)
+            }
+            StringFormat::LengthPrefixed => length_prefixed_bytes_at(self.data, pos, self.endian),
+        };
+        let v = bytes.map(|bytes| {
+            let text = decode_string(bytes, &self.encoding);
+            if encode_string_bytes(&text, &self.encoding) != bytes {
+                self.original_bytes
+                    .entry(text.clone())
+                    .or_insert_with(|| bytes.to_vec());
+            }
+            text
+        });
+        if let Some(text) = &v {
+            if is_suspect_string_read(
+                self.data,
+                pos,
+                self.format,
+                self.endian,
+                &self.encoding,
+                text,
+            ) {
+                self.suspect_offsets.push(offset);
+            }
+        }
+        self.cache.insert(offset, v.clone());
+        v
+    }
 }
 
-fn round_up(n: usize, exp: usize) -> usize {
-    ((n + exp - 1) / exp) * exp
+/// Rounds `n` up to the next multiple of `exp` (which must be non-zero).
+pub(crate) fn round_up(n: usize, exp: usize) -> usize {
+    n.div_ceil(exp) * exp
 }
 
-fn write_alignment(buf: &mut Vec<u8>, alignment: usize, pad_byte: u8) {
+/// Pads `buf` with `pad_byte` until its length is a multiple of `alignment` (which must be
+/// non-zero); already-aligned buffers are left untouched.
+pub(crate) fn write_alignment(buf: &mut Vec<u8>, alignment: usize, pad_byte: u8) {
     let remainder = buf.len() % alignment;
     if remainder != 0 {
         let padding = alignment - remainder;
-        buf.extend(std::iter::repeat(pad_byte).take(padding));
+        buf.extend(std::iter::repeat_n(pad_byte, padding));
     }
 }
 
@@ -266,27 +1013,130 @@ fn parse_address_key(key: &str) -> Result<u32> {
         .map_err(|_| anyhow!("Invalid decimal address key: {}", key))
 }
 
-fn detect_encoding(data: &[u8]) -> (CfgBinEncoding, u16) {
-    // Footer encoding is a u16 at file_end - 0x0A.
-    // Some files use values like 0x0100/0x0101 for UTF-8 variants; treat any non-zero as UTF-8.
-    let footer_encoding = if data.len() >= 10 {
-        read_u16(data, data.len() - 10)
-    } else {
-        1 // default UTF-8
+fn apply_key_order(unique_keys: Vec<String>, order: &[String]) -> Vec<String> {
+    let mut ordered: Vec<String> = Vec::with_capacity(unique_keys.len());
+    for key in order {
+        if unique_keys.contains(key) && !ordered.contains(key) {
+            ordered.push(key.clone());
+        }
+    }
+    for key in unique_keys {
+        if !ordered.contains(&key) {
+            ordered.push(key);
+        }
+    }
+    ordered
+}
+
+// `encode_key_table` writes one row per name. A repeated name, or two names with the same CRC32,
+// would make entry rows resolve ambiguously, so `save` refuses either.
+fn check_key_list(key_list: &[String], encoding: &CfgBinEncoding) -> Result<()> {
+    let mut names = BTreeSet::new();
+    let mut crcs: BTreeMap<u32, &str> = BTreeMap::new();
+    for key in key_list {
+        if !names.insert(key.as_str()) {
+            bail!("Key table would list {} twice", key);
+        }
+        let crc = crc32::compute(&encode_string_bytes(key, encoding));
+        if let Some(other) = crcs.insert(crc, key) {
+            bail!(
+                "Keys {} and {} share CRC32 0x{:08X}; the key table would be ambiguous",
+                other,
+                key,
+                crc
+            );
+        }
+    }
+    Ok(())
+}
+
+// A row stores its variable count in one byte.
+const MAX_VARIABLES: usize = u8::MAX as usize;
+
+const FOOTER_MAGIC: [u8; 4] = [0x01, 0x74, 0x32, 0x62];
+// Footer fields around the encoding that every file seen so far has (see `CfgBin::footer_unk1`).
+pub const FOOTER_UNK1: u16 = 0x01FE;
+pub const FOOTER_UNK2: u16 = 1;
+/// Footer encoding value written for UTF-16LE files. Other files use it for a UTF-8 variant
+/// too, so on open it only reads as UTF-16LE if the string table looks like it (see
+/// `detect_encoding_with`).
+pub const FOOTER_ENCODING_UTF16LE: u16 = 2;
+
+// Position of the footer magic. The footer is the last thing in the file, so the magic is
+// searched from the end; other tools pad it differently.
+fn find_footer(data: &[u8], endian: Endian) -> Option<usize> {
+    let magic_bytes = endian.u32_bytes(u32::from_le_bytes(FOOTER_MAGIC));
+    data.windows(magic_bytes.len())
+        .rposition(|w| w == magic_bytes)
+}
+
+/// Whether `data` contains the footer magic; without it, `detect_encoding_with` reads the
+/// encoding from a fixed distance to the end of the file.
+pub fn has_footer(data: &[u8], endian: Endian) -> bool {
+    find_footer(data, endian).is_some()
+}
+
+pub fn detect_encoding(data: &[u8]) -> (CfgBinEncoding, u16) {
+    detect_encoding_with(data, Endian::Little)
+}
+
+/// `detect_encoding` for a file in the given byte order (the magic is a u32 field too).
+pub fn detect_encoding_with(data: &[u8], endian: Endian) -> (CfgBinEncoding, u16) {
+    // Footer encoding is the u16 6 bytes after the footer magic (`find_footer`).
+    // Without a magic, fall back to file_end - 0x0A (the 16-byte-aligned layout we write).
+    // Some files use values like 0x0100/0x0101 for UTF-8 variants; treat any value other than
+    // Shift-JIS (0) as UTF-8, except a 2 over a string table that reads as UTF-16LE.
+    let magic = find_footer(data, endian).filter(|&pos| pos + 8 <= data.len());
+    let footer_encoding = match magic {
+        Some(pos) => endian.read_u16(data, pos + 6),
+        None if data.len() >= 10 => endian.read_u16(data, data.len() - 10),
+        None => 1, // default UTF-8
     };
-    let encoding = if footer_encoding == 0 {
-        CfgBinEncoding::ShiftJis
-    } else {
-        CfgBinEncoding::Utf8
+    let encoding = match footer_encoding {
+        0 => CfgBinEncoding::ShiftJis,
+        FOOTER_ENCODING_UTF16LE if string_table_looks_utf16le(data, endian) => {
+            CfgBinEncoding::Utf16Le
+        }
+        _ => CfgBinEncoding::Utf8,
     };
 
     (encoding, footer_encoding)
 }
 
+// Whether the header's string table reads as mostly-ASCII UTF-16LE: an even length ending in a
+// 16-bit zero, with at least half of its code units an ASCII character followed by a zero byte.
+// UTF-8 text only has zero bytes at record ends, so it almost never passes.
+fn string_table_looks_utf16le(data: &[u8], endian: Endian) -> bool {
+    if data.len() < 16 {
+        return false;
+    }
+    let Ok(table) = checked_range(
+        data.len(),
+        endian.read_i32(data, 4),
+        endian.read_i32(data, 8),
+        "String table",
+    ) else {
+        return false;
+    };
+    let table = &data[table];
+    if !table.len().is_multiple_of(2) || !table.ends_with(&[0, 0]) {
+        return false;
+    }
+    let units = table.chunks_exact(2);
+    let ascii = units
+        .clone()
+        .filter(|unit| (0x01..0x80).contains(&unit[0]) && unit[1] == 0)
+        .count();
+    ascii * 2 >= units.len()
+}
+
+// (string_table_offset, string_table_length, entry_count, [(field address, text)])
+type StringRefs = (usize, usize, i32, Vec<(usize, String)>);
+
 fn collect_string_refs_with_addresses(
     data: &[u8],
     encoding: &CfgBinEncoding,
-) -> Result<(usize, usize, i32, Vec<(usize, String)>)> {
+) -> Result<StringRefs> {
     if data.len() < 16 {
         bail!("cfg.bin is too small");
     }
@@ -354,23 +1204,20 @@ fn collect_string_refs_with_addresses(
         }
 
         // Alignment: if (ceil(paramCount/4) + 1) % 4 != 0, align to 4
-        if (type_byte_count + 1) % 4 != 0 {
-            let rem = pos % 4;
-            if rem != 0 {
-                pos += 4 - rem;
-            }
+        if !(type_byte_count + 1).is_multiple_of(4) {
+            pos = round_up(pos, 4);
         }
 
         if pos > entries_end {
             bail!("Unexpected end while applying entry alignment");
         }
 
-        for j in 0..param_count {
+        for param_type in &param_types {
             if pos.checked_add(4).context("Entry value overflow")? > entries_end {
                 bail!("Unexpected end while reading entry values");
             }
 
-            if matches!(param_types[j], VarType::String) {
+            if matches!(param_type, VarType::String) {
                 let string_offset = read_i32(data, pos);
                 if string_offset >= 0 {
                     let value = read_null_terminated_string_at(
@@ -396,44 +1243,303 @@ fn collect_string_refs_with_addresses(
 }
 
 impl CfgBin {
+    pub fn new(encoding: CfgBinEncoding, footer_encoding: u16, entries: Vec<Entry>) -> Self {
+        CfgBin {
+            encoding,
+            footer_encoding,
+            entries,
+            key_order: Vec::new(),
+            unresolved_crcs: Vec::new(),
+            string_format: StringFormat::default(),
+            suspect_string_offsets: Vec::new(),
+            original_key_crcs: BTreeMap::new(),
+            original_key_strings: KeyStrings::default(),
+            padding: Padding::default(),
+            referenced_crcs: BTreeSet::new(),
+            unparsed_entry_bytes: 0,
+            endian: Endian::default(),
+            footer_unk1: FOOTER_UNK1,
+            footer_unk2: FOOTER_UNK2,
+            string_field_addresses: Vec::new(),
+            original_strings: None,
+        }
+    }
+
     pub fn open(data: &[u8]) -> Result<Self> {
-        let (encoding, footer_encoding) = detect_encoding(data);
+        Self::open_with_options(data, &OpenOptions::default())
+    }
+
+    pub fn open_with_options(data: &[u8], options: &OpenOptions) -> Result<Self> {
+        let endian = options.endian;
+        let (detected, footer_encoding) = detect_encoding_with(data, endian);
+        let encoding = match options.encoding {
+            Some(encoding) => encoding,
+            None if options.auto_encoding => {
+                Self::encoding_scores(data, options.string_format, endian)
+                    .ok()
+                    .and_then(|scores| scores.better_alternative(detected))
+                    .unwrap_or(detected)
+            }
+            None => detected,
+        };
 
         // Read header (16 bytes)
-        let entries_count = read_i32(data, 0) as usize;
-        let string_table_offset = read_i32(data, 4) as usize;
-        let string_table_length = read_i32(data, 8) as usize;
+        if data.len() < 16 {
+            bail!("cfg.bin is too small");
+        }
+        let entries_count = endian.read_i32(data, 0);
+        if entries_count < 0 {
+            bail!("Invalid entry count: {}", entries_count);
+        }
+        let entries_count = options.entries_count.unwrap_or(entries_count as usize);
+        let (string_table, key_table_range) = regions(data, endian)?;
 
         // Read string table blob
-        let string_table_data =
-            &data[string_table_offset..string_table_offset + string_table_length];
+        let string_table_data = &data[string_table.clone()];
 
         // Parse key table
-        let key_table_offset = round_up(string_table_offset + string_table_length, 16);
-        let key_table_size = read_i32(data, key_table_offset) as usize;
-        let key_table_data = &data[key_table_offset..key_table_offset + key_table_size];
-        let key_table = Self::parse_key_table(key_table_data, &encoding);
-
-        // Parse entries
-        let entries_data = &data[0x10..string_table_offset];
-        let entries = Self::parse_entries(
+        let key_table_data = &data[key_table_range.clone()];
+        let (key_table, key_order, key_strings) =
+            Self::parse_key_table(key_table_data, &encoding, endian)?;
+
+        // Parse entries. A row takes at least 8 bytes (CRC, param count, alignment).
+        let entries_data = &data[0x10..string_table.start];
+        let max_rows = entries_data.len() / 8;
+        if entries_count > max_rows {
+            bail!(
+                "Entry count {} exceeds the {} rows that fit before the string table; the header may be wrong (see --entries-count)",
+                entries_count,
+                max_rows
+            );
+        }
+        let mut unresolved_crcs = Vec::new();
+        let mut referenced_crcs = BTreeSet::new();
+        let mut strings =
+            StringTableReader::new(string_table_data, encoding, options.string_format, endian);
+        let (entries, entries_end, string_field_addresses) = Self::parse_entries(
             entries_count,
             entries_data,
             &key_table,
-            string_table_data,
-            &encoding,
+            &mut strings,
+            options,
+            &mut unresolved_crcs,
+            &mut referenced_crcs,
         )?;
 
-        Ok(CfgBin {
-            encoding,
-            footer_encoding,
-            entries,
+        let key_rows_end = 0x10 + endian.read_i32(key_table_data, 4) as usize * 8;
+        let key_strings_start = endian.read_i32(key_table_data, 8) as usize;
+        let key_strings_end = key_strings_start + endian.read_i32(key_table_data, 12) as usize;
+        let default_padding = Padding::default();
+        let padding = Padding {
+            entries: pad_byte_at(entries_data, entries_end, entries_data.len())
+                .unwrap_or(default_padding.entries),
+            strings: pad_byte_at(data, string_table.end, key_table_range.start)
+                .unwrap_or(default_padding.strings),
+            key_table: pad_byte_at(key_table_data, key_rows_end, key_strings_start)
+                .or_else(|| pad_byte_at(key_table_data, key_strings_end, key_table_data.len()))
+                .unwrap_or(default_padding.key_table),
+            footer: pad_byte_at(data, key_table_range.end + 10, data.len())
+                .unwrap_or(default_padding.footer),
+        };
+
+        let mut cfg = CfgBin::new(encoding, footer_encoding, entries);
+        cfg.key_order = key_order;
+        cfg.unresolved_crcs = unresolved_crcs;
+        cfg.string_format = options.string_format;
+        cfg.suspect_string_offsets = strings.suspect_offsets;
+        if !strings.original_bytes.is_empty() {
+            cfg.original_strings = Some(OriginalStrings {
+                encoding,
+                bytes: strings.original_bytes,
+            });
+        }
+        cfg.original_key_crcs = key_table
+            .iter()
+            .map(|(&crc, name)| (name.clone(), crc))
+            .collect();
+        cfg.original_key_strings = key_strings;
+        cfg.padding = padding;
+        cfg.referenced_crcs = referenced_crcs;
+        cfg.string_field_addresses = string_field_addresses;
+        cfg.endian = endian;
+        if let Some(pos) = find_footer(data, endian).filter(|&pos| pos + 10 <= data.len()) {
+            cfg.footer_unk1 = endian.read_u16(data, pos + 4);
+            cfg.footer_unk2 = endian.read_u16(data, pos + 8);
+        }
+        let trailing = entries_data.len() - entries_end.min(entries_data.len());
+        if trailing >= 16 {
+            cfg.unparsed_entry_bytes = trailing;
+        }
+        Ok(cfg)
+    }
+
+    /// Scores both decodings of every non-ASCII string-table record with `looks_misdecoded`.
+    pub fn encoding_scores(
+        data: &[u8],
+        format: StringFormat,
+        endian: Endian,
+    ) -> Result<EncodingScores> {
+        let mut scores = EncodingScores {
+            strings: 0,
+            shift_jis_anomalies: 0,
+            utf8_anomalies: 0,
+        };
+        // Both candidates end records with a single zero byte.
+        for raw in Self::raw_strings(data, format, endian, CfgBinEncoding::Utf8)? {
+            if raw.bytes.is_ascii() {
+                continue;
+            }
+            scores.strings += 1;
+            if looks_misdecoded(&raw.shift_jis, CfgBinEncoding::ShiftJis) {
+                scores.shift_jis_anomalies += 1;
+            }
+            if looks_misdecoded(&raw.utf8, CfgBinEncoding::Utf8) {
+                scores.utf8_anomalies += 1;
+            }
+        }
+        Ok(scores)
+    }
+
+    /// Byte sizes of the entry rows, string table and key table of `data`, as its header lays
+    /// them out. Comparing them before and after a rebuild shows which region grew.
+    pub fn region_sizes(data: &[u8], endian: Endian) -> Result<RegionSizes> {
+        let (string_table, key_table) = regions(data, endian)?;
+        Ok(RegionSizes {
+            entries: string_table.start - 0x10,
+            string_table: string_table.len(),
+            key_table: key_table.len(),
         })
     }
 
+    /// Every record of the string table in storage order, decoded as both Shift-JIS and UTF-8
+    /// so an encoding mix-up can be spotted by eye. Reads the table directly, independent of
+    /// the entry rows; null-terminated records end at `encoding`'s terminator.
+    pub fn raw_strings(
+        data: &[u8],
+        format: StringFormat,
+        endian: Endian,
+        encoding: CfgBinEncoding,
+    ) -> Result<Vec<RawString>> {
+        if data.len() < 16 {
+            bail!("cfg.bin is too small");
+        }
+        let table = checked_range(
+            data.len(),
+            endian.read_i32(data, 4),
+            endian.read_i32(data, 8),
+            "String table",
+        )?;
+        let table = &data[table];
+
+        let mut out = Vec::new();
+        let mut pos = 0;
+        while pos < table.len() {
+            let (bytes, next) = match format {
+                StringFormat::NullTerminated => {
+                    let bytes = null_terminated_bytes_at(table, pos, &encoding).unwrap_or_default();
+                    (bytes, pos + bytes.len() + encoding.terminator().len())
+                }
+                StringFormat::LengthPrefixed => {
+                    if pos + 4 > table.len() {
+                        bail!("Truncated length prefix at string table offset 0x{:X}", pos);
+                    }
+                    let start = pos + 4;
+                    let end = start
+                        .saturating_add(endian.read_u32(table, pos) as usize)
+                        .min(table.len());
+                    (&table[start..end], end)
+                }
+            };
+            out.push(RawString {
+                offset: pos,
+                bytes: bytes.to_vec(),
+                shift_jis: decode_string(bytes, &CfgBinEncoding::ShiftJis),
+                utf8: decode_string(bytes, &CfgBinEncoding::Utf8),
+            });
+            pos = next;
+        }
+        Ok(out)
+    }
+
+    /// Extracts `data` in both modes and lists where they disagree. Strings are paired by the
+    /// file address of their offset field, and the nnk side decodes with the encoding the
+    /// standard open settled on; divergences point at a parsing bug in one of the two paths.
+    pub fn compare_modes(data: &[u8], options: &OpenOptions) -> Result<Vec<ModeDivergence>> {
+        if options.endian != Endian::Little {
+            bail!("nnk mode only supports little-endian files");
+        }
+        let cfg = Self::open_with_options(data, options)?;
+        let mut slots = Vec::new();
+        let mut next_index = 0usize;
+        for entry in &cfg.entries {
+            Self::collect_non_null_slots(entry, &mut next_index, &mut slots);
+        }
+        let mut standard = BTreeMap::new();
+        for slot in slots {
+            let address = *cfg
+                .string_field_addresses
+                .get(slot.0)
+                .with_context(|| format!("Text {} has no string field address", slot.0))?;
+            standard.insert(address, slot);
+        }
+        let mut nnk = Self::extract_texts_by_address_with(data, &cfg.encoding)?;
+
+        let mut addresses: BTreeSet<u32> = standard.keys().copied().collect();
+        addresses.extend(nnk.keys().copied());
+        let mut divergences = Vec::new();
+        for address in addresses {
+            let std_slot = standard.remove(&address);
+            let nnk_value = nnk.remove(&address);
+            if std_slot.as_ref().map(|s| &s.3) == nnk_value.as_ref() {
+                continue;
+            }
+            let (index, entry, variable_index, standard) = match std_slot {
+                Some((index, entry, var, value)) => {
+                    (Some(index), Some(entry), Some(var), Some(value))
+                }
+                None => (None, None, None, None),
+            };
+            divergences.push(ModeDivergence {
+                address,
+                index,
+                entry,
+                variable_index,
+                standard,
+                nnk: nnk_value,
+            });
+        }
+        Ok(divergences)
+    }
+
+    // (global text index, entry name, variable index, value) of each string slot with a value.
+    fn collect_non_null_slots(
+        entry: &Entry,
+        next_index: &mut usize,
+        out: &mut Vec<(usize, String, usize, String)>,
+    ) {
+        for (var_idx, var) in entry.variables.iter().enumerate() {
+            if let VarValue::String(opt) = &var.value {
+                if let Some(value) = opt {
+                    out.push((*next_index, entry.get_name(), var_idx, value.clone()));
+                }
+                *next_index += 1;
+            }
+        }
+        for child in &entry.children {
+            Self::collect_non_null_slots(child, next_index, out);
+        }
+    }
+
     pub fn extract_texts_by_address(data: &[u8]) -> Result<BTreeMap<u32, String>> {
         let (encoding, _) = detect_encoding(data);
-        let (_, _, _, refs) = collect_string_refs_with_addresses(data, &encoding)?;
+        Self::extract_texts_by_address_with(data, &encoding)
+    }
+
+    fn extract_texts_by_address_with(
+        data: &[u8],
+        encoding: &CfgBinEncoding,
+    ) -> Result<BTreeMap<u32, String>> {
+        let (_, _, _, refs) = collect_string_refs_with_addresses(data, encoding)?;
 
         let mut out = BTreeMap::new();
         for (addr, value) in refs {
@@ -472,6 +1578,57 @@ impl CfgBin {
         Ok(out)
     }
 
+    /// Every referenced string with its file offset and the fields referencing it, in string
+    /// table order. The addresses come from the nnk walk and are checked against a standard
+    /// parse, so a file the two modes disagree on is refused rather than mapped wrongly.
+    pub fn string_offsets(data: &[u8], options: &OpenOptions) -> Result<Vec<StringOffsets>> {
+        if options.string_format != StringFormat::NullTerminated {
+            bail!("String offsets are only supported for null-terminated string tables");
+        }
+        if options.endian != Endian::Little {
+            bail!("String offsets are only supported for little-endian files");
+        }
+        let cfg = Self::open_with_options(data, options)?;
+        let (string_table_offset, _, _, refs) =
+            collect_string_refs_with_addresses(data, &cfg.encoding)?;
+
+        let mut standard = Vec::new();
+        let mut next_index = 0usize;
+        for entry in &cfg.entries {
+            Self::collect_non_null_slots(entry, &mut next_index, &mut standard);
+        }
+        if standard.len() != refs.len() {
+            bail!(
+                "Standard parse found {} string(s) but the entry rows reference {}; run compare-modes",
+                standard.len(),
+                refs.len()
+            );
+        }
+
+        let mut by_offset: BTreeMap<u32, StringOffsets> = BTreeMap::new();
+        for ((index, entry, _, parsed), (address, value)) in standard.iter().zip(&refs) {
+            if parsed != value {
+                bail!(
+                    "String at 0x{:08X} does not match entry {} (index {}) of the standard parse; run compare-modes",
+                    address,
+                    entry,
+                    index
+                );
+            }
+            let string_offset = (string_table_offset + read_i32(data, *address) as usize) as u32;
+            by_offset
+                .entry(string_offset)
+                .or_insert_with(|| StringOffsets {
+                    value: value.clone(),
+                    string_offset,
+                    reference_offsets: Vec::new(),
+                })
+                .reference_offsets
+                .push(*address as u32);
+        }
+        Ok(by_offset.into_values().collect())
+    }
+
     pub fn patch_texts_by_address_in_place(
         data: &[u8],
         texts_by_address: &BTreeMap<u32, String>,
@@ -489,7 +1646,7 @@ impl CfgBin {
         }
 
         let mut new_string_table = Vec::new();
-        let mut new_offsets_by_addr: HashMap<usize, i32> = HashMap::new();
+        let mut new_offsets_by_addr: BTreeMap<usize, i32> = BTreeMap::new();
         let mut next_offset = 0i32;
 
         for (addr, _original) in &refs {
@@ -501,8 +1658,8 @@ impl CfgBin {
 
             let encoded = encode_string_bytes(new_text, &encoding);
             new_string_table.extend_from_slice(&encoded);
-            new_string_table.push(0x00);
-            next_offset += encoded.len() as i32 + 1;
+            new_string_table.extend_from_slice(encoding.terminator());
+            next_offset += (encoded.len() + encoding.terminator().len()) as i32;
         }
 
         let old_key_table_offset = round_up(string_table_offset + old_string_table_length, 16);
@@ -514,7 +1671,13 @@ impl CfgBin {
         out.extend_from_slice(&[0u8; 16]); // header placeholder
         out.extend_from_slice(&data[0x10..string_table_offset]); // entries unchanged
         out.extend_from_slice(&new_string_table);
-        write_alignment(&mut out, 16, 0xFF);
+        let string_pad = pad_byte_at(
+            data,
+            string_table_offset + old_string_table_length,
+            old_key_table_offset,
+        )
+        .unwrap_or(0xFF);
+        write_alignment(&mut out, 16, string_pad);
         out.extend_from_slice(&data[old_key_table_offset..]); // key table + footer unchanged
 
         for (addr, new_offset) in new_offsets_by_addr {
@@ -532,60 +1695,110 @@ impl CfgBin {
         Ok(out)
     }
 
-    fn parse_key_table(data: &[u8], encoding: &CfgBinEncoding) -> HashMap<u32, String> {
-        let mut table = HashMap::new();
+    // Ordered maps keep anything that iterates the key table stable from run to run.
+    fn parse_key_table(
+        data: &[u8],
+        encoding: &CfgBinEncoding,
+        endian: Endian,
+    ) -> Result<(BTreeMap<u32, String>, Vec<String>, KeyStrings)> {
+        let mut table = BTreeMap::new();
+        let mut order = Vec::new();
+        let mut offsets = BTreeMap::new();
 
         // KeyHeader: key_length(4) + key_count(4) + key_string_offset(4) + key_string_length(4)
-        let key_count = read_i32(data, 4) as usize;
-        let key_string_offset = read_i32(data, 8) as usize;
-        let key_string_length = read_i32(data, 12) as usize;
-
-        let key_string_data = &data[key_string_offset..key_string_offset + key_string_length];
+        if data.len() < 16 {
+            bail!("Key table is too small");
+        }
+        let key_count = endian.read_i32(data, 4);
+        checked_range(
+            data.len(),
+            0x10,
+            key_count.saturating_mul(8),
+            "Key table rows",
+        )?;
+        let key_string_data = &data[checked_range(
+            data.len(),
+            endian.read_i32(data, 8),
+            endian.read_i32(data, 12),
+            "Key strings",
+        )?];
 
         let mut pos = 0x10; // after header
         for _ in 0..key_count {
-            let crc = read_u32(data, pos);
+            let crc = endian.read_u32(data, pos);
             pos += 4;
-            let string_start = read_i32(data, pos) as usize;
+            let string_start = endian.read_i32(data, pos);
             pos += 4;
-
-            // Find null terminator in key_string_data
-            let mut end = string_start;
-            while end < key_string_data.len() && key_string_data[end] != 0 {
-                end += 1;
+            if string_start < 0 || string_start as usize > key_string_data.len() {
+                bail!(
+                    "Key 0x{:08X} has an invalid string offset {}",
+                    crc,
+                    string_start
+                );
             }
-            let key = decode_string(&key_string_data[string_start..end], encoding);
+            let string_start = string_start as usize;
+
+            let key = read_null_terminated_string_at(key_string_data, string_start, encoding)
+                .unwrap_or_default();
+            order.push(key.clone());
+            offsets.insert(key.clone(), string_start as i32);
             table.insert(crc, key);
         }
 
-        table
+        let key_strings = KeyStrings {
+            data: key_string_data.to_vec(),
+            offsets,
+            encoding: Some(*encoding),
+        };
+        Ok((table, order, key_strings))
     }
 
     fn parse_entries(
         entries_count: usize,
         data: &[u8],
-        key_table: &HashMap<u32, String>,
-        string_table_data: &[u8],
-        encoding: &CfgBinEncoding,
-    ) -> Result<Vec<Entry>> {
+        key_table: &BTreeMap<u32, String>,
+        strings: &mut StringTableReader,
+        options: &OpenOptions,
+        unresolved_crcs: &mut Vec<u32>,
+        referenced_crcs: &mut BTreeSet<u32>,
+    ) -> Result<(Vec<Entry>, usize, Vec<u32>)> {
+        let endian = options.endian;
         let mut temp = Vec::new();
+        let mut string_field_addresses = Vec::new();
         let mut pos = 0usize;
-        let mut string_cache: HashMap<i32, Option<String>> = HashMap::new();
 
-        for _ in 0..entries_count {
-            let crc = read_u32(data, pos);
+        for i in 0..entries_count {
+            if pos + 5 > data.len() {
+                bail!("Entry {} header runs past the string table offset", i);
+            }
+            let crc = endian.read_u32(data, pos);
             pos += 4;
+            referenced_crcs.insert(crc);
 
-            let name = key_table
-                .get(&crc)
-                .context(format!("Unknown CRC32: 0x{:08x}", crc))?
-                .clone();
+            let name = match key_table.get(&crc) {
+                Some(name) => name.clone(),
+                None if options.ignore_unknown_crc => {
+                    if !unresolved_crcs.contains(&crc) {
+                        unresolved_crcs.push(crc);
+                    }
+                    placeholder_name(crc)
+                }
+                None => bail!("Unknown CRC32: 0x{:08x}", crc),
+            };
 
             let param_count = data[pos] as usize;
             pos += 1;
 
             let mut param_types = Vec::with_capacity(param_count);
             let type_byte_count = ((param_count as f64) / 4.0).ceil() as usize;
+            let values_start = if (type_byte_count + 1).is_multiple_of(4) {
+                pos + type_byte_count
+            } else {
+                round_up(pos + type_byte_count, 4)
+            };
+            if values_start + param_count * 4 > data.len() {
+                bail!("Entry {} ({}) runs past the string table offset", i, name);
+            }
 
             for _ in 0..type_byte_count {
                 let param_type_byte = data[pos];
@@ -604,36 +1817,26 @@ impl CfgBin {
             }
 
             // Alignment: if (ceil(paramCount/4) + 1) % 4 != 0, align to 4
-            if (type_byte_count + 1) % 4 != 0 {
-                pos = pos + (4 - (pos % 4));
+            if !(type_byte_count + 1).is_multiple_of(4) {
+                pos = round_up(pos, 4);
             }
 
             let mut variables = Vec::with_capacity(param_count);
-            for j in 0..param_count {
-                match param_types[j] {
+            for param_type in &param_types {
+                match param_type {
                     VarType::String => {
-                        let offset = read_i32(data, pos);
+                        // `data` starts after the 16-byte header.
+                        string_field_addresses.push((0x10 + pos) as u32);
+                        let offset = endian.read_i32(data, pos);
                         pos += 4;
-                        let text = if offset < 0 {
-                            None
-                        } else if let Some(v) = string_cache.get(&offset) {
-                            v.clone()
-                        } else {
-                            let v = read_null_terminated_string_at(
-                                string_table_data,
-                                offset as usize,
-                                encoding,
-                            );
-                            string_cache.insert(offset, v.clone());
-                            v
-                        };
+                        let text = strings.read(offset);
                         variables.push(Variable {
                             var_type: VarType::String,
                             value: VarValue::String(text),
                         });
                     }
                     VarType::Int => {
-                        let v = read_i32(data, pos);
+                        let v = endian.read_i32(data, pos);
                         pos += 4;
                         variables.push(Variable {
                             var_type: VarType::Int,
@@ -641,7 +1844,7 @@ impl CfgBin {
                         });
                     }
                     VarType::Float => {
-                        let v = read_f32(data, pos);
+                        let v = endian.read_f32(data, pos);
                         pos += 4;
                         variables.push(Variable {
                             var_type: VarType::Float,
@@ -649,7 +1852,7 @@ impl CfgBin {
                         });
                     }
                     VarType::Unknown => {
-                        let v = read_i32(data, pos);
+                        let v = endian.read_i32(data, pos);
                         pos += 4;
                         variables.push(Variable {
                             var_type: VarType::Unknown,
@@ -660,6 +1863,7 @@ impl CfgBin {
             }
 
             temp.push(Entry {
+                source_name: Some(name.clone()),
                 name,
                 variables,
                 children: Vec::new(),
@@ -672,18 +1876,15 @@ impl CfgBin {
         for entry in &mut temp {
             let count = occurrences.entry(entry.name.clone()).or_insert(0);
             entry.name = format!("{}_{}", entry.name, count);
-            *occurrences
-                .get_mut(
-                    &entry.name.split('_').collect::<Vec<_>>()[..entry.name.split('_').count() - 1]
-                        .join("_"),
-                )
-                .unwrap() += 1;
+            *count += 1;
         }
 
-        Ok(Self::process_entries(temp))
+        Ok((Self::process_entries(temp)?, pos, string_field_addresses))
     }
 
-    fn process_entries(entries: Vec<Entry>) -> Vec<Entry> {
+    // Fails instead of panicking when the rows nest in a way no tree can hold, e.g. a corrupt
+    // CRC that turns a row into an entry of another block.
+    fn process_entries(entries: Vec<Entry>) -> Result<Vec<Entry>> {
         let mut stack: Vec<Entry> = Vec::new();
         let mut output: Vec<Entry> = Vec::new();
         let mut depth: Vec<(String, usize)> = Vec::new(); // ordered map
@@ -694,6 +1895,25 @@ impl CfgBin {
         fn depth_remove(depth: &mut Vec<(String, usize)>, key: &str) {
             depth.retain(|(k, _)| k != key);
         }
+        fn open_block<'a>(stack: &'a mut [Entry], name: &str) -> Result<&'a mut Entry> {
+            stack.last_mut().with_context(|| {
+                format!(
+                    "Entry {} is nested in a block that is not open; the entry rows look corrupt",
+                    name
+                )
+            })
+        }
+        fn last_child<'a>(stack: &'a mut [Entry], name: &str) -> Result<&'a mut Entry> {
+            open_block(stack, name)?
+                .children
+                .last_mut()
+                .with_context(|| {
+                    format!(
+                        "Entry {} nests under a block that has no entries yet; the entry rows look corrupt",
+                        name
+                    )
+                })
+        }
         fn depth_max_key(depth: &[(String, usize)]) -> String {
             depth
                 .iter()
@@ -711,17 +1931,27 @@ impl CfgBin {
             let node_type = name_parts[name_parts.len() - 2].to_lowercase();
             let _node_name = name_parts[..name_parts.len() - 1].join("_").to_lowercase();
 
-            let is_begin = (node_type.ends_with("beg")
-                || node_type.ends_with("begin")
-                || node_type.ends_with("start")
-                || node_type.ends_with("ptree"))
-                && !name.contains("_PTREE");
-
-            let is_end = node_type.ends_with("end") || name.contains("_PTREE");
+            let is_begin = is_begin_token(&node_type) && !is_ptree_end(&name);
+
+            // END rows are written without parameters. A type token that merely ends in "end"
+            // (LEGEND, SEND) only counts when it is the END name of a block that is open, as
+            // for suffix forms like MENUBEG/MENUEND.
+            let base_name = name_parts[..name_parts.len() - 1].join("_");
+            let closes_open_block = || {
+                depth.iter().any(|(open, _)| {
+                    open.rsplit_once('_')
+                        .is_some_and(|(open_base, _)| end_name_for(open_base) == base_name)
+                })
+            };
+            let is_end = variables.is_empty()
+                && (node_type == "end"
+                    || is_ptree_end(&name)
+                    || (node_type.ends_with("end") && closes_open_block()));
 
             if is_begin {
                 let new_node = Entry {
                     name: name.clone(),
+                    source_name: entries[i].source_name.clone(),
                     variables,
                     children: Vec::new(),
                     end_terminator: false,
@@ -736,11 +1966,13 @@ impl CfgBin {
                     if name.starts_with(&base_name)
                         && (node_type.ends_with("beg") || node_type.ends_with("begin"))
                     {
-                        let stack_top = stack.last_mut().unwrap();
-                        let last_child = stack_top.children.last_mut().unwrap();
-                        last_child.children.push(new_node.clone());
+                        last_child(&mut stack, &name)?
+                            .children
+                            .push(new_node.clone());
                     } else {
-                        stack.last_mut().unwrap().children.push(new_node.clone());
+                        open_block(&mut stack, &name)?
+                            .children
+                            .push(new_node.clone());
                     }
                 } else {
                     output.push(new_node.clone());
@@ -753,16 +1985,16 @@ impl CfgBin {
                     top.end_terminator = true;
                 }
 
-                let key = if depth_get(&depth, &name.replace("_END_", "_BEG_")).is_some() {
-                    name.replace("_END_", "_BEG_")
-                } else if depth_get(&depth, &name.replace("_END_", "_BEGIN_")).is_some() {
-                    name.replace("_END_", "_BEGIN_")
-                } else if depth_get(&depth, &name.replace("_END_", "_START_")).is_some() {
-                    name.replace("_END_", "_START_")
-                } else if depth_get(&depth, &name.replace("_PTREE", "PTREE")).is_some() {
-                    name.replace("_PTREE", "PTREE")
+                // Reverse of `end_name_for`: an END row closes the open block with the same name
+                // spelled with BEG, BEGIN or START.
+                let key = if is_ptree_end(&name) {
+                    innermost_open_ptree(&depth).unwrap_or_default()
                 } else {
-                    String::new()
+                    ["_BEG_", "_BEGIN_", "_START_"]
+                        .iter()
+                        .map(|token| name.replace("_END_", token))
+                        .find(|begin| depth_get(&depth, begin).is_some())
+                        .unwrap_or_default()
                 };
 
                 if depth.len() > 1 {
@@ -805,6 +2037,7 @@ impl CfgBin {
             } else {
                 let new_item = Entry {
                     name: name.clone(),
+                    source_name: entries[i].source_name.clone(),
                     variables,
                     children: Vec::new(),
                     end_terminator: false,
@@ -824,9 +2057,9 @@ impl CfgBin {
                         let is_begin_type = entry_name_max.contains("BEGIN")
                             || entry_name_max.contains("BEG")
                             || entry_name_max.contains("START")
-                            || entry_name_max.contains("PTREE");
+                            || entry_name_max.contains(PTREE_BEGIN);
 
-                        if !is_begin_type && !name.contains("_PTREE") {
+                        if !is_begin_type && !is_ptree_end(&name) {
                             if let Some(finished) = stack.pop() {
                                 if let Some(parent) = stack.last_mut() {
                                     if let Some(child) =
@@ -838,16 +2071,16 @@ impl CfgBin {
                                 }
                                 depth_remove(&mut depth, &entry_name_max);
                             }
-                            stack.last_mut().unwrap().children.push(new_item);
+                            open_block(&mut stack, &name)?.children.push(new_item);
                         } else {
-                            let stack_top = stack.last_mut().unwrap();
-                            let last_child = stack_top.children.last_mut().unwrap();
-                            last_child.children.push(new_item.clone());
+                            last_child(&mut stack, &name)?
+                                .children
+                                .push(new_item.clone());
                             stack.push(new_item);
                             depth.push((name.clone(), stack.len()));
                         }
                     } else {
-                        stack.last_mut().unwrap().children.push(new_item);
+                        open_block(&mut stack, &name)?.children.push(new_item);
                     }
                 }
             }
@@ -855,13 +2088,86 @@ impl CfgBin {
             i += 1;
         }
 
-        output
+        Ok(output)
+    }
+
+    pub fn save(&self) -> Result<Vec<u8>> {
+        self.save_with_options(&SaveOptions::default())
+    }
+
+    /// Fails if a block's END terminator name can't be derived from its begin name, which
+    /// would otherwise write a file whose key table lacks the END key.
+    pub fn save_with_options(&self, options: &SaveOptions) -> Result<Vec<u8>> {
+        self.save_with(&self.string_tables(options.no_dedup_strings), options)
+    }
+
+    /// Builds the string table `save` writes, for reuse across `save_with` calls.
+    pub fn string_tables(&self, no_dedup_strings: bool) -> StringTables {
+        let records = if no_dedup_strings {
+            self.get_string_references()
+        } else {
+            self.get_distinct_strings()
+        };
+        let offsets = self.record_offsets(&records);
+        let by_value = if no_dedup_strings {
+            HashMap::new()
+        } else {
+            records
+                .iter()
+                .cloned()
+                .zip(offsets.iter().copied())
+                .collect()
+        };
+        StringTables {
+            data: self.encode_strings(&records),
+            records,
+            offsets,
+            by_value,
+            per_reference: no_dedup_strings,
+        }
+    }
+
+    /// Like `save_with_options`, but with a string table prebuilt by `string_tables` (whose
+    /// layout replaces `options.no_dedup_strings`). Fails if a string value is not in the table.
+    pub fn save_with(&self, tables: &StringTables, options: &SaveOptions) -> Result<Vec<u8>> {
+        self.write_file(tables, None, options)
+    }
+
+    /// Builds the key table `save_with_options` writes, for reuse across
+    /// `save_with_key_table` calls.
+    pub fn key_table(&self, options: &SaveOptions) -> Result<KeyTable> {
+        let names = self.key_table_names(options);
+        check_key_list(&names, &self.encoding)?;
+        Ok(KeyTable {
+            data: self.encode_key_table(&names, options.preserve_key_strings),
+            names: names.into_iter().collect(),
+            encoding: self.encoding,
+            endian: self.endian,
+            padding: self.padding.key_table,
+            key_order: options.key_order.clone(),
+            preserve_key_strings: options.preserve_key_strings,
+        })
+    }
+
+    /// Like `save_with`, also reusing a key table prebuilt by `key_table`. Fails if the table
+    /// is stale (see `KeyTable`).
+    pub fn save_with_key_table(
+        &self,
+        tables: &StringTables,
+        keys: &KeyTable,
+        options: &SaveOptions,
+    ) -> Result<Vec<u8>> {
+        keys.check(self, options)?;
+        self.write_file(tables, Some(keys), options)
     }
 
-    pub fn save(&self) -> Vec<u8> {
-        let distinct_strings = self.get_distinct_strings();
-        let strings_table = self.build_strings_table(&distinct_strings);
-        let strings_data = self.encode_strings(&distinct_strings);
+    fn write_file(
+        &self,
+        tables: &StringTables,
+        keys: Option<&KeyTable>,
+        options: &SaveOptions,
+    ) -> Result<Vec<u8>> {
+        check_end_terminators(&self.entries)?;
 
         let mut buf = Vec::new();
 
@@ -869,42 +2175,80 @@ impl CfgBin {
         buf.extend_from_slice(&[0u8; 16]);
 
         // Encode entries
+        let mut reference = 0usize;
+        let mut stale: Option<String> = None;
+        let mut string_offset = |s: &str| {
+            let offset = tables.offset_for(reference, s);
+            reference += 1;
+            if offset.is_none() && stale.is_none() {
+                stale = Some(s.to_string());
+            }
+            offset.unwrap_or(-1)
+        };
+        // A prebuilt key table is checked against the rows as they are written, so the entry
+        // names aren't walked a second time.
+        let mut unknown_key = None;
+        let mut used_keys = HashSet::new();
+        let mut row_key = |name: &str, crc: u32| {
+            let Some(keys) = keys.filter(|_| placeholder_crc(name).is_none()) else {
+                return;
+            };
+            if !keys.names.contains(name) && unknown_key.is_none() {
+                unknown_key = Some(name.to_string());
+            }
+            used_keys.insert(crc);
+        };
         for entry in &self.entries {
-            buf.extend_from_slice(&entry.encode_entry(&strings_table, &self.encoding));
+            buf.extend_from_slice(&entry.encode_entry(
+                &mut string_offset,
+                &mut row_key,
+                &self.encoding,
+                self.endian,
+            )?);
+        }
+        if let Some(value) = stale {
+            bail!(
+                "String {:?} is not in the prebuilt string table; rebuild it after changing strings",
+                value
+            );
         }
+        if let Some(keys) = keys {
+            // Table names have distinct CRCs, so every name is in use iff the counts match.
+            if unknown_key.is_some() || used_keys.len() != keys.names.len() {
+                bail!("Entry names changed since the key table was built; rebuild it");
+            }
+        }
+        self.check_string_table(tables)?;
+        let string_records = &tables.records;
+        let strings_data = &tables.data;
 
-        // Align to 16 bytes with 0xFF
-        write_alignment(&mut buf, 16, 0xFF);
+        // Align to 16 bytes (0xFF unless the source used another fill)
+        write_alignment(&mut buf, 16, self.padding.entries);
         let string_table_offset = buf.len() as i32;
 
         let string_table_length = strings_data.len() as i32;
-        if !distinct_strings.is_empty() {
-            buf.extend_from_slice(&strings_data);
-            write_alignment(&mut buf, 16, 0xFF);
+        if !string_records.is_empty() {
+            buf.extend_from_slice(strings_data);
+            write_alignment(&mut buf, 16, self.padding.strings);
         }
 
         // Key table
-        let unique_keys: Vec<String> = self
-            .entries
-            .iter()
-            .flat_map(|e| e.get_unique_keys())
-            .collect::<Vec<_>>()
-            .into_iter()
-            .fold(Vec::new(), |mut acc, k| {
-                if !acc.contains(&k) {
-                    acc.push(k);
-                }
-                acc
-            });
-
-        let key_table_data = self.encode_key_table(&unique_keys);
-        buf.extend_from_slice(&key_table_data);
+        let built;
+        let keys = match keys {
+            Some(keys) => keys,
+            None => {
+                built = self.key_table(options)?;
+                &built
+            }
+        };
+        buf.extend_from_slice(&keys.data);
 
         // Footer
         // Footer layout matches CfgBinEditor2:
-        // magic(u32=0x62327401) + unk1(i16=0x01FE) + encoding(u16) + unk2(i16=1)
-        buf.extend_from_slice(&[0x01, 0x74, 0x32, 0x62]);
-        buf.extend_from_slice(&(0x01FEu16).to_le_bytes());
+        // magic(u32=0x62327401) + unk1(u16, usually 0x01FE) + encoding(u16) + unk2(u16, usually 1)
+        let endian = self.endian;
+        buf.extend_from_slice(&endian.u32_bytes(u32::from_le_bytes(FOOTER_MAGIC)));
+        buf.extend_from_slice(&endian.u16_bytes(self.footer_unk1));
         let footer_encoding = match self.encoding {
             CfgBinEncoding::ShiftJis => 0u16,
             CfgBinEncoding::Utf8 => {
@@ -914,23 +2258,311 @@ impl CfgBin {
                     self.footer_encoding
                 }
             }
+            CfgBinEncoding::Utf16Le => FOOTER_ENCODING_UTF16LE,
         };
-        buf.extend_from_slice(&footer_encoding.to_le_bytes());
-        buf.extend_from_slice(&(1u16).to_le_bytes());
-        write_alignment(&mut buf, 16, 0xFF);
+        buf.extend_from_slice(&endian.u16_bytes(footer_encoding));
+        buf.extend_from_slice(&endian.u16_bytes(self.footer_unk2));
+        write_alignment(&mut buf, 16, self.padding.footer);
 
         // Write header
-        let entries_count = self.count_entries();
-        buf[0..4].copy_from_slice(&(entries_count as i32).to_le_bytes());
-        buf[4..8].copy_from_slice(&string_table_offset.to_le_bytes());
-        buf[8..12].copy_from_slice(&string_table_length.to_le_bytes());
-        buf[12..16].copy_from_slice(&(distinct_strings.len() as i32).to_le_bytes());
+        let entries_count = self.serialized_entry_count() as i32;
+        buf[0..4].copy_from_slice(&endian.i32_bytes(entries_count));
+        buf[4..8].copy_from_slice(&endian.i32_bytes(string_table_offset));
+        buf[8..12].copy_from_slice(&endian.i32_bytes(string_table_length));
+        buf[12..16].copy_from_slice(&endian.i32_bytes(string_records.len() as i32));
 
-        buf
+        Ok(buf)
+    }
+
+    // The last record's offset plus its encoded length must be the blob's length, or the
+    // offsets written to the entry rows would point past the declared table (e.g. for a table
+    // built under another encoding or string format).
+    fn check_string_table(&self, tables: &StringTables) -> Result<()> {
+        let end = match (tables.records.last(), tables.offsets.last()) {
+            (Some(last), Some(&offset)) => offset as usize + self.encode_string_record(last).len(),
+            _ => 0,
+        };
+        if end != tables.data.len() {
+            bail!(
+                "String table is {} bytes but its records end at {}; rebuild it after changing the encoding or string format",
+                tables.data.len(),
+                end
+            );
+        }
+        Ok(())
+    }
+
+    /// Whether `open` followed by `save` reproduces `original` byte for byte. Some valid files are
+    /// not stable, e.g. when their string table order or key table order differs from what `save`
+    /// writes.
+    pub fn is_roundtrip_stable(original: &[u8]) -> Result<bool> {
+        Ok(Self::roundtrip_mismatch(original)?.is_none())
+    }
+
+    /// Like `is_roundtrip_stable`, but returns the first offset where the re-saved bytes differ
+    /// (the shorter length if one output is a prefix of the other).
+    pub fn roundtrip_mismatch(original: &[u8]) -> Result<Option<usize>> {
+        let saved = Self::open(original)?.save()?;
+        let first_diff = original
+            .iter()
+            .zip(&saved)
+            .position(|(a, b)| a != b)
+            .or_else(|| (original.len() != saved.len()).then(|| original.len().min(saved.len())));
+        Ok(first_diff)
+    }
+
+    /// Renames entries per `renames`, whose keys are current entry names or CRCs written as
+    /// `0x<hex>` (which also match `UNKNOWN_0x<crc>` placeholders). New CRCs are computed by
+    /// `save` when it rebuilds the key table. A key that matches a block is refused (and reported
+    /// as unmatched) if the new name leaves no END name to derive, or one that another entry uses
+    /// or whose CRC belongs to a different key.
+    pub fn rename_keys(&mut self, renames: &BTreeMap<String, String>) -> RenameReport {
+        let encoding = self.encoding;
+        let crc_keys: Vec<(&String, Option<u32>)> = renames
+            .keys()
+            .map(|k| {
+                let hex = k.strip_prefix("0x").or_else(|| k.strip_prefix("0X"));
+                (k, hex.and_then(|h| u32::from_str_radix(h, 16).ok()))
+            })
+            .collect();
+        // Entry names carry an occurrence suffix; match and rename the base only.
+        let target_key = |base: &str| {
+            let crc = key_crc(base, &encoding);
+            crc_keys
+                .iter()
+                .find(|(k, c)| *k == base || *c == Some(crc))
+                .map(|(k, _)| *k)
+        };
+
+        let mut entry_names = HashSet::new();
+        self.walk(|entry, _| {
+            entry_names.insert(entry.get_name());
+        });
+        let key_crcs: HashMap<u32, String> = self
+            .key_name_set()
+            .into_iter()
+            .map(|name| (key_crc(&name, &encoding), name))
+            .collect();
+        let mut refused = BTreeSet::new();
+        self.walk(|entry, _| {
+            let base = entry.get_name();
+            let Some(key) = target_key(&base).filter(|_| entry.is_block() && !is_ptree_end(&base))
+            else {
+                return;
+            };
+            let new_name = renames[key].as_str();
+            let token = new_name.rsplit('_').next().unwrap_or(new_name);
+            let end_is_free = |end: &String| {
+                let crc_owner = key_crcs.get(&key_crc(end, &encoding));
+                !entry_names.contains(end) && crc_owner.is_none_or(|name| name == end)
+            };
+            if !is_begin_token(token)
+                || !derived_end_name(new_name).is_some_and(|e| end_is_free(&e))
+            {
+                refused.insert(key);
+            }
+        });
+
+        let mut matched = BTreeSet::new();
+        let mut stack: Vec<&mut Entry> = self.entries.iter_mut().collect();
+        while let Some(entry) = stack.pop() {
+            let base = entry.get_name();
+            if let Some(key) = target_key(&base).filter(|key| !refused.contains(key)) {
+                if placeholder_crc(&base).is_some() {
+                    let crc = key_crc(&base, &encoding);
+                    self.unresolved_crcs.retain(|&c| c != crc);
+                }
+                entry.name = format!("{}{}", renames[key], &entry.name[base.len()..]);
+                if entry.source_name.is_some() {
+                    entry.source_name = Some(renames[key].clone());
+                }
+                matched.insert(key.clone());
+            }
+            stack.extend(entry.children.iter_mut());
+        }
+
+        let unmatched = renames
+            .keys()
+            .filter(|k| !matched.contains(*k))
+            .cloned()
+            .collect();
+        RenameReport {
+            renamed: matched.into_iter().collect(),
+            unmatched,
+        }
+    }
+
+    /// Key names `save_with_options` writes to the key table, in row order.
+    pub fn key_table_names(&self, options: &SaveOptions) -> Vec<String> {
+        let unique_keys = self.unique_keys();
+        match &options.key_order {
+            Some(order) => apply_key_order(unique_keys, order),
+            None => unique_keys,
+        }
+    }
+
+    // The names `unique_keys` lists, without their order, collected in one pass.
+    fn key_name_set(&self) -> HashSet<String> {
+        let mut names = HashSet::new();
+        self.walk(|entry, _| {
+            let name = entry.get_name();
+            if entry.end_terminator {
+                names.insert(end_name_for(&name));
+            }
+            names.insert(name);
+        });
+        names.retain(|name| placeholder_crc(name).is_none());
+        names
+    }
+
+    // Key names `save` writes to the key table, in traversal order. Placeholders have no name
+    // to write.
+    fn unique_keys(&self) -> Vec<String> {
+        self.entries
+            .iter()
+            .flat_map(|e| e.get_unique_keys())
+            .fold(Vec::new(), |mut acc, k| {
+                if !acc.contains(&k) && placeholder_crc(&k).is_none() {
+                    acc.push(k);
+                }
+                acc
+            })
     }
 
-    fn count_entries(&self) -> i32 {
-        self.entries.iter().map(|e| e.count()).sum()
+    /// Source key-table names whose CRC no entry row references, in key-table order. Such
+    /// orphan keys hint at entry types the file format allows but this file doesn't use.
+    pub fn unused_keys(&self) -> Vec<String> {
+        self.key_order
+            .iter()
+            .filter(|name| {
+                self.original_key_crcs
+                    .get(*name)
+                    .is_some_and(|crc| !self.referenced_crcs.contains(crc))
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Keys whose CRC in the saved key table would differ from the CRC the source key table
+    /// stored for the same name, e.g. after changing `encoding` (CRCs hash the encoded name).
+    pub fn rehashed_keys(&self) -> Vec<RehashedKey> {
+        self.unique_keys()
+            .into_iter()
+            .filter_map(|name| {
+                let original_crc = *self.original_key_crcs.get(&name)?;
+                let new_crc = key_crc(&name, &self.encoding);
+                (new_crc != original_crc).then_some(RehashedKey {
+                    name,
+                    original_crc,
+                    new_crc,
+                })
+            })
+            .collect()
+    }
+
+    /// Saves without any change and compares the result with `data`, the file this was opened
+    /// from: `None` if the save is byte-identical, otherwise where it first differs.
+    pub fn verify_roundtrip(&self, data: &[u8]) -> Result<Option<RoundTripDiff>> {
+        self.verify_roundtrip_with(data, &SaveOptions::default())
+    }
+
+    /// `verify_roundtrip` saving with `options`.
+    pub fn verify_roundtrip_with(
+        &self,
+        data: &[u8],
+        options: &SaveOptions,
+    ) -> Result<Option<RoundTripDiff>> {
+        Ok(RoundTripDiff::between(
+            data,
+            &self.save_with_options(options)?,
+        ))
+    }
+
+    /// Full names of the BEGIN/BEG/START/PTREE entries that no END row closed in the source, in
+    /// file order. `save` writes them without one too.
+    pub fn unclosed_blocks(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        self.walk(|entry, _| {
+            let base = entry.get_name();
+            let token = base.rsplit('_').next().unwrap_or_default();
+            if is_begin_token(token) && !is_ptree_end(&base) && !entry.end_terminator {
+                names.push(entry.name.clone());
+            }
+        });
+        names
+    }
+
+    /// Entries in the parsed tree, nested ones included (END rows are not entries).
+    pub fn node_count(&self) -> usize {
+        self.entries.iter().map(Entry::node_count).sum()
+    }
+
+    /// Distinct string values, the records a standard `save` writes to the string table.
+    pub fn distinct_string_count(&self) -> usize {
+        self.get_distinct_strings().len()
+    }
+
+    /// END terminator rows `save` writes (see `Entry::end_terminator_count`).
+    pub fn end_terminator_count(&self) -> usize {
+        self.entries.iter().map(Entry::end_terminator_count).sum()
+    }
+
+    /// Rows `save` writes, and so the header's `entries_count`:
+    /// `node_count() + end_terminator_count()`.
+    pub fn serialized_entry_count(&self) -> usize {
+        self.entries.iter().map(Entry::serialized_count).sum()
+    }
+
+    /// Per-top-level-entry child count, nesting depth and serialized row count
+    pub fn top_level_stats(&self) -> Vec<EntryStats> {
+        self.entries
+            .iter()
+            .map(|entry| EntryStats {
+                name: entry.name.clone(),
+                child_count: entry.children.len(),
+                max_depth: entry.max_depth(),
+                total_count: entry.serialized_count(),
+            })
+            .collect()
+    }
+
+    /// Variables counted by type over every entry and descendant; a jump in `unknown` between
+    /// game versions hints at a format change or misread type descriptors
+    pub fn variable_type_counts(&self) -> VarTypeCounts {
+        let mut counts = VarTypeCounts::default();
+        self.walk(|entry, _| {
+            for var in &entry.variables {
+                match var.var_type {
+                    VarType::String => counts.string += 1,
+                    VarType::Int => counts.int += 1,
+                    VarType::Float => counts.float += 1,
+                    VarType::Unknown => counts.unknown += 1,
+                }
+            }
+        });
+        counts
+    }
+
+    /// Calls `f` with every entry and its depth (0 for top-level entries) in pre-order: an
+    /// entry, then its children's subtrees in order. This is file order, the order in which
+    /// `extract_texts` numbers string slots.
+    pub fn walk(&self, mut f: impl FnMut(&Entry, usize)) {
+        let mut stack: Vec<(&Entry, usize)> = self.entries.iter().rev().map(|e| (e, 0)).collect();
+        while let Some((entry, depth)) = stack.pop() {
+            f(entry, depth);
+            stack.extend(entry.children.iter().rev().map(|c| (c, depth + 1)));
+        }
+    }
+
+    /// `walk` with mutable entries. Children are visited after `f` returns for their parent,
+    /// so children that `f` adds or removes are walked accordingly.
+    pub fn walk_mut(&mut self, mut f: impl FnMut(&mut Entry, usize)) {
+        let mut stack: Vec<(&mut Entry, usize)> =
+            self.entries.iter_mut().rev().map(|e| (e, 0)).collect();
+        while let Some((entry, depth)) = stack.pop() {
+            f(entry, depth);
+            stack.extend(entry.children.iter_mut().rev().map(|c| (c, depth + 1)));
+        }
     }
 
     fn get_distinct_strings(&self) -> Vec<String> {
@@ -945,131 +2577,1083 @@ impl CfgBin {
         strings
     }
 
-    fn build_strings_table(&self, distinct_strings: &[String]) -> HashMap<String, i32> {
-        let mut table = HashMap::new();
+    fn encode_string_record(&self, s: &str) -> Vec<u8> {
+        let original = self
+            .original_strings
+            .as_ref()
+            .filter(|o| o.encoding == self.encoding)
+            .and_then(|o| o.bytes.get(s));
+        let bytes = match original {
+            Some(bytes) => bytes.clone(),
+            None => encode_string_bytes(s, &self.encoding),
+        };
+        match self.string_format {
+            StringFormat::NullTerminated => {
+                let mut record = bytes;
+                record.extend_from_slice(self.encoding.terminator());
+                record
+            }
+            StringFormat::LengthPrefixed => {
+                let mut record = self.endian.u32_bytes(bytes.len() as u32).to_vec();
+                record.extend_from_slice(&bytes);
+                record
+            }
+        }
+    }
+
+    fn get_string_references(&self) -> Vec<String> {
+        let mut strings = Vec::new();
+        for entry in &self.entries {
+            entry.collect_string_references(&mut strings);
+        }
+        strings
+    }
+
+    // Offset of each record when `records` are written back to back.
+    fn record_offsets(&self, records: &[String]) -> Vec<i32> {
+        let mut offsets = Vec::with_capacity(records.len());
         let mut pos = 0i32;
-        for s in distinct_strings {
-            table.insert(s.clone(), pos);
-            pos += encode_string_bytes(s, &self.encoding).len() as i32 + 1;
+        for s in records {
+            offsets.push(pos);
+            pos += self.encode_string_record(s).len() as i32;
         }
-        table
+        offsets
     }
 
-    fn encode_strings(&self, distinct_strings: &[String]) -> Vec<u8> {
+    fn encode_strings(&self, records: &[String]) -> Vec<u8> {
         let mut buf = Vec::new();
-        for s in distinct_strings {
-            buf.extend_from_slice(&encode_string_bytes(s, &self.encoding));
-            buf.push(0x00);
+        for s in records {
+            buf.extend_from_slice(&self.encode_string_record(s));
         }
         buf
     }
 
-    fn encode_key_table(&self, key_list: &[String]) -> Vec<u8> {
+    fn encode_key_table(&self, key_list: &[String], preserve_strings: bool) -> Vec<u8> {
         let mut buf = vec![0u8; 16]; // header placeholder
 
-        let mut string_offset = 0i32;
+        let original = &self.original_key_strings;
+        let preserve_strings = preserve_strings && original.encoding == Some(self.encoding);
+        let mut key_strings_data = if preserve_strings {
+            original.data.clone()
+        } else {
+            Vec::new()
+        };
         let mut key_entries = Vec::new();
         for key in key_list {
             let crc = crc32::compute(&encode_string_bytes(key, &self.encoding));
-            key_entries.extend_from_slice(&crc.to_le_bytes());
-            key_entries.extend_from_slice(&string_offset.to_le_bytes());
-            string_offset += encode_string_bytes(key, &self.encoding).len() as i32 + 1;
+            let reused = original.offsets.get(key).filter(|_| preserve_strings);
+            let string_offset = match reused {
+                Some(&offset) => offset,
+                None => {
+                    let offset = key_strings_data.len() as i32;
+                    key_strings_data.extend_from_slice(&encode_string_bytes(key, &self.encoding));
+                    key_strings_data.extend_from_slice(self.encoding.terminator());
+                    offset
+                }
+            };
+            key_entries.extend_from_slice(&self.endian.u32_bytes(crc));
+            key_entries.extend_from_slice(&self.endian.i32_bytes(string_offset));
         }
 
         // Write entries starting at 0x10
         buf.extend_from_slice(&key_entries);
-        write_alignment(&mut buf, 16, 0xFF);
+        write_alignment(&mut buf, 16, self.padding.key_table);
 
         let key_string_offset = buf.len() as i32;
 
         // Write key strings
-        let mut key_strings_data = Vec::new();
-        for key in key_list {
-            key_strings_data.extend_from_slice(&encode_string_bytes(key, &self.encoding));
-            key_strings_data.push(0x00);
-        }
         let key_string_length = key_strings_data.len() as i32;
         buf.extend_from_slice(&key_strings_data);
-        write_alignment(&mut buf, 16, 0xFF);
+        write_alignment(&mut buf, 16, self.padding.key_table);
 
         let key_length = buf.len() as i32;
 
         // Write header
-        buf[0..4].copy_from_slice(&key_length.to_le_bytes());
-        buf[4..8].copy_from_slice(&(key_list.len() as i32).to_le_bytes());
-        buf[8..12].copy_from_slice(&key_string_offset.to_le_bytes());
-        buf[12..16].copy_from_slice(&key_string_length.to_le_bytes());
+        buf[0..4].copy_from_slice(&self.endian.i32_bytes(key_length));
+        buf[4..8].copy_from_slice(&self.endian.i32_bytes(key_list.len() as i32));
+        buf[8..12].copy_from_slice(&self.endian.i32_bytes(key_string_offset));
+        buf[12..16].copy_from_slice(&self.endian.i32_bytes(key_string_length));
 
         buf
     }
 
     /// Extract all text fields as a list of TextEntry for JSON export
     pub fn extract_texts(&self) -> Vec<TextEntry> {
+        self.collect_texts(false)
+    }
+
+    /// Like `extract_texts`, with `hash` set to the CRC32 of each value's UTF-8 bytes so
+    /// unchanged source texts can be recognized across game versions
+    pub fn extract_texts_with_hash(&self) -> Vec<TextEntry> {
+        self.collect_texts(true)
+    }
+
+    /// Like `extract_texts`, limited to entries without children. Indices stay global, so the
+    /// result can be edited and passed to `update_texts` as is.
+    pub fn extract_leaf_texts(&self) -> Vec<TextEntry> {
         let mut texts = Vec::new();
-        let mut global_index = 0usize;
-        for entry in &self.entries {
-            Self::collect_texts_recursive(entry, &mut texts, &mut global_index);
-        }
+        let Ok(()) = self.visit_texts::<Infallible>(VarType::String, false, true, &mut |te| {
+            texts.push(te);
+            Ok(())
+        });
         texts
     }
 
-    fn collect_texts_recursive(
-        entry: &Entry,
-        texts: &mut Vec<TextEntry>,
-        global_index: &mut usize,
-    ) {
+    fn collect_texts(&self, with_hash: bool) -> Vec<TextEntry> {
+        let mut texts = Vec::new();
+        let Ok(()) = self.visit_texts::<Infallible>(VarType::String, with_hash, false, &mut |te| {
+            texts.push(te);
+            Ok(())
+        });
+        texts
+    }
+
+    /// Text entries whose value equals `needle`. Matching is on the decoded string, so the
+    /// needle is independent of the file encoding.
+    pub fn find_text(&self, needle: &str) -> Vec<TextEntry> {
+        self.find_texts_by(|value| value == needle)
+    }
+
+    /// Like `find_text`, but matches values containing `needle` as a substring
+    pub fn find_text_containing(&self, needle: &str) -> Vec<TextEntry> {
+        self.find_texts_by(|value| value.contains(needle))
+    }
+
+    fn find_texts_by(&self, matches: impl Fn(&str) -> bool) -> Vec<TextEntry> {
+        let mut found = Vec::new();
+        let Ok(()) = self.visit_texts::<Infallible>(VarType::String, false, false, &mut |te| {
+            if matches(&te.value) {
+                found.push(te);
+            }
+            Ok(())
+        });
+        found
+    }
+
+    /// Streams the `extract_texts` JSON (pretty-printed, byte-identical to serializing the
+    /// vector) to `writer` without building the vector or the string; returns the entry count
+    /// `leaves_only` skips entries with children, as in `extract_leaf_texts`.
+    pub fn write_texts_json<W: io::Write>(
+        &self,
+        writer: W,
+        with_hash: bool,
+        leaves_only: bool,
+    ) -> Result<usize> {
+        self.write_values_json(writer, VarType::String, with_hash, leaves_only)
+    }
+
+    /// `extract_values` streamed like `write_texts_json`.
+    pub fn write_values_json<W: io::Write>(
+        &self,
+        writer: W,
+        var_type: VarType,
+        with_hash: bool,
+        leaves_only: bool,
+    ) -> Result<usize> {
+        let mut ser = serde_json::Serializer::pretty(writer);
+        let mut seq = ser.serialize_seq(None)?;
+        let mut count = 0usize;
+        self.visit_texts(var_type, with_hash, leaves_only, &mut |te| {
+            count += 1;
+            seq.serialize_element(&te)
+        })?;
+        seq.end()?;
+        Ok(count)
+    }
+
+    /// Like `extract_texts` (or `extract_leaf_texts` with `leaves_only`), for the variables
+    /// holding `var_type` values, as written by `VarValue::text_of_type`. Indices count only
+    /// the slots of that type, so a string slice matches `extract_texts`.
+    pub fn extract_values(
+        &self,
+        var_type: VarType,
+        with_hash: bool,
+        leaves_only: bool,
+    ) -> Vec<TextEntry> {
+        let mut values = Vec::new();
+        let Ok(()) = self.visit_texts::<Infallible>(var_type, with_hash, leaves_only, &mut |te| {
+            values.push(te);
+            Ok(())
+        });
+        values
+    }
+
+    // Calls `emit` for every `var_type` slot in extraction order, stopping at the first error.
+    // With `leaves_only`, slots of entries with children are counted but not emitted.
+    fn visit_texts<E>(
+        &self,
+        var_type: VarType,
+        with_hash: bool,
+        leaves_only: bool,
+        emit: &mut impl FnMut(TextEntry) -> std::result::Result<(), E>,
+    ) -> std::result::Result<(), E> {
+        let mut global_index = 0usize;
+        for entry in &self.entries {
+            Self::visit_texts_recursive(
+                entry,
+                var_type,
+                &mut global_index,
+                with_hash,
+                leaves_only,
+                emit,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn visit_texts_recursive<E>(
+        entry: &Entry,
+        var_type: VarType,
+        global_index: &mut usize,
+        with_hash: bool,
+        leaves_only: bool,
+        emit: &mut impl FnMut(TextEntry) -> std::result::Result<(), E>,
+    ) -> std::result::Result<(), E> {
         let entry_name = entry.get_name();
+        let skip = leaves_only && !entry.children.is_empty();
         for (var_idx, var) in entry.variables.iter().enumerate() {
-            if let VarValue::String(opt) = &var.value {
-                texts.push(TextEntry {
+            if let Some(value) = var.value.text_of_type(var_type) {
+                if skip {
+                    *global_index += 1;
+                    continue;
+                }
+                let hash = with_hash.then(|| format!("{:08x}", crc32::compute(value.as_bytes())));
+                emit(TextEntry {
                     index: *global_index,
                     entry: entry_name.clone(),
                     variable_index: var_idx,
+                    value,
+                    hash,
+                })?;
+                *global_index += 1;
+            }
+        }
+        for child in &entry.children {
+            Self::visit_texts_recursive(
+                child,
+                var_type,
+                global_index,
+                with_hash,
+                leaves_only,
+                emit,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Replaces every string value for which `f` returns a different string, returning how
+    /// many values changed
+    pub fn map_texts(&mut self, mut f: impl FnMut(&str) -> Option<String>) -> usize {
+        let mut changed = 0;
+        let mut stack: Vec<&mut Entry> = self.entries.iter_mut().collect();
+        while let Some(entry) = stack.pop() {
+            for var in &mut entry.variables {
+                if let VarValue::String(Some(value)) = &mut var.value {
+                    if let Some(new_value) = f(value).filter(|v| v != value) {
+                        *value = new_value;
+                        changed += 1;
+                    }
+                }
+            }
+            stack.extend(entry.children.iter_mut());
+        }
+        changed
+    }
+
+    /// Removes entries left without content: no children and only null or empty string
+    /// variables. Blocks (BEGIN/START/PTREE) and parameterless rows are kept even when empty,
+    /// and a parent emptied by the removal is removed too. Returns how many entries went;
+    /// `save` derives the header counts from the remaining tree, and occurrence suffixes are
+    /// renumbered to match what `open` would give the saved file.
+    pub fn strip_empty_entries(&mut self) -> usize {
+        let removed = Self::strip_empty_children(&mut self.entries);
+        if removed > 0 {
+            let mut occurrences: HashMap<String, usize> = HashMap::new();
+            self.walk_mut(|entry, _| {
+                let base = entry.get_name();
+                let count = occurrences.entry(base.clone()).or_insert(0);
+                entry.name = format!("{}_{}", base, count);
+                entry.source_name = Some(base.clone());
+                *count += 1;
+            });
+        }
+        removed
+    }
+
+    fn strip_empty_children(entries: &mut Vec<Entry>) -> usize {
+        let mut removed = 0;
+        for entry in entries.iter_mut() {
+            removed += Self::strip_empty_children(&mut entry.children);
+        }
+        let before = entries.len();
+        entries.retain(|entry| !entry.is_empty_leaf());
+        removed + before - entries.len()
+    }
+
+    /// Entry at `entry_path`: entry names joined by `/`, as in `extract_texts_by_path`
+    /// (`LIST_BEGIN_0/ITEM_1`). A segment without its occurrence suffix names the first
+    /// occurrence, so `ITEM_LIST/ITEM` is `ITEM_LIST_0/ITEM_0`.
+    pub fn get_entry(&self, entry_path: &str) -> Option<&Entry> {
+        let mut segments = entry_path.split('/');
+        let first = segments.next()?;
+        let mut entry = self
+            .entries
+            .iter()
+            .find(|e| Self::path_segment_matches(e, first))?;
+        for segment in segments {
+            entry = entry
+                .children
+                .iter()
+                .find(|e| Self::path_segment_matches(e, segment))?;
+        }
+        Some(entry)
+    }
+
+    pub fn get_entry_mut(&mut self, entry_path: &str) -> Option<&mut Entry> {
+        let mut segments = entry_path.split('/');
+        let first = segments.next()?;
+        let mut entry = self
+            .entries
+            .iter_mut()
+            .find(|e| Self::path_segment_matches(e, first))?;
+        for segment in segments {
+            entry = entry
+                .children
+                .iter_mut()
+                .find(|e| Self::path_segment_matches(e, segment))?;
+        }
+        Some(entry)
+    }
+
+    fn path_segment_matches(entry: &Entry, segment: &str) -> bool {
+        entry.name == segment
+            || entry
+                .name
+                .strip_prefix(segment)
+                .is_some_and(|suffix| suffix == "_0")
+    }
+
+    /// Whether both files hold the same entry tree, to the bit (see `VarValue`'s equality).
+    /// Encoding, key table layout and padding are not compared.
+    pub fn structurally_eq(&self, other: &CfgBin) -> bool {
+        self.entries == other.entries
+    }
+
+    pub fn get_variable(&self, entry_path: &str, variable_index: usize) -> Option<&Variable> {
+        self.get_entry(entry_path)?.variables.get(variable_index)
+    }
+
+    /// Replaces one variable's value. The value must have the variable's type; string offsets
+    /// are recomputed on save.
+    pub fn set_variable(
+        &mut self,
+        entry_path: &str,
+        variable_index: usize,
+        value: VarValue,
+    ) -> Result<()> {
+        let entry = self
+            .get_entry_mut(entry_path)
+            .ok_or_else(|| anyhow!("No entry at path {}", entry_path))?;
+        let count = entry.variables.len();
+        let var = entry.variables.get_mut(variable_index).ok_or_else(|| {
+            anyhow!(
+                "{} has {} variable(s); index {} is out of range",
+                entry_path,
+                count,
+                variable_index
+            )
+        })?;
+        let value_type = match &value {
+            VarValue::String(_) => VarType::String,
+            VarValue::Int(_) => VarType::Int,
+            VarValue::Float(_) => VarType::Float,
+            VarValue::Unknown(_) => VarType::Unknown,
+        };
+        if value_type != var.var_type {
+            bail!(
+                "{}[{}] is a {:?} variable; got a {:?} value",
+                entry_path,
+                variable_index,
+                var.var_type,
+                value_type
+            );
+        }
+        var.value = value;
+        Ok(())
+    }
+
+    /// Texts keyed by path, `"<entry>/<child>/.../<variable_index>"` with occurrence-suffixed
+    /// entry names (e.g. `"TEXT_INFO_BEGIN_0/TEXT_INFO_2/1"`), in extraction order. Paths are
+    /// still positional: an entry inserted before a same-named one renumbers its suffix.
+    pub fn extract_texts_by_path(&self) -> Result<Vec<(String, String)>> {
+        let mut texts = Vec::new();
+        let mut seen = BTreeSet::new();
+        for entry in &self.entries {
+            Self::collect_texts_by_path(entry, "", &mut texts);
+        }
+        for (path, _) in &texts {
+            if !seen.insert(path.as_str()) {
+                bail!("Duplicate text path {}; entry names are not unique", path);
+            }
+        }
+        Ok(texts)
+    }
+
+    fn collect_texts_by_path(entry: &Entry, parent: &str, texts: &mut Vec<(String, String)>) {
+        let path = format!("{}{}", parent, entry.name);
+        for (var_idx, var) in entry.variables.iter().enumerate() {
+            if let VarValue::String(opt) = &var.value {
+                texts.push((
+                    format!("{}/{}", path, var_idx),
+                    opt.clone().unwrap_or_default(),
+                ));
+            }
+        }
+        let prefix = format!("{}/", path);
+        for child in &entry.children {
+            Self::collect_texts_by_path(child, &prefix, texts);
+        }
+    }
+
+    /// Every entry with all of its variables, whatever their type, in `walk` order.
+    pub fn extract_all(&self) -> Vec<EntryDump> {
+        let mut dumps = Vec::new();
+        self.walk(|entry, _| {
+            dumps.push(EntryDump {
+                index: dumps.len(),
+                entry: entry.name.clone(),
+                variables: entry.variables.iter().map(VariableDump::of).collect(),
+            })
+        });
+        dumps
+    }
+
+    /// Replaces the variables of each listed entry with those of its dump (see `extract_all`),
+    /// returning how many entries were applied; unlisted entries are left alone. A variable
+    /// whose value reads back unchanged keeps its stored bits. Fails without changing anything
+    /// if an index or entry name doesn't match the file or a value doesn't fit its type.
+    pub fn apply_all(&mut self, dumps: &[EntryDump]) -> Result<usize> {
+        let mut names = Vec::new();
+        self.walk(|entry, _| names.push(entry.name.clone()));
+        let mut by_index = HashMap::new();
+        for dump in dumps {
+            match names.get(dump.index) {
+                None => bail!(
+                    "Index {} does not exist; the file has {} entries",
+                    dump.index,
+                    names.len()
+                ),
+                Some(name) if *name != dump.entry => bail!(
+                    "Index {} is entry {} in this file, not {}",
+                    dump.index,
+                    name,
+                    dump.entry
+                ),
+                Some(_) => {}
+            }
+            let variables = dump
+                .variables
+                .iter()
+                .enumerate()
+                .map(|(var_idx, var)| {
+                    var.to_variable().with_context(|| {
+                        format!(
+                            "Index {} ({}[{}]): {} is not a valid {} value",
+                            dump.index,
+                            dump.entry,
+                            var_idx,
+                            serde_json::to_string(&var.value).unwrap_or_default(),
+                            var.var_type.name()
+                        )
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            if variables.len() > MAX_VARIABLES {
+                bail!(
+                    "Index {} ({}) has {} variables; a row holds at most {}",
+                    dump.index,
+                    dump.entry,
+                    variables.len(),
+                    MAX_VARIABLES
+                );
+            }
+            if by_index.insert(dump.index, variables).is_some() {
+                bail!("Index {} is listed more than once", dump.index);
+            }
+        }
+
+        let mut index = 0;
+        self.walk_mut(|entry, _| {
+            if let Some(variables) = by_index.remove(&index) {
+                let old = std::mem::replace(&mut entry.variables, variables);
+                for (new, old) in entry.variables.iter_mut().zip(old) {
+                    let text = |v: &Variable| v.value.text_of_type(v.var_type);
+                    if new.var_type == old.var_type && text(new) == text(&old) {
+                        *new = old;
+                    }
+                }
+            }
+            index += 1;
+        });
+        Ok(dumps.len())
+    }
+
+    /// Applies path-keyed texts (see `extract_texts_by_path`), returning how many were applied.
+    /// Fails without changing anything if a path names no string slot.
+    pub fn update_texts_by_path(&mut self, texts: &BTreeMap<String, String>) -> Result<usize> {
+        let known: BTreeSet<String> = self
+            .extract_texts_by_path()?
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        if let Some(unknown) = texts.keys().find(|path| !known.contains(*path)) {
+            bail!(
+                "Text path {} does not match any string slot in this file",
+                unknown
+            );
+        }
+        for entry in &mut self.entries {
+            Self::update_texts_by_path_recursive(entry, "", texts);
+        }
+        Ok(texts.len())
+    }
+
+    fn update_texts_by_path_recursive(
+        entry: &mut Entry,
+        parent: &str,
+        texts: &BTreeMap<String, String>,
+    ) {
+        let path = format!("{}{}", parent, entry.name);
+        for (var_idx, var) in entry.variables.iter_mut().enumerate() {
+            if let VarValue::String(_) = &var.value {
+                if let Some(value) = texts.get(&format!("{}/{}", path, var_idx)) {
+                    var.value = VarValue::String(Some(value.clone()));
+                }
+            }
+        }
+        let prefix = format!("{}/", path);
+        for child in &mut entry.children {
+            Self::update_texts_by_path_recursive(child, &prefix, texts);
+        }
+    }
+
+    /// Global text indices of the string slots in entries whose base name is `name` and in their
+    /// descendants, in extraction order; `None` if no entry has that name
+    pub fn text_indices_under(&self, name: &str) -> Option<Vec<usize>> {
+        self.value_indices_under(VarType::String, name)
+    }
+
+    /// `text_indices_under` for the slots of `extract_values(var_type, ..)`
+    pub fn value_indices_under(&self, var_type: VarType, name: &str) -> Option<Vec<usize>> {
+        let mut next_index = 0usize;
+        let mut found = false;
+        let mut indices = Vec::new();
+        for entry in &self.entries {
+            Self::collect_indices_under(
+                entry,
+                var_type,
+                name,
+                false,
+                &mut next_index,
+                &mut found,
+                &mut indices,
+            );
+        }
+        found.then_some(indices)
+    }
+
+    fn collect_indices_under(
+        entry: &Entry,
+        var_type: VarType,
+        name: &str,
+        inside: bool,
+        next_index: &mut usize,
+        found: &mut bool,
+        indices: &mut Vec<usize>,
+    ) {
+        let matches = entry.get_name() == name;
+        *found |= matches;
+        let inside = inside || matches;
+        for var in &entry.variables {
+            if var.value.has_type(var_type) {
+                if inside {
+                    indices.push(*next_index);
+                }
+                *next_index += 1;
+            }
+        }
+        for child in &entry.children {
+            Self::collect_indices_under(child, var_type, name, inside, next_index, found, indices);
+        }
+    }
+
+    /// Extract all text fields nested under their entry hierarchy (read-only view)
+    pub fn extract_text_tree(&self) -> Vec<TextNode> {
+        let mut global_index = 0usize;
+        self.entries
+            .iter()
+            .map(|entry| Self::collect_text_node(entry, &mut global_index))
+            .collect()
+    }
+
+    fn collect_text_node(entry: &Entry, global_index: &mut usize) -> TextNode {
+        let mut texts = Vec::new();
+        for (var_idx, var) in entry.variables.iter().enumerate() {
+            if let VarValue::String(opt) = &var.value {
+                texts.push(TreeText {
+                    index: *global_index,
+                    variable_index: var_idx,
                     value: opt.clone().unwrap_or_default(),
                 });
                 *global_index += 1;
             }
         }
-        for child in &entry.children {
-            Self::collect_texts_recursive(child, texts, global_index);
+        let children = entry
+            .children
+            .iter()
+            .map(|child| Self::collect_text_node(child, global_index))
+            .collect();
+        TextNode {
+            name: entry.name.clone(),
+            texts,
+            children,
+        }
+    }
+
+    /// Slots whose value in `edited` (an extract of this file, edited) differs from this file.
+    /// Indices that don't exist in this file are ignored.
+    pub fn diff_texts(&self, edited: &[TextEntry]) -> Vec<TextPatch> {
+        let current = self.extract_texts();
+        let mut patches: Vec<TextPatch> = edited
+            .iter()
+            .filter_map(|e| {
+                let slot = current.get(e.index)?;
+                (slot.value != e.value).then(|| TextPatch {
+                    index: slot.index,
+                    entry: slot.entry.clone(),
+                    variable_index: slot.variable_index,
+                    old: slot.value.clone(),
+                    new: e.value.clone(),
+                })
+            })
+            .collect();
+        patches.sort_by_key(|p| p.index);
+        patches.dedup_by_key(|p| p.index);
+        patches
+    }
+
+    /// Applies `patches` after checking that each one targets the recorded slot and that the
+    /// slot still holds `old`, so a patch made for another file or version is refused whole.
+    pub fn apply_text_patches(&mut self, patches: &[TextPatch]) -> Result<usize> {
+        let mut texts = self.extract_texts();
+        for p in patches {
+            let slot = texts
+                .get_mut(p.index)
+                .with_context(|| format!("Patch index {} does not exist in this file", p.index))?;
+            if slot.entry != p.entry || slot.variable_index != p.variable_index {
+                bail!(
+                    "Patch index {} is {}[{}], but the file has {}[{}] there",
+                    p.index,
+                    p.entry,
+                    p.variable_index,
+                    slot.entry,
+                    slot.variable_index
+                );
+            }
+            if slot.value != p.old && slot.value != p.new {
+                bail!(
+                    "Patch index {} ({}[{}]) expects {:?}, but the file has {:?}",
+                    p.index,
+                    p.entry,
+                    p.variable_index,
+                    p.old,
+                    slot.value
+                );
+            }
+            slot.value = p.new.clone();
+        }
+        self.update_texts(&texts);
+        Ok(patches.len())
+    }
+
+    /// Update text fields from a list of TextEntry (from JSON import).
+    ///
+    /// Slots are matched by `index`; the value is applied even when the slot's entry name or
+    /// variable index differs from the one recorded in the TextEntry, and every such slot is
+    /// returned so callers can flag stale or hand-edited translation files.
+    pub fn update_texts(&mut self, texts: &[TextEntry]) -> Vec<SlotMismatch> {
+        self.update_slots(VarType::String, texts)
+    }
+
+    /// Like `update_texts`, for slots numbered as in `extract_values(var_type, ..)`. Numbers
+    /// must parse as the slot's type; then the whole update is refused. A number whose text is
+    /// unchanged keeps its stored bits (e.g. a NaN payload).
+    pub fn update_values(
+        &mut self,
+        var_type: VarType,
+        values: &[TextEntry],
+    ) -> Result<Vec<SlotMismatch>> {
+        if let Some(bad) = values.iter().find(|v| !var_type.accepts(&v.value)) {
+            bail!(
+                "Index {}: {:?} is not a valid {} value",
+                bad.index,
+                bad.value,
+                var_type.name()
+            );
         }
+        Ok(self.update_slots(var_type, values))
     }
 
-    /// Update text fields from a list of TextEntry (from JSON import)
-    pub fn update_texts(&mut self, texts: &[TextEntry]) {
-        let mut text_iter_index = 0usize;
+    fn update_slots(&mut self, var_type: VarType, texts: &[TextEntry]) -> Vec<SlotMismatch> {
         let mut global_index = 0usize;
+        let mut mismatches = Vec::new();
         for entry in &mut self.entries {
-            Self::update_texts_recursive(entry, texts, &mut text_iter_index, &mut global_index);
+            Self::update_texts_recursive(
+                entry,
+                var_type,
+                texts,
+                &mut global_index,
+                &mut mismatches,
+            );
         }
+        mismatches
     }
 
     fn update_texts_recursive(
         entry: &mut Entry,
+        var_type: VarType,
         texts: &[TextEntry],
-        text_iter_index: &mut usize,
         global_index: &mut usize,
+        mismatches: &mut Vec<SlotMismatch>,
     ) {
-        for (_var_idx, var) in entry.variables.iter_mut().enumerate() {
-            if let VarValue::String(_) = &var.value {
+        let entry_name = entry.get_name();
+        for (var_idx, var) in entry.variables.iter_mut().enumerate() {
+            if var.value.has_type(var_type) {
                 if let Some(te) = texts.iter().find(|t| t.index == *global_index) {
-                    var.value = VarValue::String(Some(te.value.clone()));
+                    if te.entry != entry_name || te.variable_index != var_idx {
+                        mismatches.push(SlotMismatch {
+                            index: te.index,
+                            recorded_entry: te.entry.clone(),
+                            recorded_variable_index: te.variable_index,
+                            actual_entry: entry_name.clone(),
+                            actual_variable_index: var_idx,
+                        });
+                    }
+                    let unchanged = var_type != VarType::String
+                        && var.value.text_of_type(var_type).as_ref() == Some(&te.value);
+                    if let Some(value) = var.value.with_text(&te.value).filter(|_| !unchanged) {
+                        var.value = value;
+                    }
                 }
                 *global_index += 1;
             }
         }
         for child in &mut entry.children {
-            Self::update_texts_recursive(child, texts, text_iter_index, global_index);
+            Self::update_texts_recursive(child, var_type, texts, global_index, mismatches);
+        }
+    }
+}
+
+/// Key-string blob of a source key table and the offset of each key name in it.
+#[derive(Debug, Clone, Default)]
+pub struct KeyStrings {
+    pub data: Vec<u8>,
+    pub offsets: BTreeMap<String, i32>,
+    // Encoding `data` was written in; the blob is only reused by saves in the same encoding.
+    pub encoding: Option<CfgBinEncoding>,
+}
+
+/// String table prebuilt by `CfgBin::string_tables` for `CfgBin::save_with`, so repeated saves
+/// skip collecting and encoding the strings. It depends only on the string values (their order
+/// and, with `no_dedup_strings`, every reference) and on `encoding`/`string_format`: rebuild it
+/// whenever one of those changes. Edits to ints, floats or key names keep it valid.
+#[derive(Debug, Clone)]
+pub struct StringTables {
+    records: Vec<String>,
+    offsets: Vec<i32>,
+    by_value: HashMap<String, i32>,
+    data: Vec<u8>,
+    per_reference: bool,
+}
+
+impl StringTables {
+    // Offset for the `reference`-th string value written, if the table still matches it.
+    fn offset_for(&self, reference: usize, value: &str) -> Option<i32> {
+        if self.per_reference {
+            (self.records.get(reference)? == value).then(|| self.offsets[reference])
+        } else {
+            self.by_value.get(value).copied()
+        }
+    }
+}
+
+/// Key table prebuilt by `CfgBin::key_table` for `CfgBin::save_with_key_table`, so repeated
+/// saves skip collecting, hashing and encoding the key names. It stays valid while the set of
+/// entry names (with their END terminators) is unchanged: adding, removing or renaming entries
+/// invalidates it, and so do another `encoding`, `endian`, key-table padding, `key_order` or
+/// `preserve_key_strings`. Edits
+/// to variable values keep it valid; reordered entries keep the table's original row order.
+#[derive(Debug, Clone)]
+pub struct KeyTable {
+    names: HashSet<String>,
+    data: Vec<u8>,
+    encoding: CfgBinEncoding,
+    endian: Endian,
+    padding: u8,
+    key_order: Option<Vec<String>>,
+    preserve_key_strings: bool,
+}
+
+impl KeyTable {
+    // The entry names are checked while `write_file` encodes the rows.
+    fn check(&self, cfg: &CfgBin, options: &SaveOptions) -> Result<()> {
+        if self.encoding != cfg.encoding
+            || self.endian != cfg.endian
+            || self.padding != cfg.padding.key_table
+            || self.key_order != options.key_order
+            || self.preserve_key_strings != options.preserve_key_strings
+        {
+            bail!("Key table was built for another encoding, byte order, padding or key options; rebuild it");
         }
+        Ok(())
+    }
+}
+
+/// The first byte where a re-saved file differs from the original (see
+/// `CfgBin::verify_roundtrip`), with the bytes around it from each side. When one file is a
+/// prefix of the other, `offset` is the shorter one's length.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RoundTripDiff {
+    pub offset: usize,
+    pub original_len: usize,
+    pub saved_len: usize,
+    /// Offset of the first byte of `original` and `saved`
+    pub context_start: usize,
+    pub original: Vec<u8>,
+    pub saved: Vec<u8>,
+}
+
+impl RoundTripDiff {
+    /// Context bytes kept on each side of `offset`.
+    pub const CONTEXT: usize = 8;
+
+    pub fn between(original: &[u8], saved: &[u8]) -> Option<Self> {
+        let offset = original
+            .iter()
+            .zip(saved)
+            .position(|(a, b)| a != b)
+            .or_else(|| (original.len() != saved.len()).then(|| original.len().min(saved.len())))?;
+        let context_start = offset.saturating_sub(Self::CONTEXT);
+        let window = |bytes: &[u8]| {
+            let end = (offset + Self::CONTEXT).min(bytes.len());
+            bytes[context_start.min(end)..end].to_vec()
+        };
+        Some(RoundTripDiff {
+            offset,
+            original_len: original.len(),
+            saved_len: saved.len(),
+            context_start,
+            original: window(original),
+            saved: window(saved),
+        })
     }
 }
 
+/// A string-table record and every entry field that points at it (see `CfgBin::string_offsets`).
+/// All offsets are from the start of the file.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StringOffsets {
+    pub value: String,
+    pub string_offset: u32,
+    /// Addresses of the 4-byte string-offset fields in the entry rows
+    pub reference_offsets: Vec<u32>,
+}
+
+/// A string on which standard and nnk extraction disagree (see `CfgBin::compare_modes`). Each
+/// side is `None` when that mode has no string at this address.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ModeDivergence {
+    /// File address of the string's offset field
+    pub address: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entry: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variable_index: Option<usize>,
+    pub standard: Option<String>,
+    pub nnk: Option<String>,
+}
+
+/// One record of the string table as stored, with both candidate decodings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawString {
+    /// Offset from the start of the string table (what entry rows store)
+    pub offset: usize,
+    /// Record bytes without the terminator or length prefix
+    pub bytes: Vec<u8>,
+    pub shift_jis: String,
+    pub utf8: String,
+}
+
+/// Names sharing one CRC32, from `crc_collisions`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrcCollision {
+    pub crc: u32,
+    pub names: Vec<String>,
+}
+
+/// Region sizes in bytes, from `CfgBin::region_sizes`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct RegionSizes {
+    /// Entry rows, 0x10 up to the string table
+    pub entries: usize,
+    pub string_table: usize,
+    pub key_table: usize,
+}
+
+/// Number of variables of each `VarType` across the whole entry tree.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct VarTypeCounts {
+    pub string: usize,
+    pub int: usize,
+    pub float: usize,
+    pub unknown: usize,
+}
+
+/// A key whose CRC changes on save; see `CfgBin::rehashed_keys`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RehashedKey {
+    pub name: String,
+    pub original_crc: u32,
+    pub new_crc: u32,
+}
+
+/// Outcome of `CfgBin::rename_keys`: rename-map keys that matched at least one entry, and
+/// those that matched none or were refused because a renamed block's END name couldn't be
+/// derived or would collide.
+#[derive(Debug, Default)]
+pub struct RenameReport {
+    pub renamed: Vec<String>,
+    pub unmatched: Vec<String>,
+}
+
+/// A node of the read-only tree export: one entry with its string values and child entries.
+#[derive(Debug, Clone, Serialize)]
+pub struct TextNode {
+    pub name: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub texts: Vec<TreeText>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<TextNode>,
+}
+
+/// A string value inside a `TextNode`; `index` matches `TextEntry::index` of the flat export.
+#[derive(Debug, Clone, Serialize)]
+pub struct TreeText {
+    pub index: usize,
+    pub variable_index: usize,
+    pub value: String,
+}
+
+/// A TextEntry whose `(entry, variable_index)` doesn't match the slot its `index` resolved to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlotMismatch {
+    pub index: usize,
+    pub recorded_entry: String,
+    pub recorded_variable_index: usize,
+    pub actual_entry: String,
+    pub actual_variable_index: usize,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TextEntry {
     pub index: usize,
     pub entry: String,
     pub variable_index: usize,
     pub value: String,
+    // CRC32 of the source value, only exported with `--with-hash`; ignored on update.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+/// An entry with every variable, as `CfgBin::extract_all` lists them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EntryDump {
+    /// Position in `CfgBin::walk` order
+    pub index: usize,
+    pub entry: String,
+    pub variables: Vec<VariableDump>,
+}
+
+/// A variable as `{"type": "float", "value": 1.5}`. Strings are JSON strings (or null) and
+/// numbers JSON numbers; a float JSON can't hold is written as text (`"NaN"`, `"inf"`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VariableDump {
+    #[serde(rename = "type")]
+    pub var_type: VarType,
+    pub value: DumpValue,
+}
+
+/// A JSON value of a `VariableDump`. Integers read as `Int`, so the `type` decides whether
+/// `3` is an int or a float; an int slot rejects `1.5`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum DumpValue {
+    Null,
+    Int(i64),
+    Float(f32),
+    Text(String),
+}
+
+impl VariableDump {
+    fn of(var: &Variable) -> Self {
+        let value = match &var.value {
+            VarValue::String(Some(s)) => DumpValue::Text(s.clone()),
+            VarValue::String(None) => DumpValue::Null,
+            VarValue::Int(n) | VarValue::Unknown(n) => DumpValue::Int(i64::from(*n)),
+            VarValue::Float(f) if f.is_finite() => DumpValue::Float(*f),
+            VarValue::Float(f) => DumpValue::Text(f.to_string()),
+        };
+        VariableDump {
+            var_type: var.var_type,
+            value,
+        }
+    }
+
+    // `None` if the value doesn't fit the type. Numbers may also be given as text.
+    fn to_variable(&self) -> Option<Variable> {
+        let int = || match &self.value {
+            DumpValue::Int(n) => i32::try_from(*n).ok(),
+            DumpValue::Text(s) => s.trim().parse().ok(),
+            _ => None,
+        };
+        let value = match (self.var_type, &self.value) {
+            (VarType::String, DumpValue::Null) => VarValue::String(None),
+            (VarType::String, DumpValue::Text(s)) => VarValue::String(Some(s.clone())),
+            (VarType::String, _) => return None,
+            (VarType::Int, _) => VarValue::Int(int()?),
+            (VarType::Unknown, _) => VarValue::Unknown(int()?),
+            (VarType::Float, DumpValue::Int(n)) => VarValue::Float(*n as f32),
+            (VarType::Float, DumpValue::Float(f)) => VarValue::Float(*f),
+            (VarType::Float, DumpValue::Text(s)) => VarValue::Float(s.trim().parse().ok()?),
+            (VarType::Float, DumpValue::Null) => return None,
+        };
+        Some(Variable {
+            var_type: self.var_type,
+            value,
+        })
+    }
+}
+
+/// One edited string slot: the value a `CfgBin::diff_texts` source had and the edited one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TextPatch {
+    pub index: usize,
+    pub entry: String,
+    pub variable_index: usize,
+    pub old: String,
+    pub new: String,
 }
 
 #[cfg(test)]
@@ -1102,144 +3686,1568 @@ mod tests {
         buf.extend_from_slice(&strings_data);
         write_alignment(&mut buf, 16, 0xFF);
 
-        let tmp_cfg = CfgBin {
-            encoding,
-            footer_encoding: 1,
-            entries: Vec::new(),
+        let tmp_cfg = CfgBin::new(encoding, 1, Vec::new());
+        let key_table_data = tmp_cfg.encode_key_table(&[entry_name.to_string()], false);
+        buf.extend_from_slice(&key_table_data);
+
+        // Footer (UTF-8)
+        buf.extend_from_slice(&[0x01, 0x74, 0x32, 0x62]);
+        buf.extend_from_slice(&(0x01FEu16).to_le_bytes());
+        buf.extend_from_slice(&(1u16).to_le_bytes());
+        buf.extend_from_slice(&(1u16).to_le_bytes());
+        write_alignment(&mut buf, 16, 0xFF);
+
+        // Header
+        buf[0..4].copy_from_slice(&(1i32).to_le_bytes());
+        buf[4..8].copy_from_slice(&string_table_offset.to_le_bytes());
+        buf[8..12].copy_from_slice(&string_table_length.to_le_bytes());
+        buf[12..16].copy_from_slice(&string_table_count.to_le_bytes());
+
+        buf
+    }
+
+    // Builds a UTF-8 file from raw rows of (entry name, param types, raw 4-byte values), with
+    // an explicit key list so tests can omit or reorder keys.
+    fn build_cfg(rows: &[(&str, &[VarType], &[i32])], strings: &[u8], keys: &[&str]) -> Vec<u8> {
+        build_cfg_with(Endian::Little, rows, strings, keys)
+    }
+
+    fn build_cfg_with(
+        endian: Endian,
+        rows: &[(&str, &[VarType], &[i32])],
+        strings: &[u8],
+        keys: &[&str],
+    ) -> Vec<u8> {
+        let encoding = CfgBinEncoding::Utf8;
+        let mut buf = vec![0u8; 16]; // header placeholder
+        for (name, types, values) in rows {
+            let crc = crc32::compute(&encode_string_bytes(name, &encoding));
+            buf.extend_from_slice(&endian.u32_bytes(crc));
+            buf.push(types.len() as u8);
+            buf.extend_from_slice(&Entry::encode_types(types));
+            for v in values.iter() {
+                buf.extend_from_slice(&endian.i32_bytes(*v));
+            }
+        }
+        write_alignment(&mut buf, 16, 0xFF);
+
+        let string_table_offset = buf.len() as i32;
+        buf.extend_from_slice(strings);
+        write_alignment(&mut buf, 16, 0xFF);
+
+        let keys: Vec<String> = keys.iter().map(|k| k.to_string()).collect();
+        let mut key_writer = CfgBin::new(encoding, 1, Vec::new());
+        key_writer.endian = endian;
+        buf.extend_from_slice(&key_writer.encode_key_table(&keys, false));
+
+        // Footer (UTF-8)
+        buf.extend_from_slice(&endian.u32_bytes(0x6232_7401));
+        buf.extend_from_slice(&endian.u16_bytes(0x01FE));
+        buf.extend_from_slice(&endian.u16_bytes(1));
+        buf.extend_from_slice(&endian.u16_bytes(1));
+        write_alignment(&mut buf, 16, 0xFF);
+
+        let record_count = strings.iter().filter(|&&b| b == 0).count() as i32;
+        buf[0..4].copy_from_slice(&endian.i32_bytes(rows.len() as i32));
+        buf[4..8].copy_from_slice(&endian.i32_bytes(string_table_offset));
+        buf[8..12].copy_from_slice(&endian.i32_bytes(strings.len() as i32));
+        buf[12..16].copy_from_slice(&endian.i32_bytes(record_count));
+        buf
+    }
+
+    #[test]
+    fn save_with_options_reproduces_scrambled_key_order() -> Result<()> {
+        let int = &[VarType::Int][..];
+        let rows = [
+            ("AAA", int, &[7][..]),
+            ("BBB", int, &[7]),
+            ("CCC", int, &[7]),
+        ];
+        // Key table rows deliberately differ from entry traversal order.
+        let buf = build_cfg(&rows, b"", &["CCC", "AAA", "BBB"]);
+        let scrambled = vec!["CCC".to_string(), "AAA".to_string(), "BBB".to_string()];
+
+        let cfg = CfgBin::open(&buf)?;
+        assert_eq!(cfg.key_order, scrambled);
+
+        let options = SaveOptions {
+            key_order: Some(cfg.key_order.clone()),
+            ..SaveOptions::default()
+        };
+        let reopened = CfgBin::open(&cfg.save_with_options(&options)?)?;
+        assert_eq!(reopened.key_order, scrambled);
+
+        let default_order = CfgBin::open(&cfg.save()?)?;
+        assert_ne!(default_order.key_order, scrambled);
+
+        Ok(())
+    }
+
+    #[test]
+    fn preserve_key_strings_keeps_source_key_string_offsets() -> Result<()> {
+        let rows = [
+            ("LIST_BEGIN", &[][..], &[][..]),
+            ("LIST", &[VarType::Int][..], &[1][..]),
+            ("LIST_END", &[], &[]),
+        ];
+        let mut data = build_cfg(&rows, b"", &["LIST", "LIST_END", "LIST_BEGIN"]);
+        // Store the key strings in a different order than the key rows.
+        let key_table = round_up(
+            read_i32(&data, 4) as usize + read_i32(&data, 8) as usize,
+            16,
+        );
+        let blob = key_table + read_i32(&data, key_table + 8) as usize;
+        let reordered = b"LIST_BEGIN\0LIST\0LIST_END\0";
+        data[blob..blob + reordered.len()].copy_from_slice(reordered);
+        for (row, offset) in [(0, 11i32), (1, 16), (2, 0)] {
+            let pos = key_table + 0x10 + row * 8 + 4;
+            data[pos..pos + 4].copy_from_slice(&offset.to_le_bytes());
+        }
+        let key_offsets = |bytes: &[u8]| -> Vec<i32> {
+            (0..3)
+                .map(|row| read_i32(bytes, key_table + 0x10 + row * 8 + 4))
+                .collect()
+        };
+
+        let cfg = CfgBin::open(&data)?;
+        let options = SaveOptions {
+            key_order: Some(cfg.key_order.clone()),
+            preserve_key_strings: true,
+            ..SaveOptions::default()
+        };
+        let saved = cfg.save_with_options(&options)?;
+        assert_eq!(key_offsets(&saved), [11, 16, 0]);
+        assert_eq!(saved, data);
+
+        // A different output encoding writes the key strings afresh.
+        let mut converted = CfgBin::open(&data)?;
+        converted.encoding = CfgBinEncoding::ShiftJis;
+        let saved = converted.save_with_options(&options)?;
+        assert_eq!(key_offsets(&saved), [0, 5, 14]);
+        assert_eq!(CfgBin::open(&saved)?.key_order, cfg.key_order);
+
+        let options = SaveOptions {
+            key_order: Some(cfg.key_order.clone()),
+            ..SaveOptions::default()
+        };
+        assert_eq!(key_offsets(&cfg.save_with_options(&options)?), [0, 5, 14]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn open_with_ignore_unknown_crc_uses_placeholder_names_and_saves_their_crcs() -> Result<()> {
+        let string = &[VarType::String][..];
+        let rows = [("KNOWN", string, &[0][..]), ("MISSING", string, &[3])];
+        let buf = build_cfg(&rows, b"aa\0bb\0", &["KNOWN"]);
+        let missing_crc = crc32::compute(b"MISSING");
+
+        assert!(CfgBin::open(&buf).is_err());
+
+        let options = OpenOptions {
+            ignore_unknown_crc: true,
+            ..OpenOptions::default()
+        };
+        let cfg = CfgBin::open_with_options(&buf, &options)?;
+        assert_eq!(cfg.unresolved_crcs, vec![missing_crc]);
+
+        let texts = cfg.extract_texts();
+        assert_eq!(texts.len(), 2);
+        assert_eq!(texts[0].entry, "KNOWN");
+        assert_eq!(texts[1].entry, format!("UNKNOWN_0x{:08X}", missing_crc));
+        assert_eq!(texts[1].value, "bb");
+
+        let mut cfg = cfg;
+        cfg.update_texts(&[TextEntry {
+            value: "changed".to_string(),
+            ..texts[1].clone()
+        }]);
+        let saved = cfg.save()?;
+        let err = CfgBin::open(&saved).err().unwrap();
+        assert!(
+            err.to_string().contains(&format!("{:08x}", missing_crc)),
+            "{}",
+            err
+        );
+        let reopened = CfgBin::open_with_options(&saved, &options)?;
+        assert_eq!(reopened.unresolved_crcs, vec![missing_crc]);
+        assert_eq!(reopened.key_order, ["KNOWN"]);
+        assert_eq!(reopened.extract_texts()[1].value, "changed");
+
+        Ok(())
+    }
+
+    #[test]
+    fn rename_keys_matches_names_and_crcs() -> Result<()> {
+        let string = &[VarType::String][..];
+        let rows = [("KNOWN", string, &[0][..]), ("MISSING", string, &[3])];
+        let buf = build_cfg(&rows, b"aa\0bb\0", &["KNOWN"]);
+        let options = OpenOptions {
+            ignore_unknown_crc: true,
+            ..OpenOptions::default()
+        };
+        let mut cfg = CfgBin::open_with_options(&buf, &options)?;
+
+        let renames: BTreeMap<String, String> = [("KNOWN", "FIRST"), ("0xDEADBEEF", "NOWHERE")]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .chain([(
+                format!("0x{:08x}", crc32::compute(b"MISSING")),
+                "SECOND".to_string(),
+            )])
+            .collect();
+        let report = cfg.rename_keys(&renames);
+        assert_eq!(report.renamed.len(), 2);
+        assert_eq!(report.unmatched, vec!["0xDEADBEEF".to_string()]);
+        assert!(cfg.unresolved_crcs.is_empty());
+
+        let reopened = CfgBin::open(&cfg.save()?)?;
+        let names: Vec<String> = reopened
+            .extract_texts()
+            .into_iter()
+            .map(|t| t.entry)
+            .collect();
+        assert_eq!(names, ["FIRST", "SECOND"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn rename_keys_refuses_blocks_whose_end_name_cannot_follow() -> Result<()> {
+        let rows = [
+            ("LIST_BEGIN", &[VarType::Int][..], &[1][..]),
+            ("LIST", &[VarType::Int], &[1]),
+            ("LIST_END", &[], &[]),
+        ];
+        let buf = build_cfg(&rows, b"", &["LIST_BEGIN", "LIST", "LIST_END"]);
+        for refused in ["LIST_Begin", "LIST_HEAD"] {
+            let mut cfg = CfgBin::open(&buf)?;
+            let renames = BTreeMap::from([("LIST_BEGIN".to_string(), refused.to_string())]);
+            let report = cfg.rename_keys(&renames);
+            assert!(report.renamed.is_empty(), "{}", refused);
+            assert_eq!(report.unmatched, ["LIST_BEGIN"]);
+            assert_eq!(cfg.save()?, buf);
+        }
+
+        let mut cfg = CfgBin::open(&buf)?;
+        let renames = BTreeMap::from([
+            ("LIST_BEGIN".to_string(), "SHOP_BEGIN".to_string()),
+            ("LIST".to_string(), "SHOP".to_string()),
+        ]);
+        assert_eq!(cfg.rename_keys(&renames).renamed, ["LIST", "LIST_BEGIN"]);
+        let reopened = CfgBin::open(&cfg.save()?)?;
+        let names: Vec<String> = reopened.entries.iter().map(|e| e.get_name()).collect();
+        assert_eq!(names, ["SHOP_BEGIN"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn unused_keys_lists_key_table_names_no_row_references() -> Result<()> {
+        let rows = [
+            ("LIST_BEGIN", &[][..], &[][..]),
+            ("LIST", &[VarType::Int][..], &[1]),
+            ("LIST_END", &[], &[]),
+        ];
+        let keys = ["ORPHAN", "LIST_BEGIN", "LIST", "LIST_END", "SPARE"];
+        let cfg = CfgBin::open(&build_cfg(&rows, b"", &keys))?;
+        assert_eq!(cfg.unused_keys(), ["ORPHAN", "SPARE"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn variable_type_counts_cover_nested_entries() -> Result<()> {
+        use VarType::*;
+        let rows = [
+            ("LIST_BEGIN", &[Int][..], &[1][..]),
+            ("LIST", &[String, Float, Unknown][..], &[0, 0, 7]),
+            ("LIST", &[String, Int][..], &[0, 2]),
+            ("LIST_END", &[], &[]),
+        ];
+        let keys = ["LIST_BEGIN", "LIST", "LIST_END"];
+        let cfg = CfgBin::open(&build_cfg(&rows, b"a\0", &keys))?;
+
+        let counts = cfg.variable_type_counts();
+        assert_eq!(
+            counts,
+            VarTypeCounts {
+                string: 2,
+                int: 2,
+                float: 1,
+                unknown: 1
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn saved_key_tables_and_key_diagnostics_are_stable() -> Result<()> {
+        let rows = [
+            ("ZED", &[][..], &[][..]),
+            ("ALPHA", &[], &[]),
+            ("MID", &[], &[]),
+        ];
+        let keys = ["ZED", "SPARE", "ALPHA", "ORPHAN", "MID"];
+        let buf = build_cfg(&rows, b"", &keys);
+
+        let cfg = CfgBin::open(&buf)?;
+        assert_eq!(cfg.unused_keys(), ["SPARE", "ORPHAN"]);
+        let saves: Vec<Vec<u8>> = (0..2)
+            .map(|_| CfgBin::open(&buf)?.save())
+            .collect::<Result<_>>()?;
+        assert_eq!(saves[0], saves[1]);
+        let reopened = CfgBin::open(&saves[0])?;
+        assert_eq!(reopened.key_order, ["ZED", "ALPHA", "MID"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn rehashed_keys_reports_crcs_changed_by_encoding() -> Result<()> {
+        let rows = [("キー", &[][..], &[][..])];
+        let mut cfg = CfgBin::open(&build_cfg(&rows, b"", &["キー"]))?;
+        assert!(cfg.rehashed_keys().is_empty());
+
+        cfg.encoding = CfgBinEncoding::ShiftJis;
+        let rehashed = cfg.rehashed_keys();
+        assert_eq!(rehashed.len(), 1);
+        assert_eq!(rehashed[0].name, "キー");
+        assert_eq!(rehashed[0].original_crc, crc32::compute("キー".as_bytes()));
+        assert_eq!(
+            rehashed[0].new_crc,
+            crc32::compute(&encode_string_bytes("キー", &CfgBinEncoding::ShiftJis))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn open_captures_non_ff_padding_for_byte_exact_round_trip() -> Result<()> {
+        let string = &[VarType::String][..];
+        let rows = [
+            ("LIST_BEGIN", &[][..], &[][..]),
+            ("LIST", string, &[0]),
+            ("LIST_END", &[], &[]),
+        ];
+        let keys = ["LIST_BEGIN", "LIST", "LIST_END"];
+        let mut cfg = CfgBin::open(&build_cfg(&rows, b"abc\0", &keys))?;
+        assert_eq!(cfg.padding, Padding::default());
+
+        let zero = Padding {
+            entries: 0x00,
+            strings: 0x00,
+            key_table: 0x00,
+            footer: 0x00,
+        };
+        cfg.padding = zero;
+        let zero_padded = cfg.save()?;
+        let string_table_offset = read_i32(&zero_padded, 4) as usize;
+        assert_eq!(zero_padded[string_table_offset - 1], 0x00);
+        assert_eq!(zero_padded[zero_padded.len() - 1], 0x00);
+
+        let reopened = CfgBin::open(&zero_padded)?;
+        assert_eq!(reopened.padding, zero);
+        assert_eq!(reopened.save()?, zero_padded);
+        assert!(CfgBin::is_roundtrip_stable(&zero_padded)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn roundtrip_mismatch_reports_first_differing_offset() -> Result<()> {
+        let string = &[VarType::String][..];
+        let rows = [
+            ("LIST_BEGIN", &[][..], &[][..]),
+            ("LIST", string, &[0]),
+            ("LIST_END", &[], &[]),
+        ];
+        let keys = ["LIST_BEGIN", "LIST", "LIST_END"];
+        let saved = CfgBin::open(&build_cfg(&rows, b"abc\0", &keys))?.save()?;
+        assert!(CfgBin::is_roundtrip_stable(&saved)?);
+
+        // Alignment padding before the string table is rewritten as 0xFF on save.
+        let string_table_offset = read_i32(&saved, 4) as usize;
+        let mut tampered = saved.clone();
+        tampered[string_table_offset - 1] = 0x00;
+        assert_eq!(
+            CfgBin::roundtrip_mismatch(&tampered)?,
+            Some(string_table_offset - 1)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn length_prefixed_string_table_round_trips() -> Result<()> {
+        let string = &[VarType::String][..];
+        let rows = [("A", string, &[0][..]), ("B", string, &[6])];
+        let mut strings = Vec::new();
+        for s in ["aa", "bbb"] {
+            strings.extend_from_slice(&(s.len() as u32).to_le_bytes());
+            strings.extend_from_slice(s.as_bytes());
+        }
+        let buf = build_cfg(&rows, &strings, &["A", "B"]);
+
+        // Read as null-terminated, the length bytes show up as control characters.
+        let misread = CfgBin::open(&buf)?;
+        assert!(!misread.suspect_string_offsets.is_empty());
+
+        let options = OpenOptions {
+            string_format: StringFormat::LengthPrefixed,
+            ..OpenOptions::default()
+        };
+        let cfg = CfgBin::open_with_options(&buf, &options)?;
+        assert!(cfg.suspect_string_offsets.is_empty());
+        let values: Vec<String> = cfg.extract_texts().into_iter().map(|t| t.value).collect();
+        assert_eq!(values, ["aa", "bbb"]);
+
+        let saved = cfg.save()?;
+        let string_table_offset = read_i32(&saved, 4) as usize;
+        let string_table_length = read_i32(&saved, 8) as usize;
+        assert_eq!(
+            &saved[string_table_offset..string_table_offset + string_table_length],
+            &strings[..]
+        );
+        let reopened = CfgBin::open_with_options(&saved, &options)?;
+        let values: Vec<String> = reopened
+            .extract_texts()
+            .into_iter()
+            .map(|t| t.value)
+            .collect();
+        assert_eq!(values, ["aa", "bbb"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn raw_strings_lists_records_with_both_decodings() -> Result<()> {
+        let rows = [("LIST_BEGIN", &[VarType::String][..], &[0][..])];
+        // "あ" in UTF-8, then "あ" in Shift-JIS
+        let strings = b"\xE3\x81\x82\0\x82\xA0\0";
+        let data = build_cfg(&rows, strings, &["LIST_BEGIN"]);
+
+        let raw = CfgBin::raw_strings(
+            &data,
+            StringFormat::NullTerminated,
+            Endian::Little,
+            CfgBinEncoding::Utf8,
+        )?;
+        assert_eq!(raw.len(), 2);
+        assert_eq!(raw[0].offset, 0);
+        assert_eq!(raw[0].bytes, b"\xE3\x81\x82");
+        assert_eq!(raw[0].utf8, "あ");
+        assert_ne!(raw[0].shift_jis, "あ");
+        assert_eq!(raw[1].offset, 4);
+        assert_eq!(raw[1].shift_jis, "あ");
+        assert!(raw[1].utf8.contains('\u{FFFD}'));
+
+        Ok(())
+    }
+
+    #[test]
+    fn region_sizes_follow_the_header_and_track_a_rebuild() -> Result<()> {
+        let rows = [("LIST_BEGIN", &[VarType::String][..], &[0][..])];
+        let data = build_cfg(&rows, b"Hi\0", &["LIST_BEGIN"]);
+        let string_table_offset = read_i32(&data, 4) as usize;
+        let key_table_offset = round_up(string_table_offset + 3, 16);
+
+        let sizes = CfgBin::region_sizes(&data, Endian::Little)?;
+        assert_eq!(sizes.entries, string_table_offset - 0x10);
+        assert_eq!(sizes.string_table, 3);
+        assert_eq!(sizes.key_table, read_i32(&data, key_table_offset) as usize);
+
+        let mut cfg = CfgBin::open(&data)?;
+        let mut texts = cfg.extract_texts();
+        texts[0].value = "Hello".to_string();
+        cfg.update_texts(&texts);
+        let rebuilt = CfgBin::region_sizes(&cfg.save()?, Endian::Little)?;
+        assert_eq!(rebuilt.entries, sizes.entries);
+        assert_eq!(rebuilt.string_table, 6);
+        assert_eq!(rebuilt.key_table, sizes.key_table);
+        assert!(CfgBin::region_sizes(&data[..8], Endian::Little).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn null_terminated_read_without_terminator_is_suspect() -> Result<()> {
+        let string = &[VarType::String][..];
+        let rows = [("A", string, &[0][..])];
+        // No terminator anywhere in the declared string table.
+        let buf = build_cfg(&rows, b"abc", &["A"]);
+
+        let cfg = CfgBin::open(&buf)?;
+        assert_eq!(cfg.suspect_string_offsets, vec![0]);
+        assert_eq!(cfg.extract_texts()[0].value, "abc");
+
+        Ok(())
+    }
+
+    #[test]
+    fn open_resolves_suffix_offsets_in_string_table() -> Result<()> {
+        let encoding = CfgBinEncoding::Utf8;
+        let entry_name = "TEST";
+        let entry_crc = crc32::compute(&encode_string_bytes(entry_name, &encoding));
+
+        // One entry with a single string value pointing to offset 2 inside "abcdef\0" -> "cdef".
+        let mut entry_bytes = Vec::new();
+        entry_bytes.extend_from_slice(&entry_crc.to_le_bytes());
+        entry_bytes.push(1); // param_count
+        entry_bytes.push(0); // types: 1x string
+        entry_bytes.extend_from_slice(&[0xFF, 0xFF]); // padding to 4-byte alignment
+        entry_bytes.extend_from_slice(&2i32.to_le_bytes()); // string offset
+
+        let mut buf = vec![0u8; 16]; // header placeholder
+        buf.extend_from_slice(&entry_bytes);
+        write_alignment(&mut buf, 16, 0xFF);
+
+        let string_table_offset = buf.len() as i32;
+        let strings_data = b"abcdef\0".to_vec();
+        let string_table_length = strings_data.len() as i32;
+        let string_table_count = 1i32;
+
+        buf.extend_from_slice(&strings_data);
+        write_alignment(&mut buf, 16, 0xFF);
+
+        // Key table: only needs the entry name for CRC resolution.
+        let tmp_cfg = CfgBin::new(encoding, 1, Vec::new());
+        let key_table_data = tmp_cfg.encode_key_table(&[entry_name.to_string()], false);
+        buf.extend_from_slice(&key_table_data);
+
+        // Footer (UTF-8).
+        buf.extend_from_slice(&[0x01, 0x74, 0x32, 0x62]);
+        buf.extend_from_slice(&(0x01FEu16).to_le_bytes());
+        buf.extend_from_slice(&(1u16).to_le_bytes());
+        buf.extend_from_slice(&(1u16).to_le_bytes());
+        write_alignment(&mut buf, 16, 0xFF);
+
+        // Header
+        buf[0..4].copy_from_slice(&(1i32).to_le_bytes());
+        buf[4..8].copy_from_slice(&string_table_offset.to_le_bytes());
+        buf[8..12].copy_from_slice(&string_table_length.to_le_bytes());
+        buf[12..16].copy_from_slice(&string_table_count.to_le_bytes());
+
+        let cfg = CfgBin::open(&buf)?;
+        let texts = cfg.extract_texts();
+        assert_eq!(texts.len(), 1);
+        assert_eq!(texts[0].value, "cdef");
+
+        Ok(())
+    }
+
+    #[test]
+    fn open_rejects_out_of_range_header_values() {
+        let string = &[VarType::String][..];
+        let rows = [("A", string, &[0][..])];
+        let buf = build_cfg(&rows, b"abc\0", &["A"]);
+        assert!(CfgBin::open(&buf).is_ok());
+
+        let patched = |pos: usize, value: i32| {
+            let mut bad = buf.clone();
+            bad[pos..pos + 4].copy_from_slice(&value.to_le_bytes());
+            bad
+        };
+        for (pos, value) in [(0, -1), (4, -16), (4, 8), (8, -1), (8, i32::MAX), (0, 1000)] {
+            assert!(
+                CfgBin::open(&patched(pos, value)).is_err(),
+                "header field at {} = {}",
+                pos,
+                value
+            );
+        }
+        assert!(CfgBin::open(&buf[..8]).is_err());
+
+        // Key table length pointing past the end of the file.
+        let key_table_offset =
+            round_up(read_i32(&buf, 4) as usize + read_i32(&buf, 8) as usize, 16);
+        assert!(CfgBin::open(&patched(key_table_offset, i32::MAX)).is_err());
+        assert!(CfgBin::open(&patched(key_table_offset + 8, -5)).is_err());
+    }
+
+    #[test]
+    fn entries_count_override_replaces_the_header_count() -> Result<()> {
+        let int = &[VarType::Int][..];
+        let rows = [("A", int, &[1][..]), ("B", int, &[2]), ("C", int, &[3])];
+        let mut buf = build_cfg(&rows, b"", &["A", "B", "C"]);
+        buf[0..4].copy_from_slice(&1i32.to_le_bytes());
+
+        let short = CfgBin::open(&buf)?;
+        assert_eq!(short.entries.len(), 1);
+        assert!(short.unparsed_entry_bytes >= 16);
+
+        let options = OpenOptions {
+            entries_count: Some(3),
+            ..OpenOptions::default()
+        };
+        let fixed = CfgBin::open_with_options(&buf, &options)?;
+        assert_eq!(fixed.entries.len(), 3);
+        assert_eq!(fixed.unparsed_entry_bytes, 0);
+
+        buf[0..4].copy_from_slice(&1000i32.to_le_bytes());
+        let err = CfgBin::open(&buf).err().unwrap();
+        assert!(err.to_string().contains("exceeds"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn end_rows_are_told_apart_from_data_rows_named_like_them() -> Result<()> {
+        let string = &[VarType::String][..];
+        let rows = [
+            ("LIST_BEGIN", &[][..], &[][..]),
+            ("LIST_SEND", string, &[0][..]),
+            ("LIST_LEGEND", &[], &[]),
+            ("LIST", string, &[2]),
+            ("LIST_END", &[], &[]),
+        ];
+        let keys = ["LIST_BEGIN", "LIST_SEND", "LIST_LEGEND", "LIST", "LIST_END"];
+        let data = build_cfg(&rows, b"a\0b\0", &keys);
+        let cfg = CfgBin::open(&data)?;
+
+        let list = &cfg.entries[0];
+        let names: Vec<&str> = list.children.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["LIST_SEND_0", "LIST_LEGEND_0", "LIST_0"]);
+        assert!(list.end_terminator);
+        assert_eq!(cfg.serialized_entry_count(), rows.len());
+        assert_eq!(cfg.save()?, data);
+        Ok(())
+    }
+
+    #[test]
+    fn start_block_saves_with_matching_end_terminator() -> Result<()> {
+        let rows = [
+            ("LIST_START", &[][..], &[][..]),
+            ("LIST", &[VarType::Int][..], &[1][..]),
+            ("LIST_END", &[], &[]),
+        ];
+        let keys = ["LIST_START", "LIST", "LIST_END"];
+        let data = build_cfg(&rows, b"", &keys);
+        let cfg = CfgBin::open(&data)?;
+        assert_eq!(cfg.entries.len(), 1);
+        assert!(cfg.entries[0].end_terminator);
+
+        let reopened = CfgBin::open(&cfg.save()?)?;
+        assert_eq!(reopened.key_order, keys);
+        assert_eq!(reopened.entries[0].children.len(), 1);
+        assert!(reopened.entries[0].end_terminator);
+
+        Ok(())
+    }
+
+    #[test]
+    fn end_names_rewrite_only_the_trailing_type_token() -> Result<()> {
+        assert_eq!(end_name_for("STARTUP_MENU_START"), "STARTUP_MENU_END");
+        assert_eq!(end_name_for("BEGGAR_LIST_START"), "BEGGAR_LIST_END");
+        assert_eq!(end_name_for("BEGIN_SHOP_BEG"), "BEGIN_SHOP_END");
+        assert_eq!(end_name_for("MENUBEG"), "MENUEND");
+        assert_eq!(derived_end_name("LIST_Begin"), None);
+
+        for base in ["STARTUP_MENU", "BEGGAR_LIST", "BEGIN_SHOP"] {
+            let begin = format!("{}_START", base);
+            let end = format!("{}_END", base);
+            let rows = [
+                (begin.as_str(), &[][..], &[][..]),
+                (base, &[VarType::Int][..], &[1][..]),
+                (end.as_str(), &[], &[]),
+            ];
+            let keys = [begin.as_str(), base, end.as_str()];
+            let data = build_cfg(&rows, b"", &keys);
+            let cfg = CfgBin::open(&data)?;
+            assert_eq!(cfg.entries.len(), 1, "{}", begin);
+            assert_eq!(cfg.save()?, data, "{}", begin);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn start_block_nested_in_begin_block_round_trips_byte_exact() -> Result<()> {
+        let rows = [
+            ("MENU_BEGIN", &[][..], &[][..]),
+            ("MENU_ITEM_START", &[VarType::String][..], &[0][..]),
+            ("MENU_ITEM", &[VarType::String][..], &[2][..]),
+            ("MENU_ITEM_END", &[], &[]),
+            ("MENU_END", &[], &[]),
+        ];
+        let keys = [
+            "MENU_BEGIN",
+            "MENU_ITEM_START",
+            "MENU_ITEM",
+            "MENU_ITEM_END",
+            "MENU_END",
+        ];
+        let data = build_cfg(&rows, b"a\0b\0", &keys);
+
+        let cfg = CfgBin::open(&data)?;
+        let item_block = &cfg.entries[0].children[0];
+        assert_eq!(item_block.get_name(), "MENU_ITEM_START");
+        assert!(item_block.end_terminator);
+        assert_eq!(CfgBin::roundtrip_mismatch(&data)?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn save_rejects_begin_names_without_derivable_end_name() {
+        for name in ["LIST_Start", "LIST_Begin"] {
+            let entry = Entry {
+                name: name.to_string(),
+                source_name: None,
+                variables: Vec::new(),
+                children: Vec::new(),
+                end_terminator: true,
+            };
+            let result = CfgBin::new(CfgBinEncoding::Utf8, 1, vec![entry]).save();
+            let message = result.err().map(|e| e.to_string()).unwrap_or_default();
+            assert!(message.contains(name), "{}: {}", name, message);
+        }
+    }
+
+    #[test]
+    fn round_up_handles_aligned_zero_and_unit_alignment() {
+        assert_eq!(round_up(0, 16), 0);
+        assert_eq!(round_up(16, 16), 16);
+        assert_eq!(round_up(17, 16), 32);
+        assert_eq!(round_up(5, 4), 8);
+        assert_eq!(round_up(7, 1), 7);
+    }
+
+    #[test]
+    fn write_alignment_pads_only_unaligned_buffers() {
+        let mut buf = Vec::new();
+        write_alignment(&mut buf, 16, 0xFF);
+        assert!(buf.is_empty());
+
+        let mut buf = vec![0u8; 16];
+        write_alignment(&mut buf, 16, 0xFF);
+        assert_eq!(buf.len(), 16);
+
+        let mut buf = vec![0u8; 3];
+        write_alignment(&mut buf, 16, 0xFF);
+        assert_eq!(buf.len(), 16);
+        assert!(buf[3..].iter().all(|&b| b == 0xFF));
+
+        let mut buf = vec![0u8; 3];
+        write_alignment(&mut buf, 1, 0xFF);
+        assert_eq!(buf, [0, 0, 0]);
+    }
+
+    #[test]
+    fn save_writes_distinct_strings_without_suffix_cache() {
+        let entry = Entry {
+            name: "TEST_0".to_string(),
+            source_name: None,
+            variables: vec![
+                Variable {
+                    var_type: VarType::String,
+                    value: VarValue::String(Some("abcdef".to_string())),
+                },
+                Variable {
+                    var_type: VarType::String,
+                    value: VarValue::String(Some("cdef".to_string())),
+                },
+            ],
+            children: Vec::new(),
+            end_terminator: false,
+        };
+
+        let cfg = CfgBin::new(CfgBinEncoding::Utf8, 1, vec![entry]);
+
+        let out = cfg.save().unwrap();
+
+        let entries_count = read_i32(&out, 0);
+        let string_table_offset = read_i32(&out, 4) as usize;
+        let string_table_length = read_i32(&out, 8) as usize;
+        let string_table_count = read_i32(&out, 12);
+
+        assert_eq!(entries_count, 1);
+        assert_eq!(string_table_count, 2);
+
+        let string_blob = &out[string_table_offset..string_table_offset + string_table_length];
+        assert_eq!(string_blob, b"abcdef\0cdef\0");
+
+        // Parse first entry's two string offsets.
+        let entries_blob = &out[0x10..string_table_offset];
+        let mut p = 0usize;
+        p += 4; // crc
+        let param_count = entries_blob[p] as usize;
+        p += 1;
+        assert_eq!(param_count, 2);
+
+        // Read type bytes (ceil(2/4)=1), then align to 4 since (1 + 1) % 4 != 0.
+        p = round_up(p + 1, 4);
+
+        let off0 = read_i32(entries_blob, p);
+        let off1 = read_i32(entries_blob, p + 4);
+        assert_eq!(off0, 0);
+        assert_eq!(off1, 7);
+    }
+
+    #[test]
+    fn no_dedup_strings_gives_each_reference_its_own_offset() -> Result<()> {
+        let rows = [
+            ("LIST_BEGIN", &[][..], &[][..]),
+            ("LIST", &[VarType::String, VarType::String][..], &[0, 4][..]),
+            ("LIST", &[VarType::String][..], &[0][..]),
+            ("LIST_END", &[], &[]),
+        ];
+        let keys = ["LIST_BEGIN", "LIST", "LIST_END"];
+        let cfg = CfgBin::open(&build_cfg(&rows, b"Yes\0No\0", &keys))?;
+        let string_offsets = |data: &[u8]| {
+            let first_row = 0x10 + 8; // after the LIST_BEGIN row
+            let second_row = first_row + 16;
+            [
+                read_i32(data, first_row + 8),
+                read_i32(data, first_row + 12),
+                read_i32(data, second_row + 8),
+            ]
+        };
+
+        let shared = cfg.save()?;
+        assert_eq!(string_offsets(&shared), [0, 4, 0]);
+        assert_eq!(read_i32(&shared, 12), 2);
+
+        let options = SaveOptions {
+            no_dedup_strings: true,
+            ..SaveOptions::default()
+        };
+        let separate = cfg.save_with_options(&options)?;
+        assert_eq!(string_offsets(&separate), [0, 4, 7]);
+        assert_eq!(read_i32(&separate, 12), 3);
+        let reopened = CfgBin::open(&separate)?;
+        let values: Vec<String> = reopened
+            .extract_texts()
+            .into_iter()
+            .map(|t| t.value)
+            .collect();
+        assert_eq!(values, ["Yes", "No", "Yes"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn save_with_reuses_prebuilt_string_tables_until_strings_change() -> Result<()> {
+        let rows = [
+            ("LIST_BEGIN", &[][..], &[][..]),
+            ("LIST", &[VarType::String, VarType::Int][..], &[0, 1][..]),
+            ("LIST", &[VarType::String, VarType::Int][..], &[0, 2][..]),
+            ("LIST_END", &[], &[]),
+        ];
+        let keys = ["LIST_BEGIN", "LIST", "LIST_END"];
+        let mut cfg = CfgBin::open(&build_cfg(&rows, b"Yes\0", &keys))?;
+        let options = SaveOptions::default();
+
+        for no_dedup_strings in [false, true] {
+            let tables = cfg.string_tables(no_dedup_strings);
+            cfg.entries[0].children[1].variables[1].value = VarValue::Int(7);
+            let options = SaveOptions {
+                no_dedup_strings,
+                ..SaveOptions::default()
+            };
+            assert_eq!(
+                cfg.save_with(&tables, &options)?,
+                cfg.save_with_options(&options)?
+            );
+        }
+
+        let tables = cfg.string_tables(false);
+        cfg.entries[0].children[0].variables[0].value = VarValue::String(Some("No".to_string()));
+        let result = cfg.save_with(&tables, &options);
+        assert!(result.is_err(), "stale string table must be rejected");
+
+        Ok(())
+    }
+
+    #[test]
+    fn save_with_key_table_reuses_it_until_entry_names_change() -> Result<()> {
+        let mut cfg = synthetic::build(2, 3);
+        let options = SaveOptions::default();
+        let keys = cfg.key_table(&options)?;
+
+        cfg.map_texts(|s| Some(format!("{}!", s)));
+        let tables = cfg.string_tables(false);
+        assert_eq!(
+            cfg.save_with_key_table(&tables, &keys, &options)?,
+            cfg.save_with_options(&options)?
+        );
+
+        let sorted = SaveOptions {
+            key_order: Some(vec!["TEXT_INFO_5".to_string()]),
+            ..SaveOptions::default()
+        };
+        assert!(cfg.save_with_key_table(&tables, &keys, &sorted).is_err());
+        cfg.endian = Endian::Big;
+        assert!(cfg.save_with_key_table(&tables, &keys, &options).is_err());
+        cfg.endian = Endian::Little;
+        cfg.padding.key_table = 0x00;
+        assert!(cfg.save_with_key_table(&tables, &keys, &options).is_err());
+        cfg.padding.key_table = Padding::default().key_table;
+
+        cfg.entries[0].children[0].name = "RENAMED".to_string();
+        cfg.entries[0].children[0].source_name = None;
+        let err = cfg
+            .save_with_key_table(&tables, &keys, &options)
+            .unwrap_err();
+        assert!(err.to_string().contains("Entry names changed"), "{}", err);
+
+        // Removing one of several TEXT_INFO rows leaves the set of names as it was.
+        let keys = cfg.key_table(&options)?;
+        cfg.entries[1].children.pop();
+        let tables = cfg.string_tables(false);
+        cfg.save_with_key_table(&tables, &keys, &options)?;
+        cfg.entries[0].children.remove(0);
+        let tables = cfg.string_tables(false);
+        assert!(cfg.save_with_key_table(&tables, &keys, &options).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn save_with_rejects_a_string_table_built_for_another_encoding() -> Result<()> {
+        let rows = [
+            ("LIST_BEGIN", &[][..], &[][..]),
+            ("LIST", &[VarType::String][..], &[0][..]),
+            ("LIST_END", &[], &[]),
+        ];
+        let strings = "\u{3042}\0".as_bytes();
+        let mut cfg = CfgBin::open(&build_cfg(
+            &rows,
+            strings,
+            &["LIST_BEGIN", "LIST", "LIST_END"],
+        ))?;
+        let tables = cfg.string_tables(false);
+        cfg.save_with(&tables, &SaveOptions::default())?;
+
+        // "\u{3042}" is 3 bytes in UTF-8 but 2 in Shift-JIS
+        cfg.encoding = CfgBinEncoding::ShiftJis;
+        let err = cfg.save_with(&tables, &SaveOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("records end at 3"), "{}", err);
+        Ok(())
+    }
+
+    #[test]
+    fn map_texts_counts_only_changed_values() -> Result<()> {
+        let mut cfg = CfgBin::open(&make_two_string_entry_cfg())?;
+
+        // "bb" maps to itself, so only "aa" counts as changed
+        let changed = cfg.map_texts(|s| Some(s.replace('a', "b")));
+        assert_eq!(changed, 1);
+        let values: Vec<String> = cfg.extract_texts().into_iter().map(|t| t.value).collect();
+        assert_eq!(values, ["bb", "bb"]);
+        assert_eq!(cfg.map_texts(|_| None), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn strip_empty_entries_keeps_blocks_and_fixes_counts() -> Result<()> {
+        let string = &[VarType::String][..];
+        let int = &[VarType::Int][..];
+        let rows = [
+            ("LIST_BEGIN", &[][..], &[][..]),
+            ("LIST", string, &[-1][..]),
+            ("LIST", string, &[2]),
+            ("LIST", int, &[0]),
+            ("LIST", string, &[0]),
+            ("LIST_END", &[], &[]),
+            ("EMPTY_BEGIN", &[], &[]),
+            ("EMPTY", string, &[-1]),
+            ("EMPTY_END", &[], &[]),
+        ];
+        let keys = [
+            "LIST_BEGIN",
+            "LIST",
+            "LIST_END",
+            "EMPTY_BEGIN",
+            "EMPTY",
+            "EMPTY_END",
+        ];
+        let data = build_cfg(&rows, b"a\0\0", &keys);
+        let mut cfg = CfgBin::open(&data)?;
+
+        // the null and "" strings go; the int row and "a" stay; EMPTY_BEGIN stays as a block
+        assert_eq!(cfg.strip_empty_entries(), 3);
+        assert_eq!(cfg.entries[0].children.len(), 2);
+        assert!(cfg.entries[1].children.is_empty());
+        assert_eq!(cfg.serialized_entry_count(), rows.len() - 3);
+
+        let mut reopened = CfgBin::open(&cfg.save()?)?;
+        assert!(reopened.structurally_eq(&cfg));
+        assert_eq!(reopened.extract_texts()[0].value, "a");
+        assert_eq!(reopened.strip_empty_entries(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn key_list_check_rejects_repeated_names() -> Result<()> {
+        let cfg = CfgBin::open(&make_two_string_entry_cfg())?;
+        let names = cfg.key_table_names(&SaveOptions::default());
+        check_key_list(&names, &cfg.encoding)?;
+
+        let mut repeated = names.clone();
+        repeated.push(names[0].clone());
+        let err = check_key_list(&repeated, &cfg.encoding).unwrap_err();
+        assert!(err.to_string().contains("twice"), "{}", err);
+        Ok(())
+    }
+
+    #[test]
+    fn key_list_check_rejects_colliding_crcs() {
+        // "plumless" and "buckeroo" are a known CRC32 collision.
+        let keys = ["plumless".to_string(), "buckeroo".to_string()];
+        let err = check_key_list(&keys, &CfgBinEncoding::Utf8).unwrap_err();
+        assert!(err.to_string().contains("0x4DDB0C25"), "{}", err);
+    }
+
+    #[test]
+    fn synthetic_file_reopens_to_the_same_tree() -> Result<()> {
+        let cfg = synthetic::build(3, 12);
+        let reopened = CfgBin::open(&cfg.save()?)?;
+        assert!(reopened.structurally_eq(&cfg));
+        assert_eq!(reopened.extract_texts().len(), 3 * 12 * 2);
+        Ok(())
+    }
+
+    #[test]
+    fn standard_texts_convert_to_nnk_addresses_and_patch_in_place() -> Result<()> {
+        let data = synthetic::build(2, 3).save()?;
+        let cfg = CfgBin::open(&data)?;
+        let by_address = nnk::extract(&data)?;
+        assert_eq!(
+            cfg.string_field_addresses,
+            by_address.keys().copied().collect::<Vec<_>>()
+        );
+
+        // Every odd slot holds the shared "Shared 0" record; only slot 1 changes.
+        let mut texts = cfg.extract_texts();
+        texts[0].value = "First".to_string();
+        texts[1].value = "Not shared".to_string();
+        let patched = nnk::patch(&data, &nnk::from_texts(&cfg, &data, &texts[..2])?)?;
+        let values =
+            |texts: Vec<TextEntry>| -> Vec<String> { texts.into_iter().map(|t| t.value).collect() };
+        let expected = values(texts.clone());
+        assert_eq!(values(CfgBin::open(&patched)?.extract_texts()), expected);
+        assert_eq!(
+            values(nnk::to_texts(&cfg, &nnk::extract(&patched)?)?),
+            expected
+        );
+
+        texts[0].index = 12;
+        let err = nnk::from_texts(&cfg, &data, &texts[..1]).unwrap_err();
+        assert!(
+            err.to_string().contains("Index 12 does not exist"),
+            "{}",
+            err
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn big_endian_file_parses_and_resaves_byte_exact() -> Result<()> {
+        let rows = [
+            ("LIST_BEGIN", &[][..], &[][..]),
+            (
+                "LIST",
+                &[VarType::String, VarType::Int][..],
+                &[0, 0x0102_0304][..],
+            ),
+            (
+                "LIST",
+                &[VarType::Float, VarType::String][..],
+                &[0x3FC0_0000, 2],
+            ),
+            ("LIST_END", &[], &[]),
+        ];
+        let keys = ["LIST_BEGIN", "LIST", "LIST_END"];
+        let data = build_cfg_with(Endian::Big, &rows, b"a\0b\0", &keys);
+        assert_eq!(data[..4], [0, 0, 0, 4]);
+
+        let options = OpenOptions {
+            endian: Endian::Big,
+            ..OpenOptions::default()
+        };
+        let cfg = CfgBin::open_with_options(&data, &options)?;
+        let little = CfgBin::open(&build_cfg(&rows, b"a\0b\0", &keys))?;
+        assert!(cfg.structurally_eq(&little));
+        let list = &cfg.entries[0].children;
+        assert_eq!(list[0].variables[1].value, VarValue::Int(0x0102_0304));
+        assert_eq!(list[1].variables[0].value, VarValue::Float(1.5));
+        assert_eq!(cfg.save()?, data);
+
+        // Read as little-endian, the header is nonsense and the parse fails
+        assert!(CfgBin::open(&data).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn string_offsets_group_references_by_string_table_record() -> Result<()> {
+        let string2 = &[VarType::String, VarType::String][..];
+        let rows = [
+            ("LIST_BEGIN", &[][..], &[][..]),
+            ("LIST", string2, &[0, 2][..]),
+            ("LIST", string2, &[2, -1]),
+            ("LIST_END", &[], &[]),
+        ];
+        let data = build_cfg(&rows, b"a\0b\0", &["LIST_BEGIN", "LIST", "LIST_END"]);
+        let offsets = CfgBin::string_offsets(&data, &OpenOptions::default())?;
+
+        let table = read_i32(&data, 4) as u32;
+        let values: Vec<(&str, u32)> = offsets
+            .iter()
+            .map(|o| (o.value.as_str(), o.string_offset - table))
+            .collect();
+        assert_eq!(values, [("a", 0), ("b", 2)]);
+        assert_eq!(offsets[1].reference_offsets.len(), 2);
+        for record in &offsets {
+            for &field in &record.reference_offsets {
+                assert_eq!(
+                    read_i32(&data, field as usize) as u32 + table,
+                    record.string_offset
+                );
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn compare_modes_agrees_on_consistent_file_and_skips_null_slots() -> Result<()> {
+        let rows = [
+            ("LIST_BEGIN", &[][..], &[][..]),
+            (
+                "LIST",
+                &[VarType::String, VarType::String][..],
+                &[0, -1][..],
+            ),
+            ("LIST", &[VarType::String][..], &[2][..]),
+            ("LIST_END", &[], &[]),
+        ];
+        let keys = ["LIST_BEGIN", "LIST", "LIST_END"];
+        let data = build_cfg(&rows, b"a\0b\0", &keys);
+        assert!(CfgBin::compare_modes(&data, &OpenOptions::default())?.is_empty());
+
+        // Read as length-prefixed, the standard values no longer match the nnk ones.
+        let options = OpenOptions {
+            string_format: StringFormat::LengthPrefixed,
+            ..OpenOptions::default()
         };
-        let key_table_data = tmp_cfg.encode_key_table(&[entry_name.to_string()]);
-        buf.extend_from_slice(&key_table_data);
-
-        // Footer (UTF-8)
-        buf.extend_from_slice(&[0x01, 0x74, 0x32, 0x62]);
-        buf.extend_from_slice(&(0x01FEu16).to_le_bytes());
-        buf.extend_from_slice(&(1u16).to_le_bytes());
-        buf.extend_from_slice(&(1u16).to_le_bytes());
-        write_alignment(&mut buf, 16, 0xFF);
+        let divergences = CfgBin::compare_modes(&data, &options)?;
+        assert_eq!(divergences[0].index, Some(0));
+        assert_eq!(divergences[0].standard.as_deref(), Some(""));
+        assert_eq!(divergences[0].nnk.as_deref(), Some("a"));
+        assert!(divergences
+            .iter()
+            .all(|d| d.standard.is_some() || d.nnk.is_some()));
+        assert_eq!(divergences[0].address, cfg_string_field(&data, 0));
+
+        // A forced encoding applies to both sides, so it is not reported as a divergence.
+        let data = build_cfg(
+            &[("LIST", &[VarType::String][..], &[0][..])],
+            b"\x83\x4C\0",
+            &["LIST"],
+        );
+        let options = OpenOptions {
+            encoding: Some(CfgBinEncoding::ShiftJis),
+            ..OpenOptions::default()
+        };
+        assert!(CfgBin::compare_modes(&data, &options)?.is_empty());
+        let texts = CfgBin::open_with_options(&data, &options)?.extract_texts();
+        assert_eq!(texts[0].value, "キ");
 
-        // Header
-        buf[0..4].copy_from_slice(&(1i32).to_le_bytes());
-        buf[4..8].copy_from_slice(&string_table_offset.to_le_bytes());
-        buf[8..12].copy_from_slice(&string_table_length.to_le_bytes());
-        buf[12..16].copy_from_slice(&string_table_count.to_le_bytes());
+        Ok(())
+    }
 
-        buf
+    fn cfg_string_field(data: &[u8], index: usize) -> u32 {
+        CfgBin::open(data).unwrap().string_field_addresses[index]
     }
 
     #[test]
-    fn open_resolves_suffix_offsets_in_string_table() -> Result<()> {
-        let encoding = CfgBinEncoding::Utf8;
-        let entry_name = "TEST";
-        let entry_crc = crc32::compute(&encode_string_bytes(entry_name, &encoding));
+    fn extract_all_round_trips_every_variable_type_through_json() -> Result<()> {
+        let mut cfg = synthetic::build(1, 2);
+        cfg.walk_mut(|entry, _| {
+            if entry.name == "TEXT_INFO_1" {
+                entry.variables[2].value = VarValue::Float(f32::from_bits(0x7FC0_0001));
+            }
+        });
+        let dumps = cfg.extract_all();
+        assert_eq!(dumps.len(), 3);
+        let json = serde_json::to_value(&dumps)?;
+        assert_eq!(
+            json[1]["variables"],
+            serde_json::json!([
+                { "type": "string", "value": "Text 0 of block 0" },
+                { "type": "int", "value": 0 },
+                { "type": "float", "value": 0.0 },
+                { "type": "string", "value": "Shared 0" }
+            ])
+        );
+        assert_eq!(json[2]["variables"][2]["value"], "NaN");
+
+        let edited = json.to_string().replacen(
+            r#"{"type":"int","value":0}"#,
+            r#"{"type":"int","value":3}"#,
+            1,
+        );
+        let edited = edited.replacen(
+            r#"{"type":"float","value":0.0}"#,
+            r#"{"type":"float","value":2}"#,
+            1,
+        );
+        let edited: Vec<EntryDump> = serde_json::from_str(&edited)?;
+        assert_eq!(cfg.apply_all(&edited)?, 3);
+        let back = CfgBin::open(&cfg.save()?)?;
+        let values = back.extract_all();
+        assert_eq!(values[1].variables[1].value, DumpValue::Int(3));
+        assert_eq!(
+            back.entries[0].children[0].variables[2].value,
+            VarValue::Float(2.0)
+        );
+        assert_eq!(
+            back.entries[0].children[1].variables[2].value,
+            VarValue::Float(f32::from_bits(0x7FC0_0001))
+        );
+
+        let mut bad = edited.clone();
+        bad[1].variables[1].value = DumpValue::Float(1.5);
+        let err = cfg.apply_all(&bad).unwrap_err();
+        assert!(
+            err.to_string().contains("1.5 is not a valid int"),
+            "{}",
+            err
+        );
+        bad = edited;
+        bad[2].entry = "OTHER_0".to_string();
+        assert!(cfg.apply_all(&bad).is_err());
+        assert_eq!(cfg.extract_all(), values);
+        Ok(())
+    }
 
-        // One entry with a single string value pointing to offset 2 inside "abcdef\0" -> "cdef".
-        let mut entry_bytes = Vec::new();
-        entry_bytes.extend_from_slice(&entry_crc.to_le_bytes());
-        entry_bytes.push(1); // param_count
-        entry_bytes.push(0); // types: 1x string
-        entry_bytes.extend_from_slice(&[0xFF, 0xFF]); // padding to 4-byte alignment
-        entry_bytes.extend_from_slice(&2i32.to_le_bytes()); // string offset
+    #[test]
+    fn rows_with_more_than_255_variables_are_refused() -> Result<()> {
+        let mut cfg = synthetic::build(1, 1);
+        let mut dumps = cfg.extract_all();
+        let int = dumps[1].variables[1].clone();
+        dumps[1].variables = vec![int; 256];
+        let err = cfg.apply_all(&dumps).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("Index 1 (TEXT_INFO_0) has 256 variables"),
+            "{}",
+            err
+        );
+        assert_eq!(cfg.entries[0].children[0].variables.len(), 4);
+
+        dumps[1].variables.pop();
+        cfg.apply_all(&dumps)?;
+        let back = CfgBin::open(&cfg.save()?)?;
+        assert_eq!(back.entries[0].children[0].variables.len(), 255);
+
+        let row = &mut cfg.entries[0].children[0];
+        row.variables.push(row.variables[0].clone());
+        let err = cfg.save().unwrap_err();
+        assert!(err.to_string().contains("has 256 variables"), "{}", err);
+        Ok(())
+    }
 
-        let mut buf = vec![0u8; 16]; // header placeholder
-        buf.extend_from_slice(&entry_bytes);
-        write_alignment(&mut buf, 16, 0xFF);
+    #[test]
+    fn verify_roundtrip_reports_the_first_differing_byte_with_context() -> Result<()> {
+        let data = synthetic::build(1, 2).save()?;
+        let cfg = CfgBin::open(&data)?;
+        assert_eq!(cfg.verify_roundtrip(&data)?, None);
+
+        let mut changed = data.clone();
+        changed[20] ^= 0xFF;
+        let diff = cfg.verify_roundtrip(&changed)?.unwrap();
+        assert_eq!((diff.offset, diff.context_start), (20, 12));
+        assert_eq!(diff.original, changed[12..28]);
+        assert_eq!(diff.saved, data[12..28]);
+
+        let diff = RoundTripDiff::between(&data[..30], &data).unwrap();
+        assert_eq!(
+            (diff.offset, diff.original_len, diff.saved_len),
+            (30, 30, data.len())
+        );
+        assert_eq!(diff.original, data[22..30]);
+        assert_eq!(diff.saved, data[22..38]);
+        Ok(())
+    }
 
-        let string_table_offset = buf.len() as i32;
-        let strings_data = b"abcdef\0".to_vec();
-        let string_table_length = strings_data.len() as i32;
-        let string_table_count = 1i32;
+    #[test]
+    fn texts_by_path_round_trip_and_reject_unknown_paths() -> Result<()> {
+        let mut cfg = CfgBin::open(&make_two_string_entry_cfg())?;
+        let texts = cfg.extract_texts_by_path()?;
+        let flat = cfg.extract_texts();
+        assert_eq!(texts.len(), flat.len());
+        for ((path, value), te) in texts.iter().zip(&flat) {
+            assert!(
+                path.ends_with(&format!("/{}", te.variable_index)),
+                "{}",
+                path
+            );
+            assert_eq!(value, &te.value);
+        }
 
-        buf.extend_from_slice(&strings_data);
-        write_alignment(&mut buf, 16, 0xFF);
+        let (path, _) = &texts[1];
+        let updates = BTreeMap::from([(path.clone(), "changed".to_string())]);
+        assert_eq!(cfg.update_texts_by_path(&updates)?, 1);
+        assert_eq!(cfg.extract_texts()[1].value, "changed");
+        assert_eq!(cfg.extract_texts()[0].value, flat[0].value);
 
-        // Key table: only needs the entry name for CRC resolution.
-        let tmp_cfg = CfgBin {
-            encoding,
-            footer_encoding: 1,
-            entries: Vec::new(),
-        };
-        let key_table_data = tmp_cfg.encode_key_table(&[entry_name.to_string()]);
-        buf.extend_from_slice(&key_table_data);
+        let unknown = BTreeMap::from([("NO_SUCH_ENTRY_0/0".to_string(), "x".to_string())]);
+        assert!(cfg.update_texts_by_path(&unknown).is_err());
 
-        // Footer (UTF-8).
-        buf.extend_from_slice(&[0x01, 0x74, 0x32, 0x62]);
-        buf.extend_from_slice(&(0x01FEu16).to_le_bytes());
-        buf.extend_from_slice(&(1u16).to_le_bytes());
-        buf.extend_from_slice(&(1u16).to_le_bytes());
-        write_alignment(&mut buf, 16, 0xFF);
+        Ok(())
+    }
 
-        // Header
-        buf[0..4].copy_from_slice(&(1i32).to_le_bytes());
-        buf[4..8].copy_from_slice(&string_table_offset.to_le_bytes());
-        buf[8..12].copy_from_slice(&string_table_length.to_le_bytes());
-        buf[12..16].copy_from_slice(&string_table_count.to_le_bytes());
+    #[test]
+    fn top_level_stats_reports_children_depth_and_count() {
+        let leaf = |name: &str| Entry {
+            name: name.to_string(),
+            source_name: None,
+            variables: Vec::new(),
+            children: Vec::new(),
+            end_terminator: false,
+        };
+        let inner = Entry {
+            name: "INNER_BEGIN_0".to_string(),
+            source_name: None,
+            variables: Vec::new(),
+            children: vec![leaf("ITEM_0"), leaf("ITEM_1")],
+            end_terminator: true,
+        };
+        let outer = Entry {
+            name: "OUTER_BEGIN_0".to_string(),
+            source_name: None,
+            variables: Vec::new(),
+            children: vec![inner, leaf("ITEM_2")],
+            end_terminator: true,
+        };
+        let cfg = CfgBin::new(CfgBinEncoding::Utf8, 1, vec![outer, leaf("SINGLE_0")]);
+
+        let stats = cfg.top_level_stats();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].name, "OUTER_BEGIN_0");
+        assert_eq!(stats[0].child_count, 2);
+        assert_eq!(stats[0].max_depth, 3);
+        // OUTER + END, INNER + END, ITEM_0, ITEM_1, ITEM_2
+        assert_eq!(stats[0].total_count, 7);
+        assert_eq!(stats[1].child_count, 0);
+        assert_eq!(stats[1].max_depth, 1);
+        assert_eq!(stats[1].total_count, 1);
+    }
 
-        let cfg = CfgBin::open(&buf)?;
-        let texts = cfg.extract_texts();
-        assert_eq!(texts.len(), 1);
-        assert_eq!(texts[0].value, "cdef");
+    #[test]
+    fn entry_counts_separate_nodes_from_end_terminators() -> Result<()> {
+        let rows = [
+            ("LIST_BEGIN", &[][..], &[][..]),
+            ("ITEM_BEGIN", &[], &[]),
+            ("ITEM", &[VarType::Int][..], &[1][..]),
+            ("ITEM", &[VarType::Int][..], &[2][..]),
+            ("ITEM_END", &[], &[]),
+            ("LIST_END", &[], &[]),
+        ];
+        let keys = ["LIST_BEGIN", "ITEM_BEGIN", "ITEM", "ITEM_END", "LIST_END"];
+        let data = build_cfg(&rows, b"", &keys);
+        let cfg = CfgBin::open(&data)?;
+
+        // LIST_BEGIN > ITEM_BEGIN > 2 x ITEM, plus the two END rows
+        assert_eq!(cfg.entries[0].node_count(), 4);
+        assert_eq!(cfg.entries[0].children[0].node_count(), 3);
+        assert_eq!(cfg.node_count(), 4);
+        assert_eq!(cfg.end_terminator_count(), 2);
+        assert_eq!(cfg.serialized_entry_count(), 6);
+        assert_eq!(read_i32(&cfg.save()?, 0), 6);
+        assert_eq!(read_i32(&data, 0), 6);
 
         Ok(())
     }
 
     #[test]
-    fn save_writes_distinct_strings_without_suffix_cache() {
-        let entry = Entry {
-            name: "TEST_0".to_string(),
-            variables: vec![
-                Variable {
-                    var_type: VarType::String,
-                    value: VarValue::String(Some("abcdef".to_string())),
-                },
-                Variable {
-                    var_type: VarType::String,
-                    value: VarValue::String(Some("cdef".to_string())),
-                },
-            ],
+    fn walk_visits_entries_in_pre_order_with_depth() {
+        let leaf = |name: &str| Entry {
+            name: name.to_string(),
+            source_name: None,
+            variables: Vec::new(),
             children: Vec::new(),
             end_terminator: false,
         };
+        let block = |name: &str, children: Vec<Entry>| Entry {
+            children,
+            end_terminator: true,
+            ..leaf(name)
+        };
+        let mut cfg = CfgBin::new(
+            CfgBinEncoding::Utf8,
+            1,
+            vec![
+                block(
+                    "A_BEGIN_0",
+                    vec![leaf("B_0"), block("C_BEGIN_0", vec![leaf("D_0")])],
+                ),
+                leaf("E_0"),
+            ],
+        );
+
+        let mut visits = Vec::new();
+        cfg.walk(|entry, depth| visits.push((entry.name.clone(), depth)));
+        let expected = [
+            ("A_BEGIN_0", 0),
+            ("B_0", 1),
+            ("C_BEGIN_0", 1),
+            ("D_0", 2),
+            ("E_0", 0),
+        ];
+        let expected: Vec<(String, usize)> =
+            expected.iter().map(|(n, d)| (n.to_string(), *d)).collect();
+        assert_eq!(visits, expected);
+
+        // Children added by `f` are walked too
+        let mut names = Vec::new();
+        cfg.walk_mut(|entry, _| {
+            names.push(entry.name.clone());
+            if entry.name == "E_0" {
+                entry.children.push(leaf("F_0"));
+            }
+        });
+        assert_eq!(names.last().map(String::as_str), Some("F_0"));
+    }
 
-        let cfg = CfgBin {
-            encoding: CfgBinEncoding::Utf8,
-            footer_encoding: 1,
-            entries: vec![entry],
+    #[test]
+    fn extract_text_tree_nests_texts_with_flat_indices() {
+        let string_var = |s: &str| Variable {
+            var_type: VarType::String,
+            value: VarValue::String(Some(s.to_string())),
+        };
+        let child = Entry {
+            name: "LINE_0".to_string(),
+            source_name: None,
+            variables: vec![string_var("b"), string_var("c")],
+            children: Vec::new(),
+            end_terminator: false,
         };
+        let block = Entry {
+            name: "TALK_BEGIN_0".to_string(),
+            source_name: None,
+            variables: vec![string_var("a")],
+            children: vec![child],
+            end_terminator: true,
+        };
+        let cfg = CfgBin::new(CfgBinEncoding::Utf8, 1, vec![block]);
+
+        let tree = cfg.extract_text_tree();
+        assert_eq!(tree.len(), 1);
+        assert_eq!(tree[0].name, "TALK_BEGIN_0");
+        assert_eq!(tree[0].texts[0].value, "a");
+        let line = &tree[0].children[0];
+        assert_eq!(line.name, "LINE_0");
+        assert_eq!(line.texts.len(), 2);
+
+        let flat = cfg.extract_texts();
+        assert_eq!(line.texts[1].index, flat[2].index);
+        assert_eq!(line.texts[1].value, flat[2].value);
+        assert_eq!(line.texts[1].variable_index, flat[2].variable_index);
+    }
 
-        let out = cfg.save();
+    fn assert_ptree_block(entry: &Entry, name: &str, values: &[&str]) {
+        assert_eq!(entry.name, name);
+        assert!(entry.end_terminator);
+        let child_values: Vec<String> = entry
+            .children
+            .iter()
+            .map(|c| match &c.variables[0].value {
+                VarValue::String(Some(s)) => s.clone(),
+                other => panic!("Expected string, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(child_values, values);
+    }
 
-        let entries_count = read_i32(&out, 0);
-        let string_table_offset = read_i32(&out, 4) as usize;
-        let string_table_length = read_i32(&out, 8) as usize;
-        let string_table_count = read_i32(&out, 12);
+    #[test]
+    fn ptree_block_parses_nests_and_resaves() -> Result<()> {
+        let int = &[VarType::Int][..];
+        let string = &[VarType::String][..];
+        let rows = [
+            ("PTREE", int, &[1][..]),
+            ("ITEM", string, &[0]),
+            ("ITEM", string, &[3]),
+            ("_PTREE", &[][..], &[]),
+        ];
+        let buf = build_cfg(&rows, b"aa\0bb\0", &["PTREE", "ITEM", "_PTREE"]);
 
-        assert_eq!(entries_count, 1);
-        assert_eq!(string_table_count, 2);
+        let cfg = CfgBin::open(&buf)?;
+        assert_eq!(cfg.entries.len(), 1);
+        assert_ptree_block(&cfg.entries[0], "PTREE_0", &["aa", "bb"]);
 
-        let string_blob = &out[string_table_offset..string_table_offset + string_table_length];
-        assert_eq!(string_blob, b"abcdef\0cdef\0");
+        let saved = cfg.save()?;
+        assert_eq!(read_i32(&saved, 0), 4);
+        let reopened = CfgBin::open(&saved)?;
+        assert_eq!(reopened.entries.len(), 1);
+        assert_ptree_block(&reopened.entries[0], "PTREE_0", &["aa", "bb"]);
+        assert!(reopened.key_order.contains(&"_PTREE".to_string()));
 
-        // Parse first entry's two string offsets.
-        let entries_blob = &out[0x10..string_table_offset];
-        let mut p = 0usize;
-        p += 4; // crc
-        let param_count = entries_blob[p] as usize;
-        p += 1;
-        assert_eq!(param_count, 2);
+        Ok(())
+    }
 
-        // Read type bytes (ceil(2/4)=1), then align to 4.
-        p += 1;
-        if (1 + 1) % 4 != 0 {
-            let rem = p % 4;
-            if rem != 0 {
-                p += 4 - rem;
-            }
+    #[test]
+    fn sequential_ptree_blocks_inside_begin_block_stay_siblings() -> Result<()> {
+        let int = &[VarType::Int][..];
+        let string = &[VarType::String][..];
+        let rows = [
+            ("LIST_BEGIN", int, &[2][..]),
+            ("PTREE", int, &[1]),
+            ("ITEM", string, &[0]),
+            ("_PTREE", &[][..], &[]),
+            ("PTREE", int, &[1]),
+            ("ITEM", string, &[3]),
+            ("_PTREE", &[][..], &[]),
+            ("LIST_END", &[][..], &[]),
+        ];
+        let keys = ["LIST_BEGIN", "PTREE", "ITEM", "_PTREE", "LIST_END"];
+        let buf = build_cfg(&rows, b"aa\0bb\0", &keys);
+
+        for cfg in [
+            CfgBin::open(&buf)?,
+            CfgBin::open(&CfgBin::open(&buf)?.save()?)?,
+        ] {
+            assert_eq!(cfg.entries.len(), 1);
+            let list = &cfg.entries[0];
+            assert_eq!(list.name, "LIST_BEGIN_0");
+            assert!(list.end_terminator);
+            assert_eq!(list.children.len(), 2);
+            assert_ptree_block(&list.children[0], "PTREE_0", &["aa"]);
+            assert_ptree_block(&list.children[1], "PTREE_1", &["bb"]);
         }
 
-        let off0 = read_i32(entries_blob, p);
-        let off1 = read_i32(entries_blob, p + 4);
-        assert_eq!(off0, 0);
-        assert_eq!(off1, 7);
+        Ok(())
+    }
+
+    #[test]
+    fn nested_ptree_closes_innermost_block_first() -> Result<()> {
+        let int = &[VarType::Int][..];
+        let string = &[VarType::String][..];
+        let rows = [
+            ("PTREE", int, &[1][..]),
+            ("ITEM", string, &[0]),
+            ("PTREE", int, &[2]),
+            ("ITEM", string, &[3]),
+            ("_PTREE", &[][..], &[]),
+            ("ITEM", string, &[6]),
+            ("_PTREE", &[][..], &[]),
+        ];
+        let buf = build_cfg(&rows, b"aa\0bb\0cc\0", &["PTREE", "ITEM", "_PTREE"]);
+
+        for cfg in [
+            CfgBin::open(&buf)?,
+            CfgBin::open(&CfgBin::open(&buf)?.save()?)?,
+        ] {
+            assert_eq!(cfg.entries.len(), 1);
+            let outer = &cfg.entries[0];
+            assert_eq!(outer.name, "PTREE_0");
+            assert!(outer.end_terminator);
+            let names: Vec<&str> = outer.children.iter().map(|c| c.name.as_str()).collect();
+            assert_eq!(names, ["ITEM_0", "PTREE_1", "ITEM_2"]);
+            assert_ptree_block(&outer.children[1], "PTREE_1", &["bb"]);
+        }
+
+        Ok(())
     }
 
     #[test]
@@ -1250,11 +5258,12 @@ mod tests {
 
     #[test]
     fn update_texts_keeps_empty_string_as_string() {
-        let mut cfg = CfgBin {
-            encoding: CfgBinEncoding::Utf8,
-            footer_encoding: 1,
-            entries: vec![Entry {
+        let mut cfg = CfgBin::new(
+            CfgBinEncoding::Utf8,
+            1,
+            vec![Entry {
                 name: "TEST_0".to_string(),
+                source_name: None,
                 variables: vec![Variable {
                     var_type: VarType::String,
                     value: VarValue::String(Some("x".to_string())),
@@ -1262,13 +5271,14 @@ mod tests {
                 children: Vec::new(),
                 end_terminator: false,
             }],
-        };
+        );
 
         let texts = vec![TextEntry {
             index: 0,
             entry: "TEST".to_string(),
             variable_index: 0,
             value: String::new(),
+            hash: None,
         }];
         cfg.update_texts(&texts);
 
@@ -1278,6 +5288,585 @@ mod tests {
         }
     }
 
+    #[test]
+    fn extract_texts_with_hash_hashes_source_values() {
+        let cfg = CfgBin::new(
+            CfgBinEncoding::Utf8,
+            1,
+            vec![Entry {
+                name: "TEST_0".to_string(),
+                source_name: None,
+                variables: vec![Variable {
+                    var_type: VarType::String,
+                    value: VarValue::String(Some("abc".to_string())),
+                }],
+                children: Vec::new(),
+                end_terminator: false,
+            }],
+        );
+
+        assert_eq!(cfg.extract_texts()[0].hash, None);
+        let texts = cfg.extract_texts_with_hash();
+        assert_eq!(texts[0].hash.as_deref(), Some("352441c2"));
+        let json = serde_json::to_string(&cfg.extract_texts()).unwrap();
+        assert!(!json.contains("hash"));
+    }
+
+    #[test]
+    fn text_indices_under_covers_matching_entries_and_descendants() -> Result<()> {
+        let string = &[VarType::String][..];
+        let rows = [
+            ("HEAD", string, &[0][..]),
+            ("MENU_BEGIN", string, &[0]),
+            ("MENU", string, &[0]),
+            ("MENU", string, &[0]),
+            ("MENU_END", &[], &[]),
+            ("TAIL", string, &[0]),
+        ];
+        let keys = ["HEAD", "MENU_BEGIN", "MENU", "MENU_END", "TAIL"];
+        let cfg = CfgBin::open(&build_cfg(&rows, b"x\0", &keys))?;
+
+        assert_eq!(cfg.text_indices_under("MENU_BEGIN"), Some(vec![1, 2, 3]));
+        assert_eq!(cfg.text_indices_under("MENU"), Some(vec![2, 3]));
+        assert_eq!(cfg.text_indices_under("TAIL"), Some(vec![4]));
+        assert_eq!(cfg.text_indices_under("NOPE"), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn values_of_one_type_extract_and_update_by_their_own_indices() -> Result<()> {
+        let stats = &[VarType::String, VarType::Float, VarType::Int][..];
+        let rows = [
+            ("ITEM_BEGIN", &[VarType::Float][..], &[0x3F80_0000][..]),
+            ("ITEM", stats, &[0, 0x3FC0_0000, 7]),
+            ("ITEM", stats, &[0, 0x7FC0_0001, -1]),
+            ("ITEM_END", &[], &[]),
+        ];
+        let keys = ["ITEM_BEGIN", "ITEM", "ITEM_END"];
+        let mut cfg = CfgBin::open(&build_cfg(&rows, b"a\0", &keys))?;
+
+        let floats = cfg.extract_values(VarType::Float, false, false);
+        let values: Vec<&str> = floats.iter().map(|t| t.value.as_str()).collect();
+        assert_eq!(values, ["1", "1.5", "NaN"]);
+        assert_eq!(
+            (floats[1].entry.as_str(), floats[1].variable_index),
+            ("ITEM", 1)
+        );
+        assert_eq!(cfg.extract_values(VarType::Float, false, true).len(), 2);
+        assert_eq!(
+            cfg.value_indices_under(VarType::Float, "ITEM"),
+            Some(vec![1, 2])
+        );
+        assert_eq!(
+            cfg.extract_values(VarType::Int, false, false)[1].value,
+            "-1"
+        );
+        assert_eq!(
+            cfg.extract_values(VarType::String, true, false)[0].hash,
+            cfg.extract_texts_with_hash()[0].hash
+        );
+
+        let mut edited = floats.clone();
+        edited[1].value = "2.25".to_string();
+        assert!(cfg.update_values(VarType::Float, &edited)?.is_empty());
+        let item = &cfg.entries[0].children[0];
+        assert_eq!(item.variables[1].value, VarValue::Float(2.25));
+        // The NaN payload survives because its text was not changed.
+        let nan = &cfg.entries[0].children[1].variables[1].value;
+        assert_eq!(*nan, VarValue::Float(f32::from_bits(0x7FC0_0001)));
+
+        edited[0].value = "one".to_string();
+        let err = cfg.update_values(VarType::Float, &edited).unwrap_err();
+        assert!(err.to_string().contains("not a valid float"), "{}", err);
+        assert_eq!(cfg.entries[0].variables[0].value, VarValue::Float(1.0));
+        Ok(())
+    }
+
+    #[test]
+    fn find_text_matches_exact_or_substring_with_global_indices() -> Result<()> {
+        let string = &[VarType::String, VarType::String][..];
+        let rows = [("A", string, &[0, 6][..]), ("B", string, &[0, 12])];
+        let cfg = CfgBin::open(&build_cfg(
+            &rows,
+            b"Hello\0World\0Hello world\0",
+            &["A", "B"],
+        ))?;
+
+        let exact = cfg.find_text("Hello");
+        let indices: Vec<usize> = exact.iter().map(|t| t.index).collect();
+        assert_eq!(indices, [0, 2]);
+        assert_eq!(exact[1].entry, "B");
+
+        let partial = cfg.find_text_containing("orld");
+        let indices: Vec<usize> = partial.iter().map(|t| t.index).collect();
+        assert_eq!(indices, [1, 3]);
+        assert!(cfg.find_text("hello").is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn auto_encoding_switches_when_the_footer_names_the_wrong_encoding() -> Result<()> {
+        let rows = [
+            ("LIST_BEGIN", &[][..], &[][..]),
+            ("LIST", &[VarType::String][..], &[0][..]),
+            ("LIST", &[VarType::String][..], &[13][..]),
+            ("LIST_END", &[], &[]),
+        ];
+        let strings = "はじめる\0つづき\0".as_bytes();
+        let mut data = build_cfg(&rows, strings, &["LIST_BEGIN", "LIST", "LIST_END"]);
+        let magic = data.len() - 16;
+        data[magic + 6..magic + 8].copy_from_slice(&0u16.to_le_bytes());
+
+        let scores = CfgBin::encoding_scores(&data, StringFormat::NullTerminated, Endian::Little)?;
+        assert_eq!(scores.strings, 2);
+        assert_eq!(scores.utf8_anomalies, 0);
+        assert_eq!(
+            scores.better_alternative(CfgBinEncoding::ShiftJis),
+            Some(CfgBinEncoding::Utf8)
+        );
+        assert_eq!(scores.better_alternative(CfgBinEncoding::Utf8), None);
+
+        assert_eq!(CfgBin::open(&data)?.encoding, CfgBinEncoding::ShiftJis);
+        let options = OpenOptions {
+            auto_encoding: true,
+            ..OpenOptions::default()
+        };
+        let cfg = CfgBin::open_with_options(&data, &options)?;
+        assert_eq!(cfg.encoding, CfgBinEncoding::Utf8);
+        assert_eq!(cfg.extract_texts()[1].value, "つづき");
+
+        assert!(!looks_misdecoded(
+            "line\nbreak\tand 全角",
+            CfgBinEncoding::ShiftJis
+        ));
+        assert!(looks_misdecoded("ｱｲ", CfgBinEncoding::ShiftJis));
+        assert!(!looks_misdecoded("ｱｲ", CfgBinEncoding::Utf8));
+        assert!(looks_misdecoded("a\u{FFFD}b", CfgBinEncoding::Utf8));
+        Ok(())
+    }
+
+    #[test]
+    fn footer_encoding_is_found_regardless_of_footer_padding() -> Result<()> {
+        let rows = [
+            ("LIST_BEGIN", &[][..], &[][..]),
+            ("LIST", &[VarType::String][..], &[0][..]),
+            ("LIST_END", &[], &[]),
+        ];
+        let mut data = build_cfg(&rows, b"a\0", &["LIST_BEGIN", "LIST", "LIST_END"]);
+        let magic = data.len() - 16;
+        assert_eq!(data[magic..magic + 4], FOOTER_MAGIC);
+        data[magic + 6..magic + 8].copy_from_slice(&0u16.to_le_bytes());
+        assert_eq!(detect_encoding(&data), (CfgBinEncoding::ShiftJis, 0));
+
+        let unpadded = data[..magic + 10].to_vec();
+        let mut extra = data.clone();
+        extra.extend_from_slice(&[0xFF; 16]);
+        for variant in [&unpadded, &extra] {
+            assert_eq!(detect_encoding(variant), (CfgBinEncoding::ShiftJis, 0));
+            assert_eq!(CfgBin::open(variant)?.encoding, CfgBinEncoding::ShiftJis);
+        }
+
+        // No magic: the fixed offset from the end of the file is used.
+        let mut no_magic = data.clone();
+        no_magic[magic] = 0;
+        assert_eq!(detect_encoding(&no_magic).1, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn non_default_footer_fields_survive_a_round_trip() -> Result<()> {
+        let rows = [
+            ("LIST_BEGIN", &[][..], &[][..]),
+            ("LIST", &[VarType::String][..], &[0][..]),
+            ("LIST_END", &[], &[]),
+        ];
+        let mut data = build_cfg(&rows, b"a\0", &["LIST_BEGIN", "LIST", "LIST_END"]);
+        let magic = data.len() - 16;
+        data[magic + 4..magic + 6].copy_from_slice(&0x0200u16.to_le_bytes());
+        data[magic + 8..magic + 10].copy_from_slice(&3u16.to_le_bytes());
+
+        let cfg = CfgBin::open(&data)?;
+        assert_eq!((cfg.footer_unk1, cfg.footer_unk2), (0x0200, 3));
+        assert_eq!(cfg.save()?, data);
+
+        let built = CfgBin::new(CfgBinEncoding::Utf8, 1, cfg.entries.clone()).save()?;
+        assert_eq!(built[magic + 4..magic + 6], FOOTER_UNK1.to_le_bytes());
+        Ok(())
+    }
+
+    #[test]
+    fn text_patches_record_edits_and_apply_only_to_matching_slots() -> Result<()> {
+        let rows = [
+            ("LIST_BEGIN", &[][..], &[][..]),
+            ("LIST", &[VarType::String][..], &[0][..]),
+            ("LIST", &[VarType::String][..], &[2][..]),
+            ("LIST_END", &[], &[]),
+        ];
+        let data = build_cfg(&rows, b"a\0b\0", &["LIST_BEGIN", "LIST", "LIST_END"]);
+        let cfg = CfgBin::open(&data)?;
+        let mut edited = cfg.extract_texts();
+        edited[1].value = "B".into();
+        let patches = cfg.diff_texts(&edited);
+        assert_eq!(patches.len(), 1);
+        assert_eq!(
+            (patches[0].old.as_str(), patches[0].new.as_str()),
+            ("b", "B")
+        );
+
+        let mut target = CfgBin::open(&data)?;
+        assert_eq!(target.apply_text_patches(&patches)?, 1);
+        let values: Vec<String> = target
+            .extract_texts()
+            .into_iter()
+            .map(|t| t.value)
+            .collect();
+        assert_eq!(values, ["a", "B"]);
+        // Applying twice is harmless; a slot holding neither value is refused.
+        target.apply_text_patches(&patches)?;
+        let stale = [TextPatch {
+            old: "x".into(),
+            ..patches[0].clone()
+        }];
+        assert!(CfgBin::open(&data)?.apply_text_patches(&stale).is_err());
+        let moved = [TextPatch {
+            index: 0,
+            ..patches[0].clone()
+        }];
+        assert!(CfgBin::open(&data)?.apply_text_patches(&moved).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn structural_equality_compares_floats_by_bits() -> Result<()> {
+        let float = |f: f32| VarValue::Float(f);
+        assert_eq!(float(f32::NAN), float(f32::NAN));
+        assert_ne!(
+            float(f32::NAN),
+            float(f32::from_bits(f32::NAN.to_bits() + 1))
+        );
+        assert_ne!(float(0.0), float(-0.0));
+        assert_ne!(VarValue::Int(1), VarValue::Unknown(1));
+
+        let rows = [
+            ("LIST_BEGIN", &[][..], &[][..]),
+            (
+                "LIST",
+                &[VarType::String, VarType::Float][..],
+                &[0, 0x7FC0_0000][..],
+            ),
+            ("LIST_END", &[], &[]),
+        ];
+        let data = build_cfg(&rows, b"a\0", &["LIST_BEGIN", "LIST", "LIST_END"]);
+        let cfg = CfgBin::open(&data)?;
+        let reopened = CfgBin::open(&cfg.save()?)?;
+        assert!(cfg.structurally_eq(&reopened));
+
+        let mut changed = CfgBin::open(&data)?;
+        changed.entries[0].children[0].variables[1].value = float(f32::from_bits(1));
+        assert!(!cfg.structurally_eq(&changed));
+        let mut unterminated = CfgBin::open(&data)?;
+        unterminated.entries[0].end_terminator = false;
+        assert!(!cfg.structurally_eq(&unterminated));
+        Ok(())
+    }
+
+    #[test]
+    fn crc_collisions_group_names_with_the_same_hash() {
+        // "plumless" and "buckeroo" are a known CRC32 collision.
+        let names = ["plumless", "ITEM", "buckeroo", "plumless", "TEXT"];
+        let collisions = crc_collisions(names, CfgBinEncoding::Utf8);
+        assert_eq!(
+            collisions,
+            [CrcCollision {
+                crc: 0x4DDB_0C25,
+                names: vec!["plumless".to_string(), "buckeroo".to_string()],
+            }]
+        );
+        assert!(crc_collisions(["ITEM", "TEXT"], CfgBinEncoding::ShiftJis).is_empty());
+    }
+
+    #[test]
+    fn var_values_serialize_with_exact_float_bits() -> Result<()> {
+        let floats = [
+            0.1,
+            1.0 / 3.0,
+            f32::from_bits(1), // smallest subnormal
+            f32::MAX,
+            -0.0,
+            f32::INFINITY,
+            f32::NEG_INFINITY,
+            f32::from_bits(0x7FC0_0001), // NaN with a payload
+        ];
+        let mut values: Vec<VarValue> = floats.iter().map(|&f| VarValue::Float(f)).collect();
+        values.extend([
+            VarValue::String(Some("a".to_string())),
+            VarValue::String(None),
+            VarValue::Int(-7),
+            VarValue::Unknown(9),
+        ]);
+        let json = serde_json::to_string(&values)?;
+        assert!(
+            json.starts_with(r#"[{"Float":{"value":0.1,"bits":"0x3DCCCCCD"}}"#),
+            "{}",
+            json
+        );
+        let read: Vec<VarValue> = serde_json::from_str(&json)?;
+        assert_eq!(read, values);
+
+        // Re-encoding the read values writes the same bytes.
+        let entry = |values: &[VarValue]| Entry {
+            name: "ROW_0".to_string(),
+            source_name: None,
+            variables: values
+                .iter()
+                .map(|v| Variable {
+                    var_type: VarType::Float,
+                    value: v.clone(),
+                })
+                .collect(),
+            children: Vec::new(),
+            end_terminator: false,
+        };
+        let save =
+            |values: &[VarValue]| CfgBin::new(CfgBinEncoding::Utf8, 1, vec![entry(values)]).save();
+        assert_eq!(save(&read[..floats.len()])?, save(&values[..floats.len()])?);
+
+        let decimal_only: VarValue = serde_json::from_str(r#"{"Float":{"value":0.1}}"#)?;
+        assert_eq!(decimal_only, VarValue::Float(0.1));
+        assert!(serde_json::from_str::<VarValue>(r#"{"Float":{}}"#).is_err());
+        assert!(serde_json::from_str::<VarValue>(r#"{"Float":{"bits":"0xZZ"}}"#).is_err());
+
+        // An edited value wins over the stale bits next to it.
+        let edited: VarValue =
+            serde_json::from_str(r#"{"Float":{"value":2.5,"bits":"0x3DCCCCCD"}}"#)?;
+        assert_eq!(edited, VarValue::Float(2.5));
+        let nan: VarValue = serde_json::from_str(r#"{"Float":{"value":1.0,"bits":"0x7FC00001"}}"#)?;
+        assert_eq!(nan, VarValue::Float(1.0));
+        let negative_zero: VarValue =
+            serde_json::from_str(r#"{"Float":{"value":0.0,"bits":"0x80000000"}}"#)?;
+        assert_eq!(negative_zero, VarValue::Float(-0.0));
+        Ok(())
+    }
+
+    #[test]
+    fn keys_ending_in_a_number_keep_it_through_open_and_save() -> Result<()> {
+        let string = &[VarType::String][..];
+        let rows = [
+            ("LIST_BEGIN", &[][..], &[][..]),
+            ("LIST_SLOT_3", string, &[0][..]),
+            ("LIST_SLOT", string, &[2][..]),
+            ("LIST_SLOT", string, &[2][..]),
+            ("LIST_SLOT", string, &[2][..]),
+            ("LIST_SLOT", string, &[4][..]),
+            ("LIST_ITEM_10", &[VarType::Int][..], &[5][..]),
+            ("LIST_END", &[], &[]),
+        ];
+        let keys = [
+            "LIST_BEGIN",
+            "LIST_SLOT_3",
+            "LIST_SLOT",
+            "LIST_ITEM_10",
+            "LIST_END",
+        ];
+        let data = build_cfg(&rows, b"a\0b\0c\0", &keys);
+        let cfg = CfgBin::open(&data)?;
+        let list = &cfg.entries[0];
+        let names: Vec<&str> = list.children.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "LIST_SLOT_3_0",
+                "LIST_SLOT_0",
+                "LIST_SLOT_1",
+                "LIST_SLOT_2",
+                "LIST_SLOT_3",
+                "LIST_ITEM_10_0"
+            ]
+        );
+        assert_eq!(cfg.extract_texts()[0].entry, "LIST_SLOT_3");
+        assert_eq!(cfg.save()?, data);
+
+        // A hand-built entry's name is its key, numeric ending included.
+        let slot = Entry {
+            name: "SLOT_3".to_string(),
+            source_name: None,
+            variables: vec![Variable {
+                var_type: VarType::String,
+                value: VarValue::String(Some("x".to_string())),
+            }],
+            children: Vec::new(),
+            end_terminator: false,
+        };
+        let reopened = CfgBin::open(&CfgBin::new(CfgBinEncoding::Utf8, 1, vec![slot]).save()?)?;
+        assert_eq!(reopened.entries[0].name, "SLOT_3_0");
+        assert_eq!(reopened.entries[0].source_name.as_deref(), Some("SLOT_3"));
+        Ok(())
+    }
+
+    #[test]
+    fn variables_resolve_by_entry_path() -> Result<()> {
+        let rows = [
+            ("LIST_BEGIN", &[][..], &[][..]),
+            ("LIST", &[VarType::String, VarType::Int][..], &[0, 7][..]),
+            ("LIST", &[VarType::String, VarType::Int][..], &[2, 8][..]),
+            ("LIST_END", &[], &[]),
+        ];
+        let keys = ["LIST_BEGIN", "LIST", "LIST_END"];
+        let mut cfg = CfgBin::open(&build_cfg(&rows, b"a\0b\0", &keys))?;
+
+        let int_of = |var: Option<&Variable>| match var.map(|v| &v.value) {
+            Some(VarValue::Int(i)) => Some(*i),
+            _ => None,
+        };
+        assert_eq!(int_of(cfg.get_variable("LIST_BEGIN_0/LIST_1", 1)), Some(8));
+        assert_eq!(int_of(cfg.get_variable("LIST_BEGIN/LIST", 1)), Some(7));
+        assert!(cfg.get_variable("LIST_BEGIN/LIST_2", 0).is_none());
+        assert!(cfg.get_variable("LIST_BEGIN/LIST_1", 2).is_none());
+        assert!(cfg.get_variable("LIS", 0).is_none());
+
+        cfg.set_variable("LIST_BEGIN/LIST_1", 0, VarValue::String(Some("B".into())))?;
+        assert!(cfg
+            .set_variable("LIST_BEGIN/LIST_1", 0, VarValue::Int(1))
+            .is_err());
+        assert!(cfg
+            .set_variable("LIST_BEGIN/LIST_1", 5, VarValue::Int(1))
+            .is_err());
+        assert!(cfg.set_variable("MISSING", 0, VarValue::Int(1)).is_err());
+
+        let reopened = CfgBin::open(&cfg.save()?)?;
+        let values: Vec<String> = reopened
+            .extract_texts()
+            .into_iter()
+            .map(|t| t.value)
+            .collect();
+        assert_eq!(values, ["a", "B"]);
+        Ok(())
+    }
+
+    #[test]
+    fn leaf_texts_skip_containers_but_keep_global_indices() -> Result<()> {
+        let string = &[VarType::String][..];
+        let rows = [
+            ("LIST_BEGIN", string, &[0][..]),
+            ("ITEM_BEGIN", string, &[2]),
+            ("ITEM", string, &[4]),
+            ("ITEM_END", &[], &[]),
+            ("LIST", string, &[6]),
+            ("LIST_END", &[], &[]),
+        ];
+        let keys = [
+            "LIST_BEGIN",
+            "ITEM_BEGIN",
+            "ITEM",
+            "ITEM_END",
+            "LIST",
+            "LIST_END",
+        ];
+        let mut cfg = CfgBin::open(&build_cfg(&rows, b"l\0i\0a\0b\0", &keys))?;
+
+        let mut leaves = cfg.extract_leaf_texts();
+        let found: Vec<(usize, &str)> =
+            leaves.iter().map(|t| (t.index, t.value.as_str())).collect();
+        assert_eq!(found, [(2, "a"), (3, "b")]);
+
+        let mut streamed = Vec::new();
+        assert_eq!(cfg.write_texts_json(&mut streamed, false, true)?, 2);
+        assert_eq!(
+            String::from_utf8(streamed)?,
+            serde_json::to_string_pretty(&leaves)?
+        );
+
+        leaves[1].value = "B".into();
+        assert!(cfg.update_texts(&leaves).is_empty());
+        let values: Vec<String> = cfg.extract_texts().into_iter().map(|t| t.value).collect();
+        assert_eq!(values, ["l", "i", "a", "B"]);
+        Ok(())
+    }
+
+    #[test]
+    fn write_texts_json_matches_pretty_serialization() -> Result<()> {
+        let string = &[VarType::String][..];
+        let rows = [
+            ("MENU_BEGIN", string, &[0][..]),
+            ("MENU", string, &[2]),
+            ("MENU_END", &[], &[]),
+        ];
+        let keys = ["MENU_BEGIN", "MENU", "MENU_END"];
+        let cfg = CfgBin::open(&build_cfg(&rows, b"a\0\"q\"\n\0", &keys))?;
+
+        for with_hash in [false, true] {
+            let mut streamed = Vec::new();
+            assert_eq!(cfg.write_texts_json(&mut streamed, with_hash, false)?, 2);
+            let texts = cfg.collect_texts(with_hash);
+            assert_eq!(
+                String::from_utf8(streamed)?,
+                serde_json::to_string_pretty(&texts)?
+            );
+        }
+
+        let empty = CfgBin::new(CfgBinEncoding::Utf8, 1, Vec::new());
+        let mut streamed = Vec::new();
+        assert_eq!(empty.write_texts_json(&mut streamed, false, false)?, 0);
+        assert_eq!(
+            String::from_utf8(streamed)?,
+            serde_json::to_string_pretty(&empty.extract_texts())?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn update_texts_reports_slot_mismatches_but_still_applies() {
+        let string_var = || Variable {
+            var_type: VarType::String,
+            value: VarValue::String(Some("x".to_string())),
+        };
+        let mut cfg = CfgBin::new(
+            CfgBinEncoding::Utf8,
+            1,
+            vec![Entry {
+                name: "MENU".to_string(),
+                source_name: None,
+                variables: vec![string_var(), string_var()],
+                children: Vec::new(),
+                end_terminator: false,
+            }],
+        );
+
+        let texts = vec![
+            TextEntry {
+                index: 0,
+                entry: "MENU".to_string(),
+                variable_index: 0,
+                value: "ok".to_string(),
+                hash: None,
+            },
+            TextEntry {
+                index: 1,
+                entry: "SHOP".to_string(),
+                variable_index: 3,
+                value: "stale".to_string(),
+                hash: None,
+            },
+        ];
+        let mismatches = cfg.update_texts(&texts);
+
+        assert_eq!(
+            mismatches,
+            vec![SlotMismatch {
+                index: 1,
+                recorded_entry: "SHOP".to_string(),
+                recorded_variable_index: 3,
+                actual_entry: "MENU".to_string(),
+                actual_variable_index: 1,
+            }]
+        );
+        let values: Vec<String> = cfg.extract_texts().into_iter().map(|t| t.value).collect();
+        assert_eq!(values, ["ok", "stale"]);
+    }
+
     #[test]
     fn patch_texts_by_address_in_place_updates_offsets_and_preserves_tail() -> Result<()> {
         let src = make_two_string_entry_cfg();
@@ -1309,4 +5898,108 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn unchanged_strings_keep_non_canonical_shift_jis_bytes() -> Result<()> {
+        // 0xEEF9 (NEC-selected IBM extension) decodes to U+FFE2, which encodes back as 0x81CA.
+        let sjis = CfgBinEncoding::ShiftJis;
+        assert_eq!(encode_string_bytes("\u{FFE2}", &sjis), [0x81, 0xCA]);
+        let string = &[VarType::String][..];
+        let rows = [
+            ("LIST_BEGIN", &[][..], &[][..]),
+            ("LIST", string, &[0][..]),
+            ("LIST", string, &[3][..]),
+            ("LIST_END", &[], &[]),
+        ];
+        let mut data = build_cfg(&rows, b"\xEE\xF9\0a\0", &["LIST_BEGIN", "LIST", "LIST_END"]);
+        let footer = find_footer(&data, Endian::Little).unwrap();
+        data[footer + 6..footer + 8].copy_from_slice(&[0, 0]);
+
+        let mut cfg = CfgBin::open(&data)?;
+        assert_eq!(cfg.encoding, sjis);
+        assert_eq!(cfg.extract_texts()[0].value, "\u{FFE2}");
+        assert_eq!(cfg.save()?, data);
+
+        let mut texts = cfg.extract_texts();
+        texts[1].value = "b".to_string();
+        cfg.update_texts(&texts);
+        let raw = CfgBin::raw_strings(
+            &cfg.save()?,
+            StringFormat::NullTerminated,
+            Endian::Little,
+            CfgBinEncoding::Utf8,
+        )?;
+        assert_eq!(raw[0].bytes, [0xEE, 0xF9]);
+        assert_eq!(raw[1].bytes, b"b");
+
+        cfg.original_strings = None;
+        let raw = CfgBin::raw_strings(
+            &cfg.save()?,
+            StringFormat::NullTerminated,
+            Endian::Little,
+            CfgBinEncoding::Utf8,
+        )?;
+        assert_eq!(raw[0].bytes, [0x81, 0xCA]);
+        Ok(())
+    }
+
+    #[test]
+    fn truncated_or_corrupt_files_fail_to_open_instead_of_panicking() -> Result<()> {
+        let data = synthetic::build(2, 2).save()?;
+        for len in 0..data.len() {
+            let _ = CfgBin::open(&data[..len]);
+        }
+        for pos in 0..data.len() {
+            for byte in [0x00, 0x01, 0x10, 0x7F, 0x80, 0xFF] {
+                let mut corrupt = data.clone();
+                corrupt[pos] = byte;
+                let _ = CfgBin::open(&corrupt);
+            }
+        }
+
+        let mut bad_table = data.clone();
+        bad_table[8..12].copy_from_slice(&0x4000_0000i32.to_le_bytes());
+        let err = CfgBin::open(&bad_table)
+            .err()
+            .context("expected an error")?;
+        assert!(err.to_string().contains("out of bounds"), "{}", err);
+        assert!(CfgBin::open(&data[..15]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn utf16le_files_round_trip_through_open_extract_and_save() -> Result<()> {
+        let utf16 = CfgBinEncoding::Utf16Le;
+        let mut cfg = synthetic::build(2, 3);
+        cfg.encoding = utf16;
+        let mut texts = cfg.extract_texts();
+        texts[0].value = "日本語 \u{1F600}".to_string();
+        cfg.update_texts(&texts);
+        let data = cfg.save()?;
+        assert_eq!(detect_encoding(&data), (utf16, FOOTER_ENCODING_UTF16LE));
+        // A UTF-8 variant with the same footer value keeps reading as UTF-8.
+        let mut variant = synthetic::build(2, 3);
+        variant.footer_encoding = FOOTER_ENCODING_UTF16LE;
+        assert_eq!(
+            detect_encoding(&variant.save()?),
+            (CfgBinEncoding::Utf8, FOOTER_ENCODING_UTF16LE)
+        );
+        let opened = CfgBin::open(&data)?;
+        assert_eq!(opened.encoding, utf16);
+        assert!(opened.suspect_string_offsets.is_empty());
+        let values =
+            |texts: &[TextEntry]| texts.iter().map(|t| t.value.clone()).collect::<Vec<_>>();
+        assert_eq!(values(&opened.extract_texts()), values(&texts));
+        assert_eq!(opened.key_order[0], "TEXT_INFO_BEGIN");
+        assert_eq!(opened.save()?, data);
+
+        // Records end with a 16-bit zero, so ASCII text is full of single zero bytes.
+        let mut record = encode_string_bytes("Text 0 of block 1", &utf16);
+        record.extend_from_slice(utf16.terminator());
+        assert!(data.windows(record.len()).any(|w| w == record));
+        let raw = CfgBin::raw_strings(&data, StringFormat::NullTerminated, Endian::Little, utf16)?;
+        assert_eq!(raw[0].bytes, encode_string_bytes(&texts[0].value, &utf16));
+        assert_eq!(raw.len(), opened.distinct_string_count());
+        Ok(())
+    }
 }
@@ -0,0 +1,122 @@
+//! Address-based ("nnk") editing: strings are keyed by the file address of the entry field
+//! that holds their string-table offset, and a patch rewrites only the string table and those
+//! offset fields. Entry rows, the key table and the footer are copied byte for byte, so this
+//! suits files that a standard rebuild would not reproduce faithfully.
+//!
+//! `from_texts` and `to_texts` convert from and to the index-based texts of standard mode, so a
+//! standard JSON can be applied with the in-place patcher.
+//!
+//! Constraints:
+//! - Only null-terminated string tables are supported.
+//! - A patch must supply a value for every address of the source file, no more and no fewer
+//!   (start from `extract`).
+//! - The file keeps its size only while the new string table fits in the old one's 16-byte
+//!   padded length; otherwise everything after the string table shifts.
+//!
+//! ```
+//! use cfg_bin_text_editor::cfgbin::{nnk, CfgBin, CfgBinEncoding, Entry, VarType, VarValue, Variable};
+//!
+//! let text = Variable {
+//!     var_type: VarType::String,
+//!     value: VarValue::String(Some("Hello".to_string())),
+//! };
+//! let entry = Entry {
+//!     name: "TEXT".to_string(),
+//!     source_name: None,
+//!     variables: vec![text],
+//!     children: Vec::new(),
+//!     end_terminator: false,
+//! };
+//! let data = CfgBin::new(CfgBinEncoding::Utf8, 1, vec![entry]).save()?;
+//!
+//! let mut texts = nnk::extract(&data)?;
+//! let address = *texts.keys().next().unwrap();
+//! texts.insert(address, "Hi".to_string());
+//! let patched = nnk::patch(&data, &texts)?;
+//!
+//! assert_eq!(nnk::extract(&patched)?[&address], "Hi");
+//! assert_eq!(patched.len(), data.len());
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+
+use super::{CfgBin, Endian, TextEntry};
+
+/// String values keyed by the address of the field holding their string-table offset.
+pub type AddressTexts = BTreeMap<u32, String>;
+
+/// Every string referenced by an entry field, in address order.
+pub fn extract(data: &[u8]) -> Result<AddressTexts> {
+    CfgBin::extract_texts_by_address(data)
+}
+
+/// `extract` with `0x%08X` address keys, the nnk JSON layout.
+pub fn extract_for_json(data: &[u8]) -> Result<BTreeMap<String, String>> {
+    CfgBin::extract_texts_by_address_for_json(data)
+}
+
+/// Parses nnk JSON (`{"0x00000018": "text"}`; decimal keys are accepted too).
+pub fn parse_json(json: &str) -> Result<AddressTexts> {
+    CfgBin::parse_address_texts_json(json)
+}
+
+/// Rewrites the string table of `data` with `texts` and points each address at its new value.
+pub fn patch(data: &[u8], texts: &AddressTexts) -> Result<Vec<u8>> {
+    CfgBin::patch_texts_by_address_in_place(data, texts)
+}
+
+/// Standard-mode `texts` (numbered as by `CfgBin::extract_texts`) as the map `patch` takes:
+/// every address of `data`, the file `cfg` was opened from, with its value from `texts`, or its
+/// current one if `texts` doesn't list that index. This lets a standard JSON be written with the
+/// in-place patcher. Null string slots have no address, so they can't get a non-empty value.
+pub fn from_texts(cfg: &CfgBin, data: &[u8], texts: &[TextEntry]) -> Result<AddressTexts> {
+    let addresses = field_addresses(cfg, cfg.extract_texts().len())?;
+    let mut by_address = extract(data)?;
+    for text in texts {
+        let Some(&address) = addresses.get(text.index) else {
+            bail!(
+                "Index {} does not exist; the file has {} text slots",
+                text.index,
+                addresses.len()
+            );
+        };
+        match by_address.get_mut(&address) {
+            Some(value) => *value = text.value.clone(),
+            None if text.value.is_empty() => {}
+            None => bail!(
+                "Index {} ({}[{}]) is a null string in the file; an nnk patch can only change strings the file references",
+                text.index,
+                text.entry,
+                text.variable_index
+            ),
+        }
+    }
+    Ok(by_address)
+}
+
+/// The reverse of `from_texts`: the texts of `cfg` with the values `texts` has for their
+/// addresses.
+pub fn to_texts(cfg: &CfgBin, texts: &AddressTexts) -> Result<Vec<TextEntry>> {
+    let mut standard = cfg.extract_texts();
+    let addresses = field_addresses(cfg, standard.len())?;
+    for (text, address) in standard.iter_mut().zip(addresses) {
+        if let Some(value) = texts.get(address) {
+            text.value = value.clone();
+        }
+    }
+    Ok(standard)
+}
+
+fn field_addresses(cfg: &CfgBin, slot_count: usize) -> Result<&[u32]> {
+    if cfg.endian == Endian::Big {
+        bail!("nnk mode only supports little-endian files");
+    }
+    let addresses = &cfg.string_field_addresses;
+    if addresses.len() != slot_count {
+        bail!("The entries no longer match the file they were opened from; reopen it");
+    }
+    Ok(addresses)
+}
@@ -0,0 +1,53 @@
+//! Synthetic files for benchmarks and tests, built in memory so their size can be scaled
+//! freely. Every call with the same arguments gives the same tree.
+
+use super::{CfgBin, CfgBinEncoding, Entry, VarType, VarValue, Variable};
+
+/// `blocks` TEXT_INFO_BEGIN blocks of `items` TEXT_INFO rows each. A row holds a unique
+/// string, an int, a float and a string shared by every tenth row, so string sharing is
+/// exercised too. Names carry the occurrence suffixes `open` gives the saved file.
+pub fn build(blocks: usize, items: usize) -> CfgBin {
+    let entries = (0..blocks)
+        .map(|block| {
+            let children = (0..items)
+                .map(|item| {
+                    let n = block * items + item;
+                    let shared = format!("Shared {}", n / 10);
+                    Entry {
+                        name: format!("TEXT_INFO_{}", n),
+                        source_name: Some("TEXT_INFO".to_string()),
+                        variables: vec![
+                            string(format!("Text {} of block {}", item, block)),
+                            Variable {
+                                var_type: VarType::Int,
+                                value: VarValue::Int(n as i32),
+                            },
+                            Variable {
+                                var_type: VarType::Float,
+                                value: VarValue::Float(n as f32 * 0.5),
+                            },
+                            string(shared),
+                        ],
+                        children: Vec::new(),
+                        end_terminator: false,
+                    }
+                })
+                .collect();
+            Entry {
+                name: format!("TEXT_INFO_BEGIN_{}", block),
+                source_name: Some("TEXT_INFO_BEGIN".to_string()),
+                variables: Vec::new(),
+                children,
+                end_terminator: true,
+            }
+        })
+        .collect();
+    CfgBin::new(CfgBinEncoding::Utf8, 1, entries)
+}
+
+fn string(value: String) -> Variable {
+    Variable {
+        var_type: VarType::String,
+        value: VarValue::String(Some(value)),
+    }
+}
@@ -1,27 +1,44 @@
 const POLYNOMIAL: u32 = 0xedb88320;
 const SEED: u32 = 0xffffffff;
 
-fn init_table() -> [u32; 256] {
+// Built at compile time, so `compute` only does the lookups.
+const TABLE: [u32; 256] = init_table();
+
+const fn init_table() -> [u32; 256] {
     let mut table = [0u32; 256];
-    for i in 0..256u32 {
-        let mut entry = i;
-        for _ in 0..8 {
+    let mut i = 0;
+    while i < 256 {
+        let mut entry = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
             if entry & 1 == 1 {
                 entry = (entry >> 1) ^ POLYNOMIAL;
             } else {
                 entry >>= 1;
             }
+            bit += 1;
         }
-        table[i as usize] = entry;
+        table[i] = entry;
+        i += 1;
     }
     table
 }
 
 pub fn compute(buffer: &[u8]) -> u32 {
-    let table = init_table();
     let mut hash = SEED;
     for &b in buffer {
-        hash = (hash >> 8) ^ table[(b ^ (hash as u8)) as usize];
+        hash = (hash >> 8) ^ TABLE[(b ^ (hash as u8)) as usize];
     }
     !hash
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compute_matches_the_standard_check_value() {
+        assert_eq!(compute(b"123456789"), 0xCBF4_3926);
+        assert_eq!(compute(b""), 0);
+    }
+}
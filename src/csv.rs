@@ -0,0 +1,189 @@
+// CSV extract/update format (RFC 4180): one `index,entry,variable_index,value` row per text
+// slot. Written with CRLF record ends and a UTF-8 BOM so spreadsheet tools open it as UTF-8;
+// values keep their own line breaks inside quotes.
+
+use std::collections::HashMap;
+
+use anyhow::{bail, Context, Result};
+use cfg_bin_text_editor::cfgbin::TextEntry;
+
+const COLUMNS: [&str; 4] = ["index", "entry", "variable_index", "value"];
+
+pub fn write_texts(texts: &[TextEntry]) -> String {
+    let mut out = String::from("\u{FEFF}");
+    push_record(&mut out, COLUMNS);
+    for t in texts {
+        let index = t.index.to_string();
+        let variable_index = t.variable_index.to_string();
+        push_record(&mut out, [&index, &t.entry, &variable_index, &t.value]);
+    }
+    out
+}
+
+fn push_record(out: &mut String, fields: [&str; 4]) {
+    for (i, field) in fields.into_iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        if field.contains([',', '"', '\r', '\n']) {
+            out.push('"');
+            out.push_str(&field.replace('"', "\"\""));
+            out.push('"');
+        } else {
+            out.push_str(field);
+        }
+    }
+    out.push_str("\r\n");
+}
+
+/// Rows of `content` as text entries. Columns are found by their header name, so they may be
+/// reordered and extra columns (notes, a source column) are ignored.
+pub fn parse_texts(content: &str) -> Result<Vec<TextEntry>> {
+    let content = content.strip_prefix('\u{FEFF}').unwrap_or(content);
+    let mut records = parse_records(content)?.into_iter();
+    let Some((_, header)) = records.next() else {
+        bail!(
+            "CSV file is empty; expected a header row: {}",
+            COLUMNS.join(",")
+        );
+    };
+    let positions: HashMap<&str, usize> = header
+        .iter()
+        .enumerate()
+        .map(|(i, name)| (name.trim(), i))
+        .collect();
+    let mut columns = [0; 4];
+    for (column, name) in columns.iter_mut().zip(COLUMNS) {
+        *column = *positions
+            .get(name)
+            .with_context(|| format!("CSV header has no \"{}\" column", name))?;
+    }
+    let [index, entry, variable_index, value] = columns;
+
+    let mut texts = Vec::new();
+    for (line, record) in records {
+        if record.len() == 1 && record[0].is_empty() {
+            continue;
+        }
+        let field = |column: usize| {
+            record.get(column).map(String::as_str).with_context(|| {
+                format!("CSV line {}: expected at least {} fields", line, column + 1)
+            })
+        };
+        let number = |column: usize| -> Result<usize> {
+            let text = field(column)?;
+            text.trim()
+                .parse()
+                .with_context(|| format!("CSV line {}: \"{}\" is not a number", line, text))
+        };
+        texts.push(TextEntry {
+            index: number(index)?,
+            entry: field(entry)?.to_string(),
+            variable_index: number(variable_index)?,
+            value: field(value)?.to_string(),
+            hash: None,
+        });
+    }
+    Ok(texts)
+}
+
+// Records with the line each starts on. A record ends at an unquoted LF or CRLF; quoted fields
+// keep their characters, line breaks included, with `""` read as `"`.
+fn parse_records(content: &str) -> Result<Vec<(usize, Vec<String>)>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut line = 1;
+    let mut start = 1;
+    let mut chars = content.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if field.is_empty() => {
+                let quote_line = line;
+                loop {
+                    match chars.next() {
+                        Some('"') if chars.peek() == Some(&'"') => {
+                            chars.next();
+                            field.push('"');
+                        }
+                        Some('"') => break,
+                        Some(c) => {
+                            line += usize::from(c == '\n');
+                            field.push(c);
+                        }
+                        None => bail!("CSV line {}: quoted field is never closed", quote_line),
+                    }
+                }
+                if !matches!(chars.peek(), None | Some(',' | '\r' | '\n')) {
+                    bail!("CSV line {}: unexpected text after a closing quote", line);
+                }
+            }
+            ',' => record.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push((start, std::mem::take(&mut record)));
+                line += 1;
+                start = line;
+            }
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push((start, record));
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_with_quotes_commas_and_newlines_round_trip() -> Result<()> {
+        let texts = vec![
+            TextEntry {
+                index: 0,
+                entry: "TEXT_INFO_0".to_string(),
+                variable_index: 1,
+                value: "He said \"hi\",\nbye".to_string(),
+                hash: None,
+            },
+            TextEntry {
+                index: 1,
+                entry: "TEXT_INFO_1".to_string(),
+                variable_index: 1,
+                value: "plain\r\nCRLF, kept".to_string(),
+                hash: None,
+            },
+        ];
+        let csv = write_texts(&texts);
+        assert!(csv.starts_with("\u{FEFF}index,entry,variable_index,value\r\n"));
+        assert!(csv.contains("0,TEXT_INFO_0,1,\"He said \"\"hi\"\",\nbye\"\r\n"));
+
+        let back = parse_texts(&csv)?;
+        assert_eq!(back.len(), 2);
+        assert_eq!(back[0].value, texts[0].value);
+        assert_eq!(back[1].value, texts[1].value);
+        assert_eq!((back[1].index, back[1].entry.as_str()), (1, "TEXT_INFO_1"));
+        Ok(())
+    }
+
+    #[test]
+    fn columns_are_found_by_header_and_bad_rows_name_their_line() -> Result<()> {
+        let csv = "note,value,index,variable_index,entry\nfix,\"a\nb\",3,1,ITEM\n\nok,c,4,2,ITEM";
+        let texts = parse_texts(csv)?;
+        assert_eq!(texts.len(), 2);
+        assert_eq!((texts[0].index, texts[0].value.as_str()), (3, "a\nb"));
+        assert_eq!((texts[1].variable_index, texts[1].value.as_str()), (2, "c"));
+
+        let err = parse_texts("index,entry,value\n0,A,x").unwrap_err();
+        assert!(err.to_string().contains("variable_index"), "{}", err);
+        let err =
+            parse_texts("index,entry,variable_index,value\n0,A,1,\"x\"\nx,A,1,y").unwrap_err();
+        assert!(err.to_string().contains("line 3"), "{}", err);
+        assert!(parse_texts("index,entry,variable_index,value\n0,A,1,\"open").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,32 @@
+//! Parsing and rebuilding of Level-5 cfg.bin files, the core of the cfg_bin_text_editor CLI.
+//! The CLI is a thin wrapper around this library, which can be embedded directly (the
+//! benchmarks drive it too).
+//!
+//! ```
+//! use cfg_bin_text_editor::{CfgBin, CfgBinEncoding, Entry, VarType, VarValue, Variable};
+//!
+//! let text = Variable {
+//!     var_type: VarType::String,
+//!     value: VarValue::String(Some("Hello".to_string())),
+//! };
+//! let entry = Entry {
+//!     name: "TEXT".to_string(),
+//!     source_name: None,
+//!     variables: vec![text],
+//!     children: Vec::new(),
+//!     end_terminator: false,
+//! };
+//! let data = CfgBin::new(CfgBinEncoding::Utf8, 1, vec![entry]).save()?;
+//!
+//! let mut cfg = CfgBin::open(&data)?;
+//! let mut texts = cfg.extract_texts();
+//! texts[0].value = "Hi".to_string();
+//! assert!(cfg.update_texts(&texts).is_empty());
+//! assert_eq!(CfgBin::open(&cfg.save()?)?.extract_texts()[0].value, "Hi");
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+pub mod cfgbin;
+pub mod crc32;
+
+pub use cfgbin::{CfgBin, CfgBinEncoding, Entry, TextEntry, VarType, VarValue, Variable};
@@ -1,13 +1,25 @@
-mod cfgbin;
-mod crc32;
+mod csv;
+mod warnings;
 
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
-use std::path::PathBuf;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-use anyhow::{bail, Context, Result};
-use clap::{Parser, ValueEnum};
+use anyhow::{anyhow, bail, Context, Result};
+use clap::parser::ValueSource;
+use clap::{ArgGroup, ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use regex::{NoExpand, Regex, RegexBuilder};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
 
-use cfgbin::{CfgBin, TextEntry};
+use cfg_bin_text_editor::cfgbin::{
+    self, nnk, CfgBin, CfgBinEncoding, Endian, EntryDump, OpenOptions, RegionSizes, SaveOptions,
+    SlotMismatch, StringFormat, TextEntry, VarType,
+};
+use warnings::{Warning, Warnings};
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
 enum Mode {
@@ -19,24 +31,518 @@ enum Mode {
 enum ExtractFormat {
     Json,
     Txt,
+    // Read-only JSON nested by entry hierarchy (standard mode)
+    Tree,
+    // JSON object keyed by entry path and variable index (standard mode)
+    Paths,
+    // index,entry,variable_index,value rows for spreadsheet tools (standard mode)
+    Csv,
+    // Every entry with all of its variables and their types (standard mode)
+    Full,
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
 enum UpdateFormat {
     Json,
     Txt,
+    Paths,
+    Csv,
+    Full,
 }
 
+// Case-insensitive parse of a ValueEnum from outside clap (env var, config), accepting aliases.
+fn parse_value_enum<T: ValueEnum + Copy>(s: &str, what: &str, aliases: &[(&str, T)]) -> Result<T> {
+    let wanted = s.trim().to_ascii_lowercase();
+    let by_name = T::value_variants()
+        .iter()
+        .copied()
+        .find(|v| value_name(*v) == wanted);
+    let by_alias = || aliases.iter().find(|(a, _)| *a == wanted).map(|(_, v)| *v);
+    by_name.or_else(by_alias).with_context(|| {
+        let names: Vec<String> = T::value_variants().iter().map(|v| value_name(*v)).collect();
+        let alias_names: Vec<&str> = aliases.iter().map(|(a, _)| *a).collect();
+        format!(
+            "Invalid {} '{}': expected one of {} (aliases: {})",
+            what,
+            s,
+            names.join(", "),
+            alias_names.join(", ")
+        )
+    })
+}
+
+impl FromStr for Mode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        parse_value_enum(
+            s,
+            "mode",
+            &[
+                ("std", Mode::Standard),
+                ("index", Mode::Standard),
+                ("address", Mode::Nnk),
+                ("addr", Mode::Nnk),
+            ],
+        )
+    }
+}
+
+impl FromStr for ExtractFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        parse_value_enum(
+            s,
+            "extract format",
+            &[
+                ("text", ExtractFormat::Txt),
+                ("nested", ExtractFormat::Tree),
+            ],
+        )
+    }
+}
+
+impl FromStr for UpdateFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        parse_value_enum(s, "update format", &[("text", UpdateFormat::Txt)])
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+enum Normalization {
+    Nfc,
+    Nfkc,
+}
+
+impl Normalization {
+    fn apply(self, s: &str) -> String {
+        match self {
+            Normalization::Nfc => s.nfc().collect(),
+            Normalization::Nfkc => s.nfkc().collect(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+enum LineEnding {
+    Lf,
+    Crlf,
+    Native,
+}
+
+impl LineEnding {
+    fn is_crlf(self) -> bool {
+        match self {
+            LineEnding::Lf => false,
+            LineEnding::Crlf => true,
+            LineEnding::Native => cfg!(windows),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+enum ReportFormat {
+    Table,
+    Json,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+enum StringFormatArg {
+    Null,
+    LenPrefixed,
+}
+
+impl From<StringFormatArg> for StringFormat {
+    fn from(arg: StringFormatArg) -> Self {
+        match arg {
+            StringFormatArg::Null => StringFormat::NullTerminated,
+            StringFormatArg::LenPrefixed => StringFormat::LengthPrefixed,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+enum EncodingArg {
+    // The footer's encoding (or, for output, the source's)
+    Auto,
+    #[value(alias = "shiftjis", alias = "sjis")]
+    ShiftJis,
+    #[value(alias = "utf-8")]
+    Utf8,
+    #[value(alias = "utf-16le")]
+    Utf16le,
+}
+
+impl EncodingArg {
+    fn forced(self) -> Option<CfgBinEncoding> {
+        match self {
+            EncodingArg::Auto => None,
+            EncodingArg::ShiftJis => Some(CfgBinEncoding::ShiftJis),
+            EncodingArg::Utf8 => Some(CfgBinEncoding::Utf8),
+            EncodingArg::Utf16le => Some(CfgBinEncoding::Utf16Le),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+enum EndianArg {
+    Le,
+    Be,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+enum VarTypeArg {
+    String,
+    Int,
+    Float,
+    Unknown,
+}
+
+impl From<VarTypeArg> for VarType {
+    fn from(arg: VarTypeArg) -> Self {
+        match arg {
+            VarTypeArg::String => VarType::String,
+            VarTypeArg::Int => VarType::Int,
+            VarTypeArg::Float => VarType::Float,
+            VarTypeArg::Unknown => VarType::Unknown,
+        }
+    }
+}
+
+impl From<EndianArg> for Endian {
+    fn from(arg: EndianArg) -> Self {
+        match arg {
+            EndianArg::Le => Endian::Little,
+            EndianArg::Be => Endian::Big,
+        }
+    }
+}
+
+fn encoding_name(encoding: CfgBinEncoding) -> &'static str {
+    match encoding {
+        CfgBinEncoding::ShiftJis => "shift-jis",
+        CfgBinEncoding::Utf8 => "utf8",
+        CfgBinEncoding::Utf16Le => "utf16le",
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Show structural statistics (children, depth, entry count) per top-level entry
+    Info {
+        #[arg(value_name = "CFG_BIN_FILE")]
+        cfg_file: PathBuf,
+
+        /// Also list key-table names that no entry references
+        #[arg(long)]
+        report_unused_keys: bool,
+
+        /// Also count variables by type (String/Int/Float/Unknown), as a table or JSON
+        #[arg(long, value_enum, value_name = "FORMAT", num_args = 0..=1, default_missing_value = "table")]
+        count_variables_by_type: Option<ReportFormat>,
+
+        /// Also list every string-table record with its offset, raw bytes, and Shift-JIS/UTF-8 decodings
+        #[arg(long)]
+        dump_raw_strings: bool,
+
+        /// Write each referenced string with its file offset and the offsets of every field pointing at it as JSON
+        #[arg(long, value_name = "JSON")]
+        dump_offsets: Option<PathBuf>,
+
+        /// Print only the footer encoding, the encoding detected from it, the one it resolves to and the entry and string counts, one per line
+        #[arg(long, conflicts_with_all = ["report_unused_keys", "count_variables_by_type", "dump_raw_strings", "dump_offsets"])]
+        detect_encoding: bool,
+    },
+    /// Print every text entry whose value contains a substring (case-insensitive by default)
+    Find {
+        #[arg(value_name = "CFG_BIN_FILE")]
+        cfg_file: PathBuf,
+
+        /// Substring to search for (a regular expression with --regex)
+        #[arg(value_name = "PATTERN")]
+        pattern: String,
+
+        /// Treat PATTERN as a regular expression
+        #[arg(long)]
+        regex: bool,
+
+        /// Match case exactly
+        #[arg(long)]
+        case_sensitive: bool,
+
+        /// Processing mode: standard(index-based) or nnk(address-based)
+        #[arg(long, value_enum, default_value_t = Mode::Standard)]
+        mode: Mode,
+    },
+    /// Parse cfg.bin files without writing anything; exits non-zero if any fails
+    Check {
+        /// cfg.bin files; names containing `*` or `?` are expanded like --input-glob
+        #[arg(value_name = "CFG_BIN_FILE", required = true)]
+        cfg_files: Vec<String>,
+
+        /// Also fail files that parse with warnings (unknown CRCs, unread entry bytes, suspect strings)
+        #[arg(long)]
+        strict: bool,
+
+        /// Also re-save each file without edits, as a standard-mode update would, and fail it unless the result is byte-identical
+        #[arg(long)]
+        verify: bool,
+
+        /// With --verify, re-save as an update with --preserve-key-order would (also set by the config file)
+        #[arg(long, requires = "verify")]
+        preserve_key_order: bool,
+    },
+    /// Run every read-only diagnostic on a cfg.bin and report the findings by severity; exits non-zero on any error
+    Audit {
+        #[arg(value_name = "CFG_BIN_FILE")]
+        cfg_file: PathBuf,
+
+        /// Also write the report as JSON to this path
+        #[arg(long, value_name = "JSON")]
+        json: Option<PathBuf>,
+    },
+    /// Check that a translation JSON would apply cleanly to a cfg.bin, without writing anything; exits non-zero on any problem
+    Validate {
+        #[arg(value_name = "CFG_BIN_FILE")]
+        cfg_file: PathBuf,
+
+        /// Standard-mode translation JSON (JSON5 if it ends in .json5)
+        #[arg(value_name = "TRANSLATION_JSON")]
+        translation: PathBuf,
+
+        /// Also report values longer than N characters
+        #[arg(long, value_name = "N")]
+        max_length: Option<usize>,
+
+        /// The JSON lists only changed slots, as for update --sparse, so missing indices are fine
+        #[arg(long)]
+        sparse: bool,
+
+        /// Check a JSON of this variable type's slots, as for update --var-type
+        #[arg(long, value_enum, value_name = "TYPE", default_value_t = VarTypeArg::String)]
+        var_type: VarTypeArg,
+
+        /// Only check the slots under entries with this name, as for update --select-entry
+        #[arg(long, value_name = "ENTRY_NAME")]
+        select_entry: Option<String>,
+
+        /// Parse the JSON as JSON5, allowing comments and trailing commas
+        #[arg(long)]
+        json5: bool,
+    },
+    /// Extract a file in both standard and nnk mode and report strings on which they disagree
+    CompareModes {
+        #[arg(value_name = "CFG_BIN_FILE")]
+        cfg_file: PathBuf,
+
+        /// Also write the divergences as JSON to this path
+        #[arg(long, value_name = "JSON")]
+        json: Option<PathBuf>,
+    },
+    /// Write the slots an edited standard JSON changes as a small patch file instead of a rebuilt cfg.bin
+    MakePatch {
+        #[arg(value_name = "CFG_BIN_FILE")]
+        cfg_file: PathBuf,
+
+        /// Edited standard-mode extract JSON
+        #[arg(value_name = "EDITED_JSON")]
+        edited: PathBuf,
+
+        /// Patch file to write (defaults to <EDITED_JSON>.patch.json)
+        #[arg(short = 'o', value_name = "PATCH_JSON")]
+        output: Option<PathBuf>,
+    },
+    /// Rebuild an edited cfg.bin from the original and a patch made by make-patch
+    ApplyPatch {
+        #[arg(value_name = "CFG_BIN_FILE")]
+        cfg_file: PathBuf,
+
+        #[arg(value_name = "PATCH_JSON")]
+        patch: PathBuf,
+
+        /// Output file (defaults to overwriting CFG_BIN_FILE)
+        #[arg(short = 'o', value_name = "OUTPUT_FILE")]
+        output: Option<PathBuf>,
+    },
+    /// Print the YYYY/MM/DD HH:MM:SS build timestamp that starts many files, or set it with --set
+    Timestamp {
+        #[arg(value_name = "CFG_BIN_FILE")]
+        cfg_file: PathBuf,
+
+        /// New timestamp, e.g. "2024/01/31 12:00:00"
+        #[arg(long, value_name = "TIMESTAMP")]
+        set: Option<String>,
+
+        /// Output file (defaults to overwriting CFG_BIN_FILE)
+        #[arg(short = 'o', value_name = "OUTPUT_FILE", requires = "set")]
+        output: Option<PathBuf>,
+    },
+    /// Replace text in every string value and save (standard mode)
+    Replace {
+        #[arg(value_name = "CFG_BIN_FILE")]
+        cfg_file: PathBuf,
+
+        /// Text to replace (a regular expression with --regex)
+        #[arg(value_name = "FROM")]
+        from: String,
+
+        /// Replacement text (may use $1 or ${name} groups with --regex)
+        #[arg(value_name = "TO")]
+        to: String,
+
+        /// Treat FROM as a regular expression
+        #[arg(long)]
+        regex: bool,
+
+        /// Only report how many values would change
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Output file (defaults to overwriting CFG_BIN_FILE)
+        #[arg(short = 'o', value_name = "OUTPUT_FILE")]
+        output: Option<PathBuf>,
+
+        /// Keep the source file's key-table order and key-string offsets when rebuilding
+        #[arg(long)]
+        preserve_key_order: bool,
+
+        /// Write every string value separately, even if identical
+        #[arg(long)]
+        no_dedup_strings: bool,
+
+        /// Save in this encoding instead of the source's, re-encoding every string and key
+        #[arg(long, value_enum, value_name = "ENCODING")]
+        output_encoding: Option<EncodingArg>,
+
+        /// Write this u16 as the footer field before the encoding instead of the source file's
+        #[arg(long, value_name = "U16", value_parser = parse_u16)]
+        footer_unk1: Option<u16>,
+
+        /// Write this u16 as the footer field after the encoding instead of the source file's
+        #[arg(long, value_name = "U16", value_parser = parse_u16)]
+        footer_unk2: Option<u16>,
+
+        /// The cfg.bin is embedded in a larger file at this byte offset (use with --length)
+        #[arg(long, value_name = "N", value_parser = parse_size, requires = "length")]
+        offset: Option<usize>,
+
+        /// Byte length of the embedded cfg.bin (use with --offset)
+        #[arg(long, value_name = "M", value_parser = parse_size, requires = "offset")]
+        length: Option<usize>,
+
+        /// Splice the rebuilt cfg.bin back into a copy of the container at --offset
+        #[arg(long, requires = "offset")]
+        in_container: bool,
+    },
+    /// Report candidate key names that share a CRC32 (uses --encoding, default utf8)
+    CrcCollisions {
+        /// Text file with one candidate key name per line
+        #[arg(value_name = "NAMES_FILE")]
+        names_file: PathBuf,
+    },
+    /// Report text values that recur across several cfg.bin files, with their locations
+    SharedStrings {
+        /// cfg.bin files; names containing `*` or `?` are expanded like --input-glob
+        #[arg(value_name = "CFG_BIN_FILE", required = true)]
+        cfg_files: Vec<String>,
+
+        /// Only report values found in at least this many files
+        #[arg(long, default_value_t = 2)]
+        min_files: usize,
+
+        /// Also write the report as a glossary JSON to this path
+        #[arg(long, value_name = "GLOSSARY_JSON")]
+        glossary: Option<PathBuf>,
+    },
+}
+
+// Shown after `--help` and when run without arguments; every subcommand should appear here.
+const USAGE_EXAMPLES: &str = "\
+Examples:
+  Extract: cfg_bin_text_editor -e <file.cfg.bin> [--extract-format json|txt|tree|paths|csv|full]
+  Update:  cfg_bin_text_editor -w <file.cfg.bin> <input.json|input.txt> [-o <output.cfg.bin>]
+  Batch:   cfg_bin_text_editor -w <cfg_dir> --input-glob \"<dir>/*.json\" [--out-dir <dir>] [--fail-fast]
+  Info:    cfg_bin_text_editor info <file.cfg.bin> [--dump-offsets <offsets.json>]
+           cfg_bin_text_editor info <file.cfg.bin> --detect-encoding
+  Find:    cfg_bin_text_editor find <file.cfg.bin> <substring> [--regex]
+  Check:   cfg_bin_text_editor check <file.cfg.bin>... [--strict] [--verify]
+           cfg_bin_text_editor audit <file.cfg.bin> [--json <report.json>]
+           cfg_bin_text_editor validate <file.cfg.bin> <translation.json> [--max-length <n>]
+  Compare: cfg_bin_text_editor compare-modes <file.cfg.bin> [--json <divergences.json>]
+  Patch:   cfg_bin_text_editor make-patch <file.cfg.bin> <edited.json> [-o <fix.patch.json>]
+           cfg_bin_text_editor apply-patch <file.cfg.bin> <fix.patch.json> [-o <output.cfg.bin>]
+  Stamp:   cfg_bin_text_editor timestamp <file.cfg.bin> [--set \"YYYY/MM/DD HH:MM:SS\"]
+  Replace: cfg_bin_text_editor replace <file.cfg.bin> <from> <to> [--regex] [--dry-run]
+  Shared:  cfg_bin_text_editor shared-strings <file.cfg.bin>... [--glossary <glossary.json>]
+  Keys:    cfg_bin_text_editor crc-collisions <names.txt> [--encoding shift-jis|utf8|utf16le]";
+
 #[derive(Parser)]
 #[command(name = "cfg_bin_text_editor")]
 #[command(about = "Extract and update text fields in Level-5 cfg.bin files")]
+#[command(after_help = USAGE_EXAMPLES)]
+#[command(args_conflicts_with_subcommands = true)]
+#[command(group(ArgGroup::new("update_input").args(["json_file", "input_glob"])))]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Project file with default flags (defaults to .cfgbin.toml in the working directory)
+    #[arg(long, value_name = "TOML", global = true)]
+    config: Option<PathBuf>,
+
+    /// Print informational notes about the input file (e.g. footer encoding variants)
+    #[arg(short = 'v', long, global = true)]
+    verbose: bool,
+
+    /// Print one JSON object per processed file to stdout (NDJSON) instead of human-readable status lines
+    #[arg(long)]
+    json_events: bool,
+
+    /// Don't print warnings to stderr (they are still written to --warnings-json)
+    #[arg(short = 'q', long, global = true)]
+    quiet: bool,
+
+    /// Also write every warning of the run to this file as a JSON array
+    #[arg(long, value_name = "JSON", global = true)]
+    warnings_json: Option<PathBuf>,
+
+    /// Exit with an error after the run if any warning was reported (outputs are still written)
+    #[arg(long, global = true)]
+    abort_on_warning: bool,
+
+    /// Use UNKNOWN_0x<crc> placeholder names for entries missing from the key table instead of failing; saving writes their CRCs back unchanged
+    #[arg(long, visible_alias = "lenient", global = true)]
+    ignore_unknown_crc: bool,
+
+    /// Parse N entry rows instead of the header's entries_count (standard mode; recovery for files with a wrong header)
+    #[arg(long, value_name = "N", global = true)]
+    entries_count: Option<usize>,
+
+    /// String table layout: null(0x00-terminated) or len-prefixed(u32 length before each string; standard mode only)
+    #[arg(long, value_enum, global = true, default_value_t = StringFormatArg::Null)]
+    string_format: StringFormatArg,
+
+    /// Decode strings as shift-jis, utf8 or utf16le instead of the encoding the footer names (auto); saving writes the matching footer (standard mode)
+    #[arg(long, value_enum, global = true, default_value_t = EncodingArg::Auto)]
+    encoding: EncodingArg,
+
+    /// Byte order of the file's integer fields: le (default) or be for big-endian platforms (standard mode)
+    #[arg(long, value_enum, global = true, default_value_t = EndianArg::Le)]
+    endian: EndianArg,
+
+    /// Switch to the other encoding when the footer's one garbles many strings and the other decodes them cleanly (standard mode)
+    #[arg(long, global = true, conflicts_with = "encoding")]
+    auto_reencode: bool,
+
     /// Extract text fields to JSON
     #[arg(short = 'e', value_name = "CFG_BIN_FILE", conflicts_with_all = ["write_file", "json_file", "output_file"])]
     extract_file: Option<PathBuf>,
 
-    /// Write updated text fields back to cfg.bin
-    #[arg(short = 'w', value_name = "CFG_BIN_FILE", requires = "json_file")]
+    /// Write updated text fields back to cfg.bin (a directory of cfg.bin files with --input-glob)
+    #[arg(short = 'w', value_name = "CFG_BIN_FILE", requires = "update_input")]
     write_file: Option<PathBuf>,
 
     /// Input file for update (json or txt; use with -w)
@@ -47,295 +553,5040 @@ struct Cli {
     #[arg(short = 'o', value_name = "OUTPUT_FILE")]
     output_file: Option<PathBuf>,
 
+    /// Batch update: translation files to apply, e.g. "translations/*.json" (use with -w <CFG_DIR>)
+    #[arg(
+        long,
+        value_name = "PATTERN",
+        requires = "write_file",
+        conflicts_with = "output_file"
+    )]
+    input_glob: Option<String>,
+
+    /// Batch update: write results here instead of overwriting the cfg.bin files
+    #[arg(long, value_name = "DIR", requires = "input_glob")]
+    out_dir: Option<PathBuf>,
+
+    /// Batch update: stop at the first file that fails
+    #[arg(long, requires = "input_glob", overrides_with = "keep_going")]
+    fail_fast: bool,
+
+    /// Batch update: process every file and report all failures at the end (the default; overrides an earlier --fail-fast)
+    #[arg(long, requires = "input_glob", overrides_with = "fail_fast")]
+    keep_going: bool,
+
     /// Processing mode: standard(index-based rebuild) or nnk(address-based in-place patch)
     #[arg(long, value_enum, default_value_t = Mode::Standard)]
     mode: Mode,
 
-    /// Extract output format: json (default) or txt (line-by-line values)
+    /// Extract output format: json (default), txt (line-by-line values), tree (read-only nested JSON), paths, csv (spreadsheet rows) or full (every variable with its type)
     #[arg(long, value_enum, default_value_t = ExtractFormat::Json)]
     extract_format: ExtractFormat,
 
-    /// Update input format: json (default) or txt (line-by-line values)
-    #[arg(long, value_enum, default_value_t = UpdateFormat::Json)]
-    update_format: UpdateFormat,
+    /// Update input format: json, txt (line-by-line values), paths, csv or full; detected from the input file when omitted
+    #[arg(long, value_enum)]
+    update_format: Option<UpdateFormat>,
+
+    /// Print the extract and update formats this build supports, with the modes each works in, as JSON
+    #[arg(long, conflicts_with_all = ["extract_file", "write_file"])]
+    list_formats: bool,
+
+    /// Print the processing modes this build supports as JSON
+    #[arg(long, conflicts_with_all = ["extract_file", "write_file"])]
+    list_modes: bool,
+
+    /// TXT entries are separated by a "---" line and keep embedded newlines literally
+    #[arg(long, overrides_with = "no_multiline_txt")]
+    multiline_txt: bool,
+
+    /// Use one TXT line per entry even if the config file sets multiline-txt (default)
+    #[arg(long, overrides_with = "multiline_txt")]
+    no_multiline_txt: bool,
+
+    /// Prefix each TXT line with "<index>\t" so the update maps lines by index instead of order
+    #[arg(
+        long,
+        conflicts_with = "multiline_txt",
+        overrides_with = "no_txt_indexed"
+    )]
+    txt_indexed: bool,
+
+    /// Don't prefix TXT lines with their index even if the config file sets txt-indexed (default)
+    #[arg(long, overrides_with = "txt_indexed")]
+    no_txt_indexed: bool,
+
+    /// Show trailing whitespace as a [[ws]] marker on extract; update puts the file's original whitespace back where the marker is kept
+    #[arg(long)]
+    collapse_trailing_ws: bool,
+
+    /// Write the first N text entries as marked header lines that update skips (TXT)
+    #[arg(long, value_name = "N", requires = "extract_file", conflicts_with_all = ["multiline_txt", "txt_indexed"])]
+    header_lines: Option<usize>,
+
+    /// End the exported TXT with a newline
+    #[arg(long, overrides_with = "no_trailing_newline")]
+    trailing_newline: bool,
+
+    /// Don't end the exported TXT with a newline (default)
+    #[arg(long, overrides_with = "trailing_newline")]
+    no_trailing_newline: bool,
+
+    /// Line endings of the exported TXT: lf (default), crlf, or native (crlf on Windows); update reads any
+    #[arg(long, value_enum, value_name = "EOL", default_value_t = LineEnding::Lf)]
+    line_ending: LineEnding,
+
+    /// Keep the source file's key-table order and key-string offsets when rebuilding (standard mode)
+    #[arg(long, overrides_with = "no_preserve_key_order")]
+    preserve_key_order: bool,
+
+    /// Rebuild the key table in the usual order even if the config file sets preserve-key-order (default)
+    #[arg(long, overrides_with = "preserve_key_order")]
+    no_preserve_key_order: bool,
+
+    /// Write a separate string-table copy for every reference instead of sharing equal strings (standard mode)
+    #[arg(long, requires = "write_file")]
+    no_dedup_strings: bool,
+
+    /// Encode every string afresh instead of keeping the source bytes of unchanged strings whose canonical encoding differs (standard mode)
+    #[arg(long, requires = "write_file")]
+    reencode_strings: bool,
+
+    /// Apply Unicode normalization to extracted values, or to every value before saving an update
+    #[arg(long, value_enum, value_name = "FORM")]
+    normalize: Option<Normalization>,
+
+    /// Add a CRC32 "hash" of each source value to the extracted JSON (standard mode, json format)
+    #[arg(long, requires = "extract_file")]
+    with_hash: bool,
+
+    /// Extract only entries that are new or changed compared to this earlier extract JSON (standard mode, json format)
+    #[arg(long, value_name = "OLD_JSON", requires = "extract_file")]
+    baseline: Option<PathBuf>,
+
+    /// Extract only strings of entries without children, keeping global indices (standard mode, json format)
+    #[arg(long, requires = "extract_file")]
+    leaves_only: bool,
+
+    /// Extract or update the variables of this type instead of strings; numbers are written as strings (standard mode, json format)
+    #[arg(long, value_enum, value_name = "TYPE", default_value_t = VarTypeArg::String)]
+    var_type: VarTypeArg,
+
+    /// JSON object of key renames applied before saving: {"OLD_NAME" or "0x<crc>": "NEW_NAME"} (standard mode)
+    #[arg(long, value_name = "MAP_JSON", requires = "write_file")]
+    rename_map: Option<PathBuf>,
+
+    /// Only update string slots under entries with this name (others keep the file's values; standard mode)
+    #[arg(long, value_name = "ENTRY_NAME", requires = "write_file")]
+    select_entry: Option<String>,
+
+    /// JSON lists only changed entries: apply those, keep every other slot, and reject unknown indices (standard mode)
+    #[arg(long, requires = "write_file")]
+    sparse: bool,
+
+    /// Pad the written file with BYTE (default 0xFF) up to the input cfg.bin's length; fails if it is larger
+    #[arg(long, value_name = "BYTE", num_args = 0..=1, default_missing_value = "0xFF", value_parser = parse_byte, requires = "write_file")]
+    pad_to_original: Option<u8>,
+
+    /// Save in this encoding instead of the source's, re-encoding every string and key (standard mode)
+    #[arg(long, value_enum, value_name = "ENCODING", requires = "write_file")]
+    output_encoding: Option<EncodingArg>,
+
+    /// Remove entries left with only null/empty strings and no children before saving; blocks are kept (standard mode)
+    #[arg(long, requires = "write_file")]
+    strip_empty_entries: bool,
+
+    /// Write this u16 (e.g. 0x01FE) as the footer field before the encoding instead of the source file's (standard mode)
+    #[arg(long, value_name = "U16", value_parser = parse_u16, requires = "write_file")]
+    footer_unk1: Option<u16>,
+
+    /// Write this u16 (e.g. 1) as the footer field after the encoding instead of the source file's (standard mode)
+    #[arg(long, value_name = "U16", value_parser = parse_u16, requires = "write_file")]
+    footer_unk2: Option<u16>,
+
+    /// Parse JSON input as JSON5, allowing comments and trailing commas (implied for .json5 files)
+    #[arg(long, requires = "write_file")]
+    json5: bool,
+
+    /// After writing, print each string slot the update changed as index/entry: "old" -> "new"
+    #[arg(long, requires = "write_file", conflicts_with = "strip_empty_entries")]
+    show_changes: bool,
+
+    /// After writing, save the changed slots as JSON here (a directory with --input-glob, one <input>.changes.json per file)
+    #[arg(
+        long,
+        value_name = "PATH",
+        requires = "write_file",
+        conflicts_with = "strip_empty_entries"
+    )]
+    show_changes_json: Option<PathBuf>,
+
+    /// Save with the encoding and footer fields of this cfg.bin, e.g. a sibling the game accepts (standard mode)
+    #[arg(
+        long,
+        value_name = "REFERENCE_CFG_BIN",
+        requires = "write_file",
+        conflicts_with_all = ["output_encoding", "footer_unk1", "footer_unk2"]
+    )]
+    assume_encoding_from: Option<PathBuf>,
+
+    /// The cfg.bin is embedded in a larger file at this byte offset (e.g. 0x1A00; use with --length)
+    #[arg(long, value_name = "N", value_parser = parse_size, requires = "length", conflicts_with = "input_glob")]
+    offset: Option<usize>,
+
+    /// Byte length of the embedded cfg.bin (use with --offset)
+    #[arg(long, value_name = "M", value_parser = parse_size, requires = "offset")]
+    length: Option<usize>,
+
+    /// Update: splice the rebuilt cfg.bin back into a copy of the container at --offset; its length must not change
+    #[arg(long, requires_all = ["offset", "write_file"])]
+    in_container: bool,
 }
 
-fn main() -> Result<()> {
-    let cli = Cli::parse();
+// A cfg.bin embedded in a larger file (--offset/--length).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct EmbeddedRange {
+    offset: usize,
+    length: usize,
+}
 
-    if let Some(cfg_path) = cli.extract_file {
-        extract(&cfg_path, cli.mode, cli.extract_format)?;
-    } else if let Some(cfg_path) = cli.write_file {
-        let input_path = cli.json_file.unwrap();
-        let out_path = cli.output_file.unwrap_or_else(|| cfg_path.clone());
-        update(
-            &cfg_path,
-            &input_path,
-            &out_path,
-            cli.mode,
-            cli.update_format,
-        )?;
-    } else {
-        eprintln!("Usage:");
-        eprintln!("  Extract: cfg_bin_text_editor -e <file.cfg.bin>");
-        eprintln!("  Update:  cfg_bin_text_editor -w <file.cfg.bin> <input.json|input.txt>");
-        eprintln!("  Update:  cfg_bin_text_editor -w <file.cfg.bin> <input.json|input.txt> -o <output.cfg.bin>");
-        eprintln!("  Mode:    --mode standard|nnk");
-        eprintln!("  Format:  --extract-format json|txt --update-format json|txt");
-        std::process::exit(1);
+impl EmbeddedRange {
+    fn slice<'a>(&self, container: &'a [u8]) -> Result<&'a [u8]> {
+        self.offset
+            .checked_add(self.length)
+            .and_then(|end| container.get(self.offset..end))
+            .with_context(|| {
+                format!(
+                    "--offset 0x{:X} --length 0x{:X} is outside the {}-byte input file",
+                    self.offset,
+                    self.length,
+                    container.len()
+                )
+            })
     }
 
-    Ok(())
+    // A copy of `container` with `blob` in place of the embedded bytes.
+    fn splice(&self, container: &[u8], blob: &[u8]) -> Result<Vec<u8>> {
+        self.slice(container)?;
+        if blob.len() != self.length {
+            bail!(
+                "Rebuilt cfg.bin doesn't fit its slot in the container ({}); use --pad-to-original, or write just the cfg.bin without --in-container",
+                format_size_change(self.length, blob.len())
+            );
+        }
+        let mut spliced = container.to_vec();
+        spliced[self.offset..self.offset + self.length].copy_from_slice(blob);
+        Ok(spliced)
+    }
 }
 
-fn normalize_txt_line(s: &str) -> String {
-    let mut out = String::with_capacity(s.len());
-    for ch in s.chars() {
-        match ch {
-            '\\' => out.push_str("\\\\"),
-            '\r' => out.push_str("\\r"),
-            '\n' => out.push_str("\\n"),
-            _ => out.push(ch),
-        }
+// Reads `path`, or just the embedded cfg.bin in it.
+fn read_cfg_file(path: &Path, embedded: Option<EmbeddedRange>) -> Result<Vec<u8>> {
+    let data = fs::read(path).context("Failed to read cfg.bin file")?;
+    match embedded {
+        Some(range) => Ok(range.slice(&data)?.to_vec()),
+        None => Ok(data),
     }
-    out
 }
 
-fn decode_txt_line(s: &str) -> String {
-    let mut out = String::with_capacity(s.len());
-    let mut chars = s.chars().peekable();
+struct ExtractOptions {
+    verbose: bool,
+    json_events: bool,
+    with_hash: bool,
+    leaves_only: bool,
+    var_type: VarType,
+    baseline: Option<PathBuf>,
+    mode: Mode,
+    extract_format: ExtractFormat,
+    multiline_txt: bool,
+    txt_indexed: bool,
+    collapse_trailing_ws: bool,
+    header_lines: usize,
+    trailing_newline: bool,
+    line_ending: LineEnding,
+    normalize: Option<Normalization>,
+    embedded: Option<EmbeddedRange>,
+    open: OpenOptions,
+}
 
-    while let Some(ch) = chars.next() {
-        if ch != '\\' {
-            out.push(ch);
-            continue;
-        }
+struct UpdateOptions {
+    verbose: bool,
+    json_events: bool,
+    open: OpenOptions,
+    mode: Mode,
+    // None means detect per input file (see `resolve_update_format`)
+    update_format: Option<UpdateFormat>,
+    multiline_txt: bool,
+    txt_indexed: bool,
+    collapse_trailing_ws: bool,
+    preserve_key_order: bool,
+    no_dedup_strings: bool,
+    reencode_strings: bool,
+    rename_map: Option<BTreeMap<String, String>>,
+    select_entry: Option<String>,
+    var_type: VarType,
+    sparse: bool,
+    json5: bool,
+    pad_to_original: Option<u8>,
+    strip_empty_entries: bool,
+    output_encoding: Option<CfgBinEncoding>,
+    footer_unk1: Option<u16>,
+    footer_unk2: Option<u16>,
+    // From --assume-encoding-from
+    reference_footer: Option<FooterSettings>,
+    show_changes: bool,
+    show_changes_json: Option<ChangesJson>,
+    normalize: Option<Normalization>,
+    embedded: Option<EmbeddedRange>,
+    // Write the rebuilt cfg.bin back into the container `embedded` points into
+    in_container: bool,
+}
 
-        match chars.next() {
-            Some('n') => out.push('\n'),
-            Some('r') => out.push('\r'),
-            Some('t') => out.push('\t'),
-            Some('\\') => out.push('\\'),
-            Some(other) => {
-                out.push('\\');
-                out.push(other);
+// Project file looked up in the working directory when --config isn't given.
+const PROJECT_CONFIG_FILE: &str = ".cfgbin.toml";
+
+// Defaults from the project file. Enum values are parsed like the matching flags (aliases
+// included); a flag given on the command line always wins over the file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct ProjectConfig {
+    mode: Option<String>,
+    extract_format: Option<String>,
+    update_format: Option<String>,
+    string_format: Option<String>,
+    endian: Option<String>,
+    normalize: Option<String>,
+    encoding: Option<String>,
+    select_entry: Option<String>,
+    line_ending: Option<String>,
+    multiline_txt: Option<bool>,
+    txt_indexed: Option<bool>,
+    preserve_key_order: Option<bool>,
+}
+
+fn load_project_config(explicit: Option<&Path>) -> Result<Option<ProjectConfig>> {
+    let path = match explicit {
+        Some(path) => path.to_path_buf(),
+        None => {
+            let default = PathBuf::from(PROJECT_CONFIG_FILE);
+            if !default.is_file() {
+                return Ok(None);
             }
-            None => out.push('\\'),
+            default
         }
-    }
+    };
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read config file {}", path.display()))?;
+    let config = parse_project_config(&content)
+        .with_context(|| format!("Invalid config file {}", path.display()))?;
+    Ok(Some(config))
+}
 
-    out
+fn parse_project_config(content: &str) -> Result<ProjectConfig> {
+    Ok(toml::from_str(content)?)
 }
 
-fn read_txt_lines(input_path: &PathBuf) -> Result<Vec<String>> {
-    let raw = fs::read(input_path).context("Failed to read TXT file")?;
-    let mut content = String::from_utf8(raw).context("TXT file must be UTF-8")?;
-    if content.starts_with('\u{FEFF}') {
-        content.remove(0);
+fn apply_project_config(cli: &mut Cli, matches: &ArgMatches, config: &ProjectConfig) -> Result<()> {
+    // Global flags written after a subcommand are recorded on the subcommand's matches.
+    let levels: Vec<&ArgMatches> = std::iter::once(matches)
+        .chain(matches.subcommand().map(|(_, sub)| sub))
+        .collect();
+    let unset = |id: &str| {
+        !levels.iter().any(|m| {
+            m.ids().any(|i| i == id) && m.value_source(id) == Some(ValueSource::CommandLine)
+        })
+    };
+    if let (Some(v), true) = (&config.mode, unset("mode")) {
+        cli.mode = v.parse()?;
+        // `find` has its own --mode.
+        if let Some(Command::Find { mode, .. }) = &mut cli.command {
+            *mode = cli.mode;
+        }
     }
-    if content.is_empty() {
-        return Ok(Vec::new());
+    if let (Some(v), true) = (&config.extract_format, unset("extract_format")) {
+        cli.extract_format = v.parse()?;
+    }
+    if let (Some(v), true) = (&config.update_format, unset("update_format")) {
+        cli.update_format = Some(v.parse()?);
+    }
+    if let (Some(v), true) = (&config.string_format, unset("string_format")) {
+        cli.string_format = parse_value_enum(v, "string format", &[])?;
+    }
+    if let (Some(v), true) = (&config.endian, unset("endian")) {
+        cli.endian = parse_value_enum(
+            v,
+            "byte order",
+            &[("big", EndianArg::Be), ("little", EndianArg::Le)],
+        )?;
+    }
+    if let (Some(v), true) = (&config.normalize, unset("normalize")) {
+        cli.normalize = Some(parse_value_enum(v, "normalization form", &[])?);
+    }
+    // --auto-reencode on the command line also overrides a configured encoding.
+    if let (Some(v), true) = (&config.encoding, unset("encoding") && !cli.auto_reencode) {
+        let aliases = [
+            ("shiftjis", EncodingArg::ShiftJis),
+            ("sjis", EncodingArg::ShiftJis),
+            ("utf-8", EncodingArg::Utf8),
+            ("utf-16le", EncodingArg::Utf16le),
+        ];
+        cli.encoding = parse_value_enum(v, "encoding", &aliases)?;
+    }
+    if let (Some(v), true) = (&config.select_entry, unset("select_entry")) {
+        if cli.write_file.is_some() {
+            cli.select_entry = Some(v.clone());
+        }
+    }
+    if let (Some(v), true) = (&config.line_ending, unset("line_ending")) {
+        cli.line_ending = parse_value_enum(v, "line ending", &[])?;
     }
+    // An on/off pair is left to the command line if either form is given there.
+    let unset_pair = |id: &str| unset(id) && unset(&format!("no_{}", id));
+    if let (Some(v), true) = (config.multiline_txt, unset_pair("multiline_txt")) {
+        cli.multiline_txt = v;
+    }
+    if let (Some(v), true) = (config.txt_indexed, unset_pair("txt_indexed")) {
+        cli.txt_indexed = v;
+    }
+    if let (Some(v), true) = (config.preserve_key_order, unset_pair("preserve_key_order")) {
+        cli.preserve_key_order = v;
+    }
+    if cli.multiline_txt && cli.txt_indexed {
+        bail!("--multiline-txt and --txt-indexed can't be combined (check the config file)");
+    }
+    Ok(())
+}
 
-    content = content.replace("\r\n", "\n").replace('\r', "\n");
-    let has_trailing_newline = content.ends_with('\n');
-    let mut lines: Vec<&str> = content.split('\n').collect();
-    if has_trailing_newline && lines.last() == Some(&"") {
-        lines.pop();
+fn parse_cli() -> Result<Cli> {
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    if let Some(config) = load_project_config(cli.config.as_deref())? {
+        apply_project_config(&mut cli, &matches, &config)?;
     }
+    Ok(cli)
+}
 
-    Ok(lines.into_iter().map(decode_txt_line).collect())
+fn main() -> Result<()> {
+    let cli = parse_cli()?;
+    let abort_on_warning = cli.abort_on_warning;
+    let mut warnings = Warnings::new(cli.quiet, cli.warnings_json.is_some() || abort_on_warning);
+    let warnings_json = cli.warnings_json.clone();
+    let result = run(cli, &mut warnings);
+    let collected = warnings.into_collected();
+    // Written even when the run fails, so a batch QA step still sees what was reported.
+    if let Some(path) = warnings_json {
+        warnings::write_json(&path, &collected)?;
+    }
+    result?;
+    if abort_on_warning && !collected.is_empty() {
+        bail!(
+            "{} warning(s) reported ({}); failing because of --abort-on-warning",
+            collected.len(),
+            warnings::summary(&collected)
+        );
+    }
+    Ok(())
 }
 
-fn is_datetime_timestamp_line(s: &str) -> bool {
-    let b = s.as_bytes();
-    if b.len() != 19 {
-        return false;
+fn run(cli: Cli, warnings: &mut Warnings) -> Result<()> {
+    let embedded = cli
+        .offset
+        .zip(cli.length)
+        .map(|(offset, length)| EmbeddedRange { offset, length });
+    let open_options = OpenOptions {
+        ignore_unknown_crc: cli.ignore_unknown_crc,
+        string_format: cli.string_format.into(),
+        entries_count: cli.entries_count,
+        encoding: cli.encoding.forced(),
+        auto_encoding: cli.auto_reencode,
+        endian: cli.endian.into(),
+    };
+    let mode = match &cli.command {
+        Some(Command::Find { mode, .. }) => *mode,
+        _ => cli.mode,
+    };
+    if mode == Mode::Nnk && open_options.string_format != StringFormat::NullTerminated {
+        bail!("--string-format len-prefixed is only supported in standard mode");
     }
-    let is_digit = |c: u8| c.is_ascii_digit();
-    is_digit(b[0])
-        && is_digit(b[1])
-        && is_digit(b[2])
-        && is_digit(b[3])
-        && b[4] == b'/'
-        && is_digit(b[5])
-        && is_digit(b[6])
-        && b[7] == b'/'
-        && is_digit(b[8])
-        && is_digit(b[9])
-        && b[10] == b' '
-        && is_digit(b[11])
-        && is_digit(b[12])
-        && b[13] == b':'
-        && is_digit(b[14])
-        && is_digit(b[15])
-        && b[16] == b':'
-        && is_digit(b[17])
-        && is_digit(b[18])
+    if mode == Mode::Nnk && (open_options.encoding.is_some() || cli.auto_reencode) {
+        bail!("--encoding and --auto-reencode are only supported in standard mode");
+    }
+    if mode == Mode::Nnk && open_options.endian != Endian::Little {
+        bail!("--endian be is only supported in standard mode");
+    }
+    if mode == Mode::Nnk && open_options.entries_count.is_some() {
+        bail!("--entries-count is only supported in standard mode");
+    }
+    if mode == Mode::Nnk && matches!(cli.command, Some(Command::Replace { .. })) {
+        bail!("replace is only supported in standard mode");
+    }
+    if mode == Mode::Nnk && matches!(cli.command, Some(Command::Validate { .. })) {
+        bail!("validate is only supported in standard mode");
+    }
+
+    if let Some(command) = cli.command {
+        return match command {
+            Command::Info {
+                cfg_file,
+                report_unused_keys,
+                count_variables_by_type,
+                dump_raw_strings,
+                dump_offsets,
+                detect_encoding,
+            } => {
+                let reports = InfoReports {
+                    unused_keys: report_unused_keys,
+                    variable_types: count_variables_by_type,
+                    raw_strings: dump_raw_strings,
+                    offsets: dump_offsets,
+                    encoding: detect_encoding,
+                };
+                info(&cfg_file, cli.verbose, &reports, &open_options, warnings)
+            }
+            Command::Find {
+                cfg_file,
+                pattern,
+                regex,
+                case_sensitive,
+                mode,
+            } => {
+                let matcher = build_find_matcher(&pattern, regex, case_sensitive)?;
+                find(&cfg_file, &matcher, mode, &open_options, warnings)
+            }
+            Command::Check {
+                cfg_files,
+                strict,
+                verify,
+                preserve_key_order,
+            } => {
+                let verify = verify.then_some(preserve_key_order || cli.preserve_key_order);
+                check(&cfg_files, strict, verify, &open_options, warnings)
+            }
+            Command::Audit { cfg_file, json } => {
+                audit(&cfg_file, json.as_deref(), &open_options, warnings)
+            }
+            Command::Validate {
+                cfg_file,
+                translation,
+                max_length,
+                sparse,
+                var_type,
+                select_entry,
+                json5,
+            } => {
+                let options = ValidateOptions {
+                    sparse,
+                    max_length,
+                    var_type: var_type.into(),
+                    select_entry,
+                    json5,
+                };
+                validate(&cfg_file, &translation, &options, &open_options, warnings)
+            }
+            Command::CompareModes { cfg_file, json } => {
+                compare_modes(&cfg_file, json.as_deref(), &open_options, warnings)
+            }
+            Command::MakePatch {
+                cfg_file,
+                edited,
+                output,
+            } => make_patch(&cfg_file, &edited, output, &open_options, warnings),
+            Command::ApplyPatch {
+                cfg_file,
+                patch,
+                output,
+            } => apply_patch(&cfg_file, &patch, output, &open_options, warnings),
+            Command::Timestamp {
+                cfg_file,
+                set,
+                output,
+            } => timestamp(&cfg_file, set.as_deref(), output, &open_options, warnings),
+            Command::Replace {
+                cfg_file,
+                from,
+                to,
+                regex,
+                dry_run,
+                output,
+                preserve_key_order,
+                no_dedup_strings,
+                output_encoding,
+                footer_unk1,
+                footer_unk2,
+                offset,
+                length,
+                in_container,
+            } => {
+                let matcher = build_find_matcher(&from, regex, true)?;
+                let request = ReplaceRequest {
+                    matcher,
+                    replacement: to,
+                    literal: !regex,
+                    dry_run,
+                };
+                let embedded = offset
+                    .zip(length)
+                    .map(|(offset, length)| EmbeddedRange { offset, length });
+                let out_path = output.unwrap_or_else(|| cfg_file.clone());
+                if embedded.is_some() && !in_container && out_path == cfg_file {
+                    bail!("Refusing to overwrite the container with just the embedded cfg.bin; pass -o or --in-container");
+                }
+                let options = UpdateOptions {
+                    verbose: cli.verbose,
+                    json_events: false,
+                    open: open_options,
+                    mode,
+                    update_format: None,
+                    multiline_txt: false,
+                    txt_indexed: false,
+                    collapse_trailing_ws: false,
+                    // The config file's preserve-key-order applies here too
+                    preserve_key_order: preserve_key_order || cli.preserve_key_order,
+                    no_dedup_strings,
+                    reencode_strings: false,
+                    rename_map: None,
+                    select_entry: None,
+                    var_type: VarType::String,
+                    sparse: false,
+                    json5: false,
+                    pad_to_original: None,
+                    strip_empty_entries: false,
+                    output_encoding: output_encoding.and_then(EncodingArg::forced),
+                    footer_unk1,
+                    footer_unk2,
+                    reference_footer: None,
+                    show_changes: false,
+                    show_changes_json: None,
+                    normalize: None,
+                    embedded,
+                    in_container,
+                };
+                replace(&cfg_file, &request, &out_path, &options, warnings)
+            }
+            Command::CrcCollisions { names_file } => crc_collisions(
+                &names_file,
+                open_options.encoding.unwrap_or(CfgBinEncoding::Utf8),
+            ),
+            Command::SharedStrings {
+                cfg_files,
+                min_files,
+                glossary,
+            } => shared_strings(
+                &cfg_files,
+                min_files,
+                glossary.as_deref(),
+                &open_options,
+                warnings,
+            ),
+        };
+    }
+
+    if cli.list_formats || cli.list_modes {
+        let capabilities = Capabilities::of_this_build(cli.list_modes, cli.list_formats);
+        println!("{}", serde_json::to_string_pretty(&capabilities)?);
+    } else if let Some(cfg_path) = cli.extract_file {
+        let options = ExtractOptions {
+            verbose: cli.verbose,
+            json_events: cli.json_events,
+            with_hash: cli.with_hash,
+            leaves_only: cli.leaves_only,
+            var_type: cli.var_type.into(),
+            baseline: cli.baseline,
+            mode: cli.mode,
+            extract_format: cli.extract_format,
+            multiline_txt: cli.multiline_txt,
+            txt_indexed: cli.txt_indexed,
+            collapse_trailing_ws: cli.collapse_trailing_ws,
+            header_lines: cli.header_lines.unwrap_or(0),
+            trailing_newline: cli.trailing_newline && !cli.no_trailing_newline,
+            line_ending: cli.line_ending,
+            normalize: cli.normalize,
+            embedded,
+            open: open_options,
+        };
+        let result = extract(&cfg_path, &options, warnings);
+        if let (true, Err(e)) = (options.json_events, &result) {
+            emit_event(&FileEvent::error(Some(&cfg_path), e));
+        }
+        result?;
+    } else if let Some(cfg_path) = cli.write_file {
+        if cli.rename_map.is_some() && cli.mode == Mode::Nnk {
+            bail!("--rename-map is only supported in standard mode");
+        }
+        if cli.select_entry.is_some() && cli.mode == Mode::Nnk {
+            bail!("--select-entry is only supported in standard mode");
+        }
+        if cli.sparse && cli.mode == Mode::Nnk {
+            bail!("--sparse is only supported in standard mode");
+        }
+        if cli.no_dedup_strings && cli.mode == Mode::Nnk {
+            bail!("--no-dedup-strings is only supported in standard mode");
+        }
+        if cli.reencode_strings && cli.mode == Mode::Nnk {
+            bail!("--reencode-strings is only supported in standard mode");
+        }
+        if cli.output_encoding.is_some() && cli.mode == Mode::Nnk {
+            bail!("--output-encoding is only supported in standard mode");
+        }
+        if cli.strip_empty_entries && cli.mode == Mode::Nnk {
+            bail!("--strip-empty-entries is only supported in standard mode");
+        }
+        if (cli.footer_unk1.is_some() || cli.footer_unk2.is_some()) && cli.mode == Mode::Nnk {
+            bail!("--footer-unk1 and --footer-unk2 are only supported in standard mode");
+        }
+        if cli.assume_encoding_from.is_some() && cli.mode == Mode::Nnk {
+            bail!("--assume-encoding-from is only supported in standard mode");
+        }
+        let reference_footer = cli
+            .assume_encoding_from
+            .as_deref()
+            .map(|path| FooterSettings::read(path, &open_options))
+            .transpose()?;
+        let rename_map = cli.rename_map.as_deref().map(read_rename_map).transpose()?;
+        let options = UpdateOptions {
+            verbose: cli.verbose,
+            json_events: cli.json_events,
+            open: open_options,
+            mode: cli.mode,
+            update_format: cli.update_format,
+            multiline_txt: cli.multiline_txt,
+            txt_indexed: cli.txt_indexed,
+            collapse_trailing_ws: cli.collapse_trailing_ws,
+            preserve_key_order: cli.preserve_key_order,
+            no_dedup_strings: cli.no_dedup_strings,
+            reencode_strings: cli.reencode_strings,
+            rename_map,
+            select_entry: cli.select_entry,
+            var_type: cli.var_type.into(),
+            sparse: cli.sparse,
+            json5: cli.json5,
+            pad_to_original: cli.pad_to_original,
+            strip_empty_entries: cli.strip_empty_entries,
+            output_encoding: cli.output_encoding.and_then(EncodingArg::forced),
+            footer_unk1: cli.footer_unk1,
+            footer_unk2: cli.footer_unk2,
+            reference_footer,
+            show_changes: cli.show_changes,
+            show_changes_json: cli.show_changes_json.map(|path| {
+                if cli.input_glob.is_some() {
+                    ChangesJson::PerInput(path)
+                } else {
+                    ChangesJson::File(path)
+                }
+            }),
+            normalize: cli.normalize,
+            embedded,
+            in_container: cli.in_container,
+        };
+        if let Some(ChangesJson::PerInput(dir)) = &options.show_changes_json {
+            fs::create_dir_all(dir).context("Failed to create --show-changes-json directory")?;
+        }
+        if let Some(pattern) = cli.input_glob {
+            batch_update(
+                &cfg_path,
+                &pattern,
+                cli.out_dir.as_deref(),
+                cli.fail_fast,
+                &options,
+                warnings,
+            )?;
+        } else {
+            let input_path = cli.json_file.unwrap();
+            let out_path = cli.output_file.unwrap_or_else(|| cfg_path.clone());
+            if embedded.is_some() && !cli.in_container && out_path == cfg_path {
+                bail!("Refusing to overwrite the container with just the embedded cfg.bin; pass -o or --in-container");
+            }
+            let result = update(&cfg_path, &input_path, &out_path, &options, warnings);
+            if let (true, Err(e)) = (options.json_events, &result) {
+                emit_event(&FileEvent::error(Some(&cfg_path), e).with_translation(&input_path));
+            }
+            result?;
+        }
+    } else {
+        // Nothing to do: a short hint on stderr and a failing exit code; `--help` exits 0.
+        eprintln!("{}", Cli::command().render_usage());
+        eprintln!();
+        eprintln!("{}", USAGE_EXAMPLES);
+        eprintln!();
+        eprintln!("Run with --help for all options.");
+        std::process::exit(1);
+    }
+
+    Ok(())
 }
 
-fn resolve_txt_update_offset(
-    expected: usize,
-    actual: usize,
-    first_original_line: Option<&str>,
-    input_path: &PathBuf,
-) -> Result<usize> {
-    if expected == actual {
-        return Ok(0);
+// One `--json-events` line: the cfg.bin and translation that were read (each when known), and
+// the written output or the error.
+#[derive(Serialize)]
+struct FileEvent {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cfg_bin: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    translation: Option<String>,
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    entries: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    input_bytes: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_bytes: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    input_regions: Option<RegionSizes>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_regions: Option<RegionSizes>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    changed_slots: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+}
+
+impl FileEvent {
+    fn ok(cfg_path: &Path, output: &Path, entries: usize) -> Self {
+        FileEvent {
+            cfg_bin: Some(cfg_path.display().to_string()),
+            translation: None,
+            status: "ok",
+            output: Some(output.display().to_string()),
+            entries: Some(entries),
+            input_bytes: None,
+            output_bytes: None,
+            input_regions: None,
+            output_regions: None,
+            changed_slots: None,
+            message: None,
+        }
     }
 
-    let can_skip_three_header_lines = expected >= 3
-        && expected - 3 == actual
-        && first_original_line.is_some_and(is_datetime_timestamp_line);
+    fn with_translation(mut self, translation: &Path) -> Self {
+        self.translation = Some(translation.display().to_string());
+        self
+    }
 
-    if can_skip_three_header_lines {
-        return Ok(3);
+    fn with_sizes(mut self, input_bytes: usize, output_bytes: usize) -> Self {
+        self.input_bytes = Some(input_bytes);
+        self.output_bytes = Some(output_bytes);
+        self
     }
 
-    if first_original_line.is_some_and(is_datetime_timestamp_line) {
-        bail!(
-            "Line count mismatch in {}: expected {} (or {} when skipping 3 metadata lines), got {}. Keep one line per text entry and represent embedded newlines as \\n.",
-            input_path.display(),
-            expected,
-            expected.saturating_sub(3),
-            actual
-        );
+    fn with_regions(mut self, input: RegionSizes, output: RegionSizes) -> Self {
+        self.input_regions = Some(input);
+        self.output_regions = Some(output);
+        self
     }
 
-    bail!(
-        "Line count mismatch in {}: expected {}, got {}. Keep one line per text entry and represent embedded newlines as \\n.",
-        input_path.display(),
-        expected,
-        actual
+    fn error(cfg_path: Option<&Path>, error: &anyhow::Error) -> Self {
+        FileEvent {
+            cfg_bin: cfg_path.map(|p| p.display().to_string()),
+            translation: None,
+            status: "error",
+            output: None,
+            entries: None,
+            input_bytes: None,
+            output_bytes: None,
+            input_regions: None,
+            output_regions: None,
+            changed_slots: None,
+            message: Some(format!("{:#}", error)),
+        }
+    }
+}
+
+fn emit_event(event: &FileEvent) {
+    println!(
+        "{}",
+        serde_json::to_string(event).expect("event serializes")
     );
 }
 
-fn extract(cfg_path: &PathBuf, mode: Mode, extract_format: ExtractFormat) -> Result<()> {
-    let data = fs::read(cfg_path).context("Failed to read cfg.bin file")?;
-    let (content, out_path, count) = match (mode, extract_format) {
-        (Mode::Standard, ExtractFormat::Json) => {
-            let cfg = CfgBin::open(&data).context("Failed to parse cfg.bin file")?;
-            let texts = cfg.extract_texts();
-            let json =
-                serde_json::to_string_pretty(&texts).context("Failed to serialize to JSON")?;
-            (json, format!("{}.json", cfg_path.display()), texts.len())
-        }
-        (Mode::Standard, ExtractFormat::Txt) => {
-            let cfg = CfgBin::open(&data).context("Failed to parse cfg.bin file")?;
-            let texts = cfg.extract_texts();
-            let lines: Vec<String> = texts.iter().map(|t| normalize_txt_line(&t.value)).collect();
-            (
-                lines.join("\n"),
-                format!("{}.txt", cfg_path.display()),
-                texts.len(),
-            )
-        }
-        (Mode::Nnk, ExtractFormat::Json) => {
-            let texts = CfgBin::extract_texts_by_address_for_json(&data)
-                .context("Failed to parse cfg.bin file in nnk mode")?;
-            let json =
-                serde_json::to_string_pretty(&texts).context("Failed to serialize to JSON")?;
-            (json, format!("{}.json", cfg_path.display()), texts.len())
+// A byte as "0xFF" or "255".
+fn parse_byte(s: &str) -> Result<u8> {
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.context(format!("Invalid byte '{}': expected 0x00-0xFF or 0-255", s))
+}
+
+// A byte offset or length, decimal or 0x-prefixed hex.
+fn parse_size(s: &str) -> Result<usize> {
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.context(format!(
+        "Invalid size '{}': expected a number such as 4096 or 0x1000",
+        s
+    ))
+}
+
+fn parse_u16(s: &str) -> Result<u16> {
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.context(format!(
+        "Invalid value '{}': expected 0x0000-0xFFFF or 0-65535",
+        s
+    ))
+}
+
+// Where --show-changes-json writes.
+enum ChangesJson {
+    File(PathBuf),
+    // Batch update: a directory with one `<input file name>.changes.json` per input
+    PerInput(PathBuf),
+}
+
+impl ChangesJson {
+    fn path_for(&self, input_path: &Path) -> PathBuf {
+        match self {
+            ChangesJson::File(path) => path.clone(),
+            ChangesJson::PerInput(dir) => {
+                let name = input_path.file_name().unwrap_or_default().to_string_lossy();
+                dir.join(format!("{}.changes.json", name))
+            }
         }
-        (Mode::Nnk, ExtractFormat::Txt) => {
-            let texts = CfgBin::extract_texts_by_address(&data)
-                .context("Failed to parse cfg.bin file in nnk mode")?;
-            let lines: Vec<String> = texts.values().map(|v| normalize_txt_line(v)).collect();
-            (
-                lines.join("\n"),
-                format!("{}.txt", cfg_path.display()),
-                texts.len(),
-            )
+    }
+}
+
+// Width a value is cut to in --show-changes lines.
+const CHANGE_PREVIEW_WIDTH: usize = 40;
+
+// `index/entry: "old" -> "new"`, each value escaped and cut to CHANGE_PREVIEW_WIDTH chars.
+fn format_change(change: &cfgbin::TextPatch) -> String {
+    let preview = |value: &str| {
+        let shown: String = value.chars().take(CHANGE_PREVIEW_WIDTH).collect();
+        if shown.len() < value.len() {
+            format!("{:?}...", shown)
+        } else {
+            format!("{:?}", shown)
         }
     };
-    fs::write(&out_path, &content).context("Failed to write extracted file")?;
-    println!("Extracted {} text entries to {}", count, out_path);
-    Ok(())
+    format!(
+        "{}/{}: {} -> {}",
+        change.index,
+        change.entry,
+        preview(&change.old),
+        preview(&change.new)
+    )
 }
 
-fn update(
-    cfg_path: &PathBuf,
-    input_path: &PathBuf,
-    out_path: &PathBuf,
+// The string slots that differ between the cfg.bin that was read and the one written,
+// matched by index.
+// Slots whose text differs between `data` and the `output` built from it, both read in the
+// `mode` that wrote it. nnk slots are numbered in address order and named by their address.
+fn written_changes(
+    data: &[u8],
+    output: &[u8],
+    open: &OpenOptions,
     mode: Mode,
-    update_format: UpdateFormat,
+) -> Result<Vec<cfgbin::TextPatch>> {
+    if mode == Mode::Nnk {
+        let original = nnk::extract(data)?;
+        let written = nnk::extract(output)
+            .context("Failed to reopen the written cfg.bin to list its changes")?;
+        let changes = original
+            .iter()
+            .enumerate()
+            .filter_map(|(index, (address, old))| {
+                let new = written.get(address).filter(|new| *new != old)?;
+                Some(cfgbin::TextPatch {
+                    index,
+                    entry: format!("0x{:08X}", address),
+                    variable_index: 0,
+                    old: old.clone(),
+                    new: new.clone(),
+                })
+            });
+        return Ok(changes.collect());
+    }
+    // The output names its own encoding, which --output-encoding may have changed.
+    let options = OpenOptions {
+        ignore_unknown_crc: true,
+        encoding: None,
+        auto_encoding: false,
+        ..open.clone()
+    };
+    let original = CfgBin::open_with_options(data, open)?;
+    let written = CfgBin::open_with_options(output, &options)
+        .context("Failed to reopen the written cfg.bin to list its changes")?;
+    Ok(original.diff_texts(&written.extract_texts()))
+}
+
+fn report_changes(
+    changes: &[cfgbin::TextPatch],
+    input_path: &Path,
+    options: &UpdateOptions,
 ) -> Result<()> {
-    let data = fs::read(cfg_path).context("Failed to read cfg.bin file")?;
-    let output = match (mode, update_format) {
-        (Mode::Standard, UpdateFormat::Json) => {
-            let json_data = fs::read_to_string(input_path).context("Failed to read JSON file")?;
-            let mut cfg = CfgBin::open(&data).context("Failed to parse cfg.bin file")?;
-            let texts: Vec<TextEntry> =
-                serde_json::from_str(&json_data).context("Failed to parse JSON file")?;
-            let text_count = texts.len();
-            cfg.update_texts(&texts);
-            let output = cfg.save();
-            println!(
-                "Written {} ({} text entries, mode=standard, update=json)",
-                out_path.display(),
-                text_count
-            );
-            output
+    if options.show_changes {
+        for change in changes {
+            status_line(options, &format_change(change));
         }
-        (Mode::Standard, UpdateFormat::Txt) => {
-            let mut cfg = CfgBin::open(&data).context("Failed to parse cfg.bin file")?;
-            let mut texts = cfg.extract_texts();
-            let expected = texts.len();
-            let lines = read_txt_lines(input_path)?;
-            let first_original_line = texts.first().map(|te| te.value.as_str());
-            let offset =
-                resolve_txt_update_offset(expected, lines.len(), first_original_line, input_path)?;
+        status_line(options, &format!("{} slot(s) changed", changes.len()));
+    }
+    if let Some(target) = &options.show_changes_json {
+        let path = target.path_for(input_path);
+        let json = serde_json::to_string_pretty(changes)?;
+        fs::write(&path, json).context("Failed to write --show-changes-json file")?;
+    }
+    Ok(())
+}
 
-            for (te, line) in texts.iter_mut().skip(offset).zip(lines.into_iter()) {
-                te.value = line;
-            }
+// Pads `output` with `byte` up to `length`, returning how many bytes were added.
+fn pad_to_length(mut output: Vec<u8>, length: usize, byte: u8) -> Result<(Vec<u8>, usize)> {
+    if output.len() > length {
+        bail!(
+            "Rebuilt file is {} bytes larger than the original, so it can't be padded to its size ({})",
+            output.len() - length,
+            format_size_change(length, output.len())
+        );
+    }
+    let added = length - output.len();
+    output.resize(length, byte);
+    Ok((output, added))
+}
 
-            cfg.update_texts(&texts);
-            let output = cfg.save();
-            println!(
-                "Written {} ({} text entries, mode=standard, update=txt)",
-                out_path.display(),
-                expected
-            );
-            output
+// "12,340 → 12,512 bytes (+172)"
+fn format_size_change(before: usize, after: usize) -> String {
+    let delta = if after == before {
+        "unchanged".to_string()
+    } else if after > before {
+        format!("+{}", group_thousands(after - before))
+    } else {
+        format!("-{}", group_thousands(before - after))
+    };
+    format!(
+        "{} → {} bytes ({})",
+        group_thousands(before),
+        group_thousands(after),
+        delta
+    )
+}
+
+// "entries 1,024 → 1,024 bytes (unchanged), string table 2,048 → 2,112 bytes (+64), ..."
+fn format_region_changes(before: &RegionSizes, after: &RegionSizes) -> String {
+    format!(
+        "entries {}, string table {}, key table {}",
+        format_size_change(before.entries, after.entries),
+        format_size_change(before.string_table, after.string_table),
+        format_size_change(before.key_table, after.key_table)
+    )
+}
+
+fn group_thousands(n: usize) -> String {
+    let digits = n.to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
         }
-        (Mode::Nnk, UpdateFormat::Json) => {
-            let json_data = fs::read_to_string(input_path).context("Failed to read JSON file")?;
-            let texts = CfgBin::parse_address_texts_json(&json_data)
-                .context("Failed to parse address-based JSON for nnk mode")?;
-            let text_count = texts.len();
-            let output = CfgBin::patch_texts_by_address_in_place(&data, &texts)
-                .context("Failed to patch cfg.bin in nnk mode")?;
-            println!(
-                "Written {} ({} text entries, mode=nnk, update=json)",
-                out_path.display(),
-                text_count
-            );
-            output
+        out.push(c);
+    }
+    out
+}
+
+fn value_name(value: impl ValueEnum) -> String {
+    value
+        .to_possible_value()
+        .map(|v| v.get_name().to_string())
+        .unwrap_or_default()
+}
+
+fn normalize_txt_line(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '\\' => out.push_str("\\\\"),
+            '\r' => out.push_str("\\r"),
+            '\n' => out.push_str("\\n"),
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+// Unknown escapes and a trailing backslash are kept verbatim, so decoding never fails.
+fn decode_txt_line(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
         }
-        (Mode::Nnk, UpdateFormat::Txt) => {
-            let mut texts = CfgBin::extract_texts_by_address(&data)
-                .context("Failed to parse cfg.bin file in nnk mode")?;
-            let expected = texts.len();
-            let lines = read_txt_lines(input_path)?;
-            let first_original_line = texts.values().next().map(String::as_str);
-            let offset =
-                resolve_txt_update_offset(expected, lines.len(), first_original_line, input_path)?;
 
-            for ((_, value), line) in texts.iter_mut().skip(offset).zip(lines.into_iter()) {
-                *value = line;
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('t') => out.push('\t'),
+            Some('\\') => out.push('\\'),
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
             }
+            None => out.push('\\'),
+        }
+    }
 
-            let output = CfgBin::patch_texts_by_address_in_place(&data, &texts)
-                .context("Failed to patch cfg.bin in nnk mode")?;
-            println!(
-                "Written {} ({} text entries, mode=nnk, update=txt)",
-                out_path.display(),
-                expected
-            );
-            output
+    out
+}
+
+// Whole TXT input as UTF-8 with the BOM stripped and every line ending normalized to `\n`.
+fn read_txt_content(input_path: &Path) -> Result<String> {
+    let raw = fs::read(input_path).context("Failed to read TXT file")?;
+    let mut content = String::from_utf8(raw).context("TXT file must be UTF-8")?;
+    if content.starts_with('\u{FEFF}') {
+        content.remove(0);
+    }
+    Ok(normalize_line_endings(&content))
+}
+
+fn normalize_line_endings(content: &str) -> String {
+    content.replace("\r\n", "\n").replace('\r', "\n")
+}
+
+fn update_format_from_extension(path: &Path) -> Option<UpdateFormat> {
+    let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+    if name.ends_with(".paths.json") {
+        return Some(UpdateFormat::Paths);
+    }
+    if name.ends_with(".full.json") {
+        return Some(UpdateFormat::Full);
+    }
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    match ext.as_str() {
+        "json" | "json5" => Some(UpdateFormat::Json),
+        "txt" => Some(UpdateFormat::Txt),
+        "csv" => Some(UpdateFormat::Csv),
+        _ => None,
+    }
+}
+
+// JSON input (standard array or nnk object) starts with `[` or `{`; anything else is TXT.
+fn sniff_update_format(content: &[u8]) -> UpdateFormat {
+    let content = content.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(content);
+    match content.iter().find(|b| !b.is_ascii_whitespace()) {
+        Some(b'[') | Some(b'{') => UpdateFormat::Json,
+        _ => UpdateFormat::Txt,
+    }
+}
+
+// An explicit --update-format wins; otherwise the extension decides, then the file content.
+fn resolve_update_format(
+    input_path: &Path,
+    explicit: Option<UpdateFormat>,
+) -> Result<UpdateFormat> {
+    if let Some(format) = explicit.or_else(|| update_format_from_extension(input_path)) {
+        return Ok(format);
+    }
+    let content = fs::read(input_path).context("Failed to read input file")?;
+    Ok(sniff_update_format(&content))
+}
+
+// JSON update input as read. JSON5 (by flag or `.json5` extension) is kept in its source form
+// and parsed as such, so errors quote the file that was edited.
+struct UpdateJson {
+    text: String,
+    json5: bool,
+}
+
+impl UpdateJson {
+    fn parse<T: DeserializeOwned>(&self) -> Result<T> {
+        if self.json5 {
+            parse_update_json5(&self.text)
+        } else {
+            parse_update_json(&self.text)
         }
+    }
+}
+
+fn read_update_json(input_path: &Path, json5: bool) -> Result<UpdateJson> {
+    let text = fs::read_to_string(input_path).context("Failed to read JSON file")?;
+    let is_json5_file = input_path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("json5"));
+    Ok(UpdateJson {
+        text,
+        json5: json5 || is_json5_file,
+    })
+}
+
+// serde_json's "at line L column C" alone is hard to act on in a large translation file, so
+// the error also quotes the offending line with a caret under the bad character.
+fn parse_update_json<T: DeserializeOwned>(json: &str) -> Result<T> {
+    serde_json::from_str(json).map_err(|e| {
+        let snippet = json_error_snippet(json, e.line(), e.column());
+        anyhow!("{}{}", e, snippet)
+    })
+}
+
+// serde_json counts lines from 1 and columns in bytes from 1; errors at end of input point
+// past the last line, so those quote the last line instead.
+fn json_error_snippet(json: &str, line: usize, column: usize) -> String {
+    const CONTEXT: usize = 40;
+    let lines: Vec<&str> = json.lines().collect();
+    let Some(number) = line.min(lines.len()).checked_sub(1) else {
+        return String::new();
     };
-    fs::write(out_path, &output).context("Failed to write cfg.bin file")?;
-    Ok(())
+    let text = lines[number];
+    let chars: Vec<char> = text.chars().collect();
+    let byte_column = if line > lines.len() {
+        text.len()
+    } else {
+        column.saturating_sub(1)
+    };
+    let at = text
+        .char_indices()
+        .take_while(|(i, _)| *i < byte_column)
+        .count()
+        .min(chars.len());
+    let start = at.saturating_sub(CONTEXT);
+    let end = (at + CONTEXT).min(chars.len());
+    let shown: String = chars[start..end].iter().collect();
+    let gutter = (number + 1).to_string();
+    format!(
+        "\n{} | {}{}{}\n{} | {}^",
+        gutter,
+        if start > 0 { "..." } else { "" },
+        shown,
+        if end < chars.len() { "..." } else { "" },
+        " ".repeat(gutter.len()),
+        " ".repeat(at - start + if start > 0 { 3 } else { 0 })
+    )
+}
+
+// json5 reports 1-based character columns; syntax errors already quote the line themselves.
+fn parse_update_json5<T: DeserializeOwned>(json5: &str) -> Result<T> {
+    let content = json5.strip_prefix('\u{FEFF}').unwrap_or(json5);
+    json5::from_str(content).map_err(|e| {
+        let json5::Error::Message { msg, location } = e;
+        let snippet = match location {
+            Some(at) if !msg.contains('\n') => {
+                let text = content.lines().nth(at.line.saturating_sub(1)).unwrap_or("");
+                let byte_column = text
+                    .char_indices()
+                    .nth(at.column.saturating_sub(1))
+                    .map_or(text.len(), |(i, _)| i);
+                let snippet = json_error_snippet(content, at.line, byte_column + 1);
+                format!(" at line {} column {}{}", at.line, at.column, snippet)
+            }
+            _ => String::new(),
+        };
+        anyhow!("{}{}", msg, snippet)
+    })
+}
+
+fn read_txt_lines(input_path: &Path) -> Result<Vec<String>> {
+    Ok(parse_txt_lines(&read_txt_content(input_path)?))
+}
+
+// A single trailing newline is optional, so files exported with or without one read the same.
+fn parse_txt_lines(content: &str) -> Vec<String> {
+    if content.is_empty() {
+        return Vec::new();
+    }
+
+    let has_trailing_newline = content.ends_with('\n');
+    let mut lines: Vec<&str> = content.split('\n').collect();
+    if has_trailing_newline && lines.last() == Some(&"") {
+        lines.pop();
+    }
+
+    lines.into_iter().map(decode_txt_line).collect()
+}
+
+// Multiline TXT: every entry is followed by a line holding only this marker, and values are
+// written verbatim, so embedded line breaks stay literal (CR and CRLF come back as LF).
+const TXT_RECORD_SEPARATOR: &str = "---";
+
+fn format_multiline_txt(values: &[&str]) -> Result<String> {
+    for (i, value) in values.iter().enumerate() {
+        let normalized = value.replace("\r\n", "\n").replace('\r', "\n");
+        if normalized
+            .split('\n')
+            .any(|line| line == TXT_RECORD_SEPARATOR)
+        {
+            bail!(
+                "Text entry {} contains a line \"{}\", which is the multiline TXT record separator; use the default TXT format for this file",
+                i,
+                TXT_RECORD_SEPARATOR
+            );
+        }
+    }
+    let records: Vec<String> = values
+        .iter()
+        .map(|value| format!("{}\n{}", value, TXT_RECORD_SEPARATOR))
+        .collect();
+    Ok(records.join("\n"))
+}
+
+// A last record without its closing separator (older exports) is read too, minus one final
+// line break; after the last separator only a final line break may follow.
+fn split_multiline_txt(content: &str) -> Vec<String> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    for line in content.split('\n') {
+        if line == TXT_RECORD_SEPARATOR {
+            records.push(std::mem::take(&mut record).join("\n"));
+        } else {
+            record.push(line);
+        }
+    }
+    if record.last() == Some(&"") {
+        record.pop();
+    }
+    if !record.is_empty() {
+        records.push(record.join("\n"));
+    }
+    records
+}
+
+// Marks the leading metadata lines of a TXT export (`--header-lines`). Update skips the
+// entries they stand for, so those keep the values of the cfg.bin file.
+const TXT_HEADER_MARKER: &str = "#cfgbin-header: ";
+
+fn format_txt_export(values: &[&str], options: &ExtractOptions) -> Result<String> {
+    let header_lines = options.header_lines;
+    if header_lines > values.len() {
+        bail!(
+            "--header-lines {} exceeds the {} text entries of the file",
+            header_lines,
+            values.len()
+        );
+    }
+    if values
+        .get(header_lines)
+        .is_some_and(|v| v.starts_with(TXT_HEADER_MARKER))
+    {
+        bail!(
+            "Text entry {} starts with the TXT header marker \"{}\" and would be read back as a header line",
+            header_lines,
+            TXT_HEADER_MARKER
+        );
+    }
+    let mut content = if options.multiline_txt {
+        format_multiline_txt(values)?
+    } else {
+        let lines: Vec<String> = values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| match options.txt_indexed {
+                true => format!("{}\t{}", i, normalize_txt_line(v)),
+                false if i < header_lines => {
+                    format!("{}{}", TXT_HEADER_MARKER, normalize_txt_line(v))
+                }
+                false => normalize_txt_line(v),
+            })
+            .collect();
+        lines.join("\n")
+    };
+    // An empty last line exists only through the line break before it, so that one is written
+    // even with --no-trailing-newline.
+    let empty_last_line = content.is_empty() || content.ends_with('\n');
+    if (options.trailing_newline || empty_last_line) && !values.is_empty() {
+        content.push('\n');
+    }
+    if options.line_ending.is_crlf() {
+        content = content.replace('\n', "\r\n");
+    }
+    Ok(content)
+}
+
+// TXT input lines, and how many marked header lines were dropped from the start.
+fn read_txt_input(input_path: &Path, options: &UpdateOptions) -> Result<(usize, Vec<String>)> {
+    if options.multiline_txt {
+        return Ok((0, split_multiline_txt(&read_txt_content(input_path)?)));
+    }
+    Ok(strip_txt_header(read_txt_lines(input_path)?))
+}
+
+fn strip_txt_header(mut lines: Vec<String>) -> (usize, Vec<String>) {
+    let header_lines = lines
+        .iter()
+        .take_while(|line| line.starts_with(TXT_HEADER_MARKER))
+        .count();
+    lines.drain(..header_lines);
+    (header_lines, lines)
+}
+
+// Indexed TXT (`--txt-indexed`): "<index>\t<value>" lines in any order. Indices must be below
+// `slot_count` and appear once; slots without a line keep their value.
+fn parse_indexed_txt(
+    content: &str,
+    input_path: &Path,
+    slot_count: usize,
+) -> Result<BTreeMap<usize, String>> {
+    let mut values = BTreeMap::new();
+    let mut first_line = BTreeMap::new();
+    for (i, line) in parse_txt_lines(content).into_iter().enumerate() {
+        let line_no = i + 1;
+        let (index, value) = line
+            .split_once('\t')
+            .and_then(|(index, value)| Some((index.parse::<usize>().ok()?, value)))
+            .ok_or_else(|| {
+                anyhow!(
+                    "{} line {}: expected \"<index>\\t<text>\" for indexed TXT",
+                    input_path.display(),
+                    line_no
+                )
+            })?;
+        if index >= slot_count {
+            bail!(
+                "{} line {}: index {} is out of range (the file has {} text entries)",
+                input_path.display(),
+                line_no,
+                index,
+                slot_count
+            );
+        }
+        if let Some(previous) = first_line.insert(index, line_no) {
+            bail!(
+                "{} line {}: index {} already appeared on line {}",
+                input_path.display(),
+                line_no,
+                index,
+                previous
+            );
+        }
+        values.insert(index, value.to_string());
+    }
+    Ok(values)
+}
+
+const TXT_PREVIEW_LINES: usize = 3;
+const TXT_PREVIEW_WIDTH: usize = 60;
+
+fn txt_preview_line(line_no: usize, line: &str) -> String {
+    let escaped = normalize_txt_line(line);
+    let shown: String = escaped.chars().take(TXT_PREVIEW_WIDTH).collect();
+    if shown.len() < escaped.len() {
+        format!("  {:>6}: {}...", line_no, shown)
+    } else {
+        format!("  {:>6}: {}", line_no, shown)
+    }
+}
+
+// First and last few lines of the TXT input, so a translator can see where a line was
+// added or dropped.
+fn txt_preview(lines: &[String]) -> String {
+    let mut out = String::new();
+    if lines.len() <= TXT_PREVIEW_LINES * 2 {
+        for (i, line) in lines.iter().enumerate() {
+            out.push('\n');
+            out.push_str(&txt_preview_line(i + 1, line));
+        }
+        return out;
+    }
+
+    for (i, line) in lines.iter().enumerate().take(TXT_PREVIEW_LINES) {
+        out.push('\n');
+        out.push_str(&txt_preview_line(i + 1, line));
+    }
+    out.push_str("\n     ...");
+    let tail_start = lines.len() - TXT_PREVIEW_LINES;
+    for (i, line) in lines.iter().enumerate().skip(tail_start) {
+        out.push('\n');
+        out.push_str(&txt_preview_line(i + 1, line));
+    }
+    out
+}
+
+fn is_datetime_timestamp_line(s: &str) -> bool {
+    let b = s.as_bytes();
+    if b.len() != 19 {
+        return false;
+    }
+    let is_digit = |c: u8| c.is_ascii_digit();
+    is_digit(b[0])
+        && is_digit(b[1])
+        && is_digit(b[2])
+        && is_digit(b[3])
+        && b[4] == b'/'
+        && is_digit(b[5])
+        && is_digit(b[6])
+        && b[7] == b'/'
+        && is_digit(b[8])
+        && is_digit(b[9])
+        && b[10] == b' '
+        && is_digit(b[11])
+        && is_digit(b[12])
+        && b[13] == b':'
+        && is_digit(b[14])
+        && is_digit(b[15])
+        && b[16] == b':'
+        && is_digit(b[17])
+        && is_digit(b[18])
+}
+
+// Number of leading slots the TXT lines don't cover: the marked header lines if there are
+// any, otherwise the timestamp heuristic.
+fn resolve_txt_update_offset(
+    expected: usize,
+    header_lines: usize,
+    lines: &[String],
+    first_original_line: Option<&str>,
+    input_path: &Path,
+) -> Result<usize> {
+    let actual = lines.len();
+    if header_lines > 0 {
+        if header_lines + actual != expected {
+            bail!(
+                "Line count mismatch in {}: expected {} after the {} header line(s), got {}. Keep one line per text entry and represent embedded newlines as \\n.\nInput preview:{}",
+                input_path.display(),
+                expected.saturating_sub(header_lines),
+                header_lines,
+                actual,
+                txt_preview(lines)
+            );
+        }
+        return Ok(header_lines);
+    }
+    if expected == actual {
+        return Ok(0);
+    }
+
+    let can_skip_three_header_lines = expected >= 3
+        && expected - 3 == actual
+        && first_original_line.is_some_and(is_datetime_timestamp_line);
+
+    if can_skip_three_header_lines {
+        return Ok(3);
+    }
+
+    if first_original_line.is_some_and(is_datetime_timestamp_line) {
+        bail!(
+            "Line count mismatch in {}: expected {} (or {} when skipping 3 metadata lines), got {}. Keep one line per text entry and represent embedded newlines as \\n.\nInput preview:{}",
+            input_path.display(),
+            expected,
+            expected.saturating_sub(3),
+            actual,
+            txt_preview(lines)
+        );
+    }
+
+    bail!(
+        "Line count mismatch in {}: expected {}, got {}. Keep one line per text entry and represent embedded newlines as \\n.\nInput preview:{}",
+        input_path.display(),
+        expected,
+        actual,
+        txt_preview(lines)
+    );
+}
+
+// Footer encoding 0 is Shift-JIS and 1 is plain UTF-8; anything else is a regional variant that
+// is read as UTF-8 and written back unchanged. UTF-16LE files write 2, which is detected when
+// the string table looks like UTF-16LE.
+fn note_footer_encoding(data: &[u8], endian: Endian) {
+    let (encoding, footer_encoding) = cfgbin::detect_encoding_with(data, endian);
+    if encoding == CfgBinEncoding::Utf8 && footer_encoding > 1 {
+        eprintln!(
+            "Note: footer encoding is 0x{:04X}, a UTF-8 variant; it is read as UTF-8 and preserved on save",
+            footer_encoding
+        );
+        if footer_encoding == cfgbin::FOOTER_ENCODING_UTF16LE {
+            eprintln!(
+                "Note: the strings don't look like UTF-16LE; if they are, pass --encoding utf16le"
+            );
+        }
+    }
+}
+
+// The `info --detect-encoding` report: `field: value` lines in a fixed order. The detected
+// encoding is the footer's; `encoding` is the one `cfg` was read with.
+fn encoding_lines(cfg_path: &Path, data: &[u8], cfg: &CfgBin) -> Vec<String> {
+    let (detected, _) = cfgbin::detect_encoding_with(data, cfg.endian);
+    vec![
+        format!("file: {}", cfg_path.display()),
+        format!("footer_encoding: 0x{:04X}", cfg.footer_encoding),
+        format!("detected_encoding: {}", encoding_name(detected)),
+        format!("encoding: {}", encoding_name(cfg.encoding)),
+        format!("entries: {}", cfg.node_count()),
+        format!("strings: {}", cfg.distinct_string_count()),
+    ]
+}
+
+fn open_cfg(data: &[u8], options: &OpenOptions, warnings: &mut Warnings) -> Result<CfgBin> {
+    let cfg = CfgBin::open_with_options(data, options).context("Failed to parse cfg.bin file")?;
+    for warning in open_warnings(data, &cfg, options) {
+        warnings.emit(warning);
+    }
+    Ok(cfg)
+}
+
+// Signs that a file parsed but probably not as intended.
+fn open_warnings(data: &[u8], cfg: &CfgBin, options: &OpenOptions) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    if let Some(warning) = encoding_warning(data, cfg, options) {
+        warnings.push(warning);
+    }
+    if !cfg.unresolved_crcs.is_empty() {
+        let crcs: Vec<String> = cfg
+            .unresolved_crcs
+            .iter()
+            .map(|crc| format!("0x{:08X}", crc))
+            .collect();
+        warnings.push(Warning::new(
+            "unknown-crc",
+            format!(
+                "{} CRC32 key(s) missing from the key table were named UNKNOWN_0x<crc>: {}",
+                crcs.len(),
+                crcs.join(", ")
+            ),
+        ));
+    }
+    if cfg.unparsed_entry_bytes > 0 {
+        warnings.push(Warning::new(
+            "unparsed-entries",
+            format!(
+                "{} bytes after the last parsed entry row were not read; the entry count may be too low (see --entries-count)",
+                cfg.unparsed_entry_bytes
+            ),
+        ));
+    }
+    if let Some(&first) = cfg.suspect_string_offsets.first() {
+        let other = match options.string_format {
+            StringFormat::NullTerminated => "len-prefixed",
+            StringFormat::LengthPrefixed => "null",
+        };
+        let message = format!(
+            "{} string(s) ran past the string table or contain control characters (first at string offset {}); the file may need --string-format {}",
+            cfg.suspect_string_offsets.len(),
+            first,
+            other
+        );
+        warnings.push(Warning::new("suspect-strings", message).with_offset(first as u32));
+    }
+    warnings
+}
+
+fn read_baseline(path: &Path) -> Result<Vec<TextEntry>> {
+    let json = fs::read_to_string(path).context("Failed to read baseline JSON")?;
+    serde_json::from_str(&json).with_context(|| {
+        format!(
+            "Failed to parse baseline {} (expected a standard-mode extract JSON)",
+            path.display()
+        )
+    })
+}
+
+// Entries whose value differs from, or is missing in, the baseline. Slots are matched by entry
+// name, variable index and the occurrence of that pair, so indices shifted by inserted entries
+// don't count as changes.
+fn changed_since_baseline(current: Vec<TextEntry>, baseline: &[TextEntry]) -> Vec<TextEntry> {
+    fn occurrence_keys(texts: &[TextEntry]) -> Vec<(String, usize, usize)> {
+        let mut seen: BTreeMap<(&str, usize), usize> = BTreeMap::new();
+        texts
+            .iter()
+            .map(|t| {
+                let n = seen
+                    .entry((t.entry.as_str(), t.variable_index))
+                    .or_default();
+                *n += 1;
+                (t.entry.clone(), t.variable_index, *n - 1)
+            })
+            .collect()
+    }
+    let old: BTreeMap<(String, usize, usize), &str> = occurrence_keys(baseline)
+        .into_iter()
+        .zip(baseline.iter().map(|t| t.value.as_str()))
+        .collect();
+    let keys = occurrence_keys(&current);
+    current
+        .into_iter()
+        .zip(keys)
+        .filter(|(t, key)| old.get(key) != Some(&t.value.as_str()))
+        .map(|(t, _)| t)
+        .collect()
+}
+
+// The footer's encoding garbles many strings: either --auto-reencode switched encodings, or
+// the user should be told to.
+fn encoding_warning(data: &[u8], cfg: &CfgBin, options: &OpenOptions) -> Option<Warning> {
+    if options.encoding.is_some() {
+        return None;
+    }
+    let (detected, _) = cfgbin::detect_encoding_with(data, options.endian);
+    let scores = CfgBin::encoding_scores(data, options.string_format, options.endian).ok()?;
+    let message = if cfg.encoding != detected {
+        format!(
+            "the footer names {} but the strings decode better as {} ({} vs {}); decoded as {}, and saving writes a {} footer",
+            encoding_name(detected),
+            encoding_name(cfg.encoding),
+            scores.anomalies(detected),
+            scores.anomalies(cfg.encoding),
+            encoding_name(cfg.encoding),
+            encoding_name(cfg.encoding)
+        )
+    } else {
+        let other = scores.better_alternative(detected)?;
+        format!(
+            "{} of {} non-ASCII strings look garbled as {} but only {} as {}; the encoding is probably mis-detected, re-run with --encoding {} or --auto-reencode",
+            scores.anomalies(detected),
+            scores.strings,
+            encoding_name(detected),
+            scores.anomalies(other),
+            encoding_name(other),
+            encoding_name(other)
+        )
+    };
+    Some(Warning::new("encoding", message))
+}
+
+fn open_cfg_for_extract(
+    data: &[u8],
+    options: &ExtractOptions,
+    warnings: &mut Warnings,
+) -> Result<CfgBin> {
+    let mut cfg = open_cfg(data, &options.open, warnings)?;
+    transform_extract_texts(&mut cfg, options)?;
+    Ok(cfg)
+}
+
+// Standard JSON extract values. `--with-hash` hashes the file's values as read, before
+// `--normalize` or `--collapse-trailing-ws` change them, so the hash still identifies the source
+// string on update.
+fn extract_json_values(
+    data: &[u8],
+    options: &ExtractOptions,
+    warnings: &mut Warnings,
+) -> Result<Vec<TextEntry>> {
+    let mut cfg = open_cfg(data, &options.open, warnings)?;
+    let source = cfg.extract_values(options.var_type, options.with_hash, options.leaves_only);
+    transform_extract_texts(&mut cfg, options)?;
+    let mut values = cfg.extract_values(options.var_type, false, options.leaves_only);
+    for (value, source) in values.iter_mut().zip(source) {
+        value.hash = source.hash;
+    }
+    Ok(values)
+}
+
+// The value rewrites an extract applies after opening.
+fn transform_extract_texts(cfg: &mut CfgBin, options: &ExtractOptions) -> Result<()> {
+    if let Some(form) = options.normalize {
+        cfg.map_texts(|s| Some(form.apply(s)));
+    }
+    if options.collapse_trailing_ws {
+        let mut clash = None;
+        cfg.map_texts(|s| {
+            collapse_trailing_ws(s).unwrap_or_else(|e| {
+                clash.get_or_insert(e);
+                None
+            })
+        });
+        if let Some(e) = clash {
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+// Written by `--collapse-trailing-ws` in place of a value's trailing whitespace.
+const TRAILING_WS_MARKER: &str = "[[ws]]";
+
+// `value` with its trailing whitespace replaced by the marker, or `None` if it has none. A value
+// already ending with the marker would be read back as collapsed, so it is refused.
+fn collapse_trailing_ws(value: &str) -> Result<Option<String>> {
+    if value.ends_with(TRAILING_WS_MARKER) {
+        bail!(
+            "Text {:?} already ends with the trailing-whitespace marker {}",
+            value,
+            TRAILING_WS_MARKER
+        );
+    }
+    let trimmed = value.trim_end();
+    Ok((trimmed.len() < value.len()).then(|| format!("{}{}", trimmed, TRAILING_WS_MARKER)))
+}
+
+fn collapse_values<'a>(values: impl Iterator<Item = &'a mut String>) -> Result<()> {
+    for value in values {
+        if let Some(collapsed) = collapse_trailing_ws(value)? {
+            *value = collapsed;
+        }
+    }
+    Ok(())
+}
+
+// Undoes `collapse_trailing_ws`: a value still ending with the marker gets the trailing
+// whitespace of `original` (the file's value) back. A removed marker means the translator
+// chose the ending, so the value is kept as written.
+fn restore_trailing_ws(value: &mut String, original: &str) {
+    if let Some(stripped) = value.strip_suffix(TRAILING_WS_MARKER) {
+        let whitespace = &original[original.trim_end().len()..];
+        *value = format!("{}{}", stripped, whitespace);
+    }
+}
+
+// Normalizes each value in place, returning how many changed.
+fn normalize_values<'a>(
+    values: impl Iterator<Item = &'a mut String>,
+    form: Normalization,
+) -> usize {
+    let mut changed = 0;
+    for value in values {
+        let normalized = form.apply(value);
+        if normalized != *value {
+            *value = normalized;
+            changed += 1;
+        }
+    }
+    changed
+}
+
+fn report_normalized(options: &UpdateOptions, form: Normalization, changed: usize) {
+    status_line(
+        options,
+        &format!(
+            "Normalized {} value(s) to {}",
+            changed,
+            value_name(form).to_uppercase()
+        ),
+    );
+}
+
+// The JSON-only extract flags; every other format (CSV included) would silently drop them.
+fn check_extract_flags(options: &ExtractOptions) -> Result<()> {
+    if options.with_hash
+        && (options.mode, options.extract_format) != (Mode::Standard, ExtractFormat::Json)
+    {
+        bail!("--with-hash is only available for standard-mode JSON extraction");
+    }
+    if options.leaves_only
+        && (options.mode, options.extract_format) != (Mode::Standard, ExtractFormat::Json)
+    {
+        bail!("--leaves-only is only available for standard-mode JSON extraction");
+    }
+    if options.baseline.is_some()
+        && (options.mode, options.extract_format) != (Mode::Standard, ExtractFormat::Json)
+    {
+        bail!("--baseline is only available for standard-mode JSON extraction");
+    }
+    if options.var_type != VarType::String
+        && (options.mode, options.extract_format) != (Mode::Standard, ExtractFormat::Json)
+    {
+        bail!("--var-type is only available for standard-mode JSON extraction");
+    }
+    Ok(())
+}
+
+fn extract(cfg_path: &Path, options: &ExtractOptions, warnings: &mut Warnings) -> Result<()> {
+    warnings.set_file(cfg_path);
+    check_extract_flags(options)?;
+    let data = read_cfg_file(cfg_path, options.embedded)?;
+    if options.verbose {
+        note_footer_encoding(&data, options.open.endian);
+    }
+    let (content, out_path, count) = match (options.mode, options.extract_format) {
+        (Mode::Standard, ExtractFormat::Json) if options.baseline.is_some() => {
+            let baseline_path = options
+                .baseline
+                .as_deref()
+                .context("--baseline has no path")?;
+            let baseline = read_baseline(baseline_path)?;
+            let current = extract_json_values(&data, options, warnings)?;
+            let total = current.len();
+            let texts = changed_since_baseline(current, &baseline);
+            if !options.json_events {
+                println!(
+                    "{} of {} text entries are new or changed since {}",
+                    texts.len(),
+                    total,
+                    baseline_path.display()
+                );
+            }
+            let json =
+                serde_json::to_string_pretty(&texts).context("Failed to serialize to JSON")?;
+            (
+                Some(json),
+                format!("{}.json", cfg_path.display()),
+                texts.len(),
+            )
+        }
+        (Mode::Standard, ExtractFormat::Json)
+            if options.with_hash
+                && (options.normalize.is_some() || options.collapse_trailing_ws) =>
+        {
+            let values = extract_json_values(&data, options, warnings)?;
+            let out_path = format!("{}.json", cfg_path.display());
+            let file = fs::File::create(&out_path).context("Failed to write extracted file")?;
+            let mut writer = BufWriter::new(file);
+            serde_json::to_writer_pretty(&mut writer, &values)
+                .context("Failed to write extracted file")?;
+            writer.flush().context("Failed to write extracted file")?;
+            (None, out_path, values.len())
+        }
+        (Mode::Standard, ExtractFormat::Json) => {
+            // Streamed to the file so the entry list and the JSON string never coexist.
+            let cfg = open_cfg_for_extract(&data, options, warnings)?;
+            let out_path = format!("{}.json", cfg_path.display());
+            let file = fs::File::create(&out_path).context("Failed to write extracted file")?;
+            let mut writer = BufWriter::new(file);
+            let count = cfg
+                .write_values_json(
+                    &mut writer,
+                    options.var_type,
+                    options.with_hash,
+                    options.leaves_only,
+                )
+                .context("Failed to write extracted file")?;
+            writer.flush().context("Failed to write extracted file")?;
+            (None, out_path, count)
+        }
+        (Mode::Standard, ExtractFormat::Txt) => {
+            let cfg = open_cfg_for_extract(&data, options, warnings)?;
+            let texts = cfg.extract_texts();
+            let values: Vec<&str> = texts.iter().map(|t| t.value.as_str()).collect();
+            (
+                Some(format_txt_export(&values, options)?),
+                format!("{}.txt", cfg_path.display()),
+                texts.len(),
+            )
+        }
+        (Mode::Standard, ExtractFormat::Tree) => {
+            let cfg = open_cfg_for_extract(&data, options, warnings)?;
+            let tree = cfg.extract_text_tree();
+            let texts = cfg.extract_texts();
+            let json =
+                serde_json::to_string_pretty(&tree).context("Failed to serialize to JSON")?;
+            (
+                Some(json),
+                format!("{}.tree.json", cfg_path.display()),
+                texts.len(),
+            )
+        }
+        (Mode::Standard, ExtractFormat::Paths) => {
+            let cfg = open_cfg_for_extract(&data, options, warnings)?;
+            let texts = cfg.extract_texts_by_path()?;
+            let json = serde_json::to_string_pretty(&OrderedMap(&texts))
+                .context("Failed to serialize to JSON")?;
+            (
+                Some(json),
+                format!("{}.paths.json", cfg_path.display()),
+                texts.len(),
+            )
+        }
+        (Mode::Standard, ExtractFormat::Csv) => {
+            let cfg = open_cfg_for_extract(&data, options, warnings)?;
+            let texts = cfg.extract_texts();
+            (
+                Some(csv::write_texts(&texts)),
+                format!("{}.csv", cfg_path.display()),
+                texts.len(),
+            )
+        }
+        (Mode::Standard, ExtractFormat::Full) => {
+            let cfg = open_cfg_for_extract(&data, options, warnings)?;
+            let dumps = cfg.extract_all();
+            let json =
+                serde_json::to_string_pretty(&dumps).context("Failed to serialize to JSON")?;
+            (
+                Some(json),
+                format!("{}.full.json", cfg_path.display()),
+                dumps.len(),
+            )
+        }
+        (Mode::Nnk, ExtractFormat::Full) => {
+            bail!("The full extract format is only available in standard mode");
+        }
+        (Mode::Nnk, ExtractFormat::Csv) => {
+            bail!("The csv extract format is only available in standard mode");
+        }
+        (Mode::Nnk, ExtractFormat::Tree) => {
+            bail!("The tree extract format is only available in standard mode");
+        }
+        (Mode::Nnk, ExtractFormat::Paths) => {
+            bail!("The paths extract format is only available in standard mode");
+        }
+        (Mode::Nnk, ExtractFormat::Json) => {
+            let mut texts =
+                nnk::extract_for_json(&data).context("Failed to parse cfg.bin file in nnk mode")?;
+            if let Some(form) = options.normalize {
+                normalize_values(texts.values_mut(), form);
+            }
+            if options.collapse_trailing_ws {
+                collapse_values(texts.values_mut())?;
+            }
+            let json =
+                serde_json::to_string_pretty(&texts).context("Failed to serialize to JSON")?;
+            (
+                Some(json),
+                format!("{}.json", cfg_path.display()),
+                texts.len(),
+            )
+        }
+        (Mode::Nnk, ExtractFormat::Txt) => {
+            let mut texts =
+                nnk::extract(&data).context("Failed to parse cfg.bin file in nnk mode")?;
+            if let Some(form) = options.normalize {
+                normalize_values(texts.values_mut(), form);
+            }
+            if options.collapse_trailing_ws {
+                collapse_values(texts.values_mut())?;
+            }
+            let values: Vec<&str> = texts.values().map(String::as_str).collect();
+            (
+                Some(format_txt_export(&values, options)?),
+                format!("{}.txt", cfg_path.display()),
+                texts.len(),
+            )
+        }
+    };
+    if let Some(content) = content {
+        fs::write(&out_path, &content).context("Failed to write extracted file")?;
+    }
+    if options.json_events {
+        emit_event(&FileEvent::ok(cfg_path, Path::new(&out_path), count));
+    } else {
+        println!("Extracted {} text entries to {}", count, out_path);
+    }
+    Ok(())
+}
+
+// Serializes key/value pairs as a JSON object, keeping their order.
+struct OrderedMap<'a>(&'a [(String, String)]);
+
+impl Serialize for OrderedMap<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(k, v)| (k, v)))
+    }
+}
+
+// A paths JSON object; a path given twice is an error instead of the last value winning.
+#[derive(Debug)]
+struct PathTexts(BTreeMap<String, String>);
+
+impl<'de> Deserialize<'de> for PathTexts {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = PathTexts;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("an object of path/text pairs")
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> Result<PathTexts, A::Error> {
+                let mut texts = BTreeMap::new();
+                while let Some((path, text)) = map.next_entry::<String, String>()? {
+                    if texts.contains_key(&path) {
+                        return Err(serde::de::Error::custom(format!("duplicate path {}", path)));
+                    }
+                    texts.insert(path, text);
+                }
+                Ok(PathTexts(texts))
+            }
+        }
+
+        deserializer.deserialize_map(Visitor)
+    }
+}
+
+// Optional sections appended to the `info` output.
+struct InfoReports {
+    unused_keys: bool,
+    variable_types: Option<ReportFormat>,
+    raw_strings: bool,
+    offsets: Option<PathBuf>,
+    encoding: bool,
+}
+
+fn info(
+    cfg_path: &Path,
+    verbose: bool,
+    reports: &InfoReports,
+    open_options: &OpenOptions,
+    warnings: &mut Warnings,
+) -> Result<()> {
+    warnings.set_file(cfg_path);
+    let data = fs::read(cfg_path).context("Failed to read cfg.bin file")?;
+    if verbose {
+        note_footer_encoding(&data, open_options.endian);
+    }
+    let cfg = open_cfg(&data, open_options, warnings)?;
+    if reports.encoding {
+        for line in encoding_lines(cfg_path, &data, &cfg) {
+            println!("{}", line);
+        }
+        return Ok(());
+    }
+    let stats = cfg.top_level_stats();
+    let total: usize = stats.iter().map(|s| s.total_count).sum();
+
+    println!("file: {}", cfg_path.display());
+    println!("top_level_entries: {}", stats.len());
+    println!("entries: {}", cfg.node_count());
+    println!("end_terminators: {}", cfg.end_terminator_count());
+    println!("serialized_entries: {}", total);
+    let regions = CfgBin::region_sizes(&data, open_options.endian)?;
+    println!("entries_region_bytes: {}", regions.entries);
+    println!("string_table_bytes: {}", regions.string_table);
+    println!("key_table_bytes: {}", regions.key_table);
+    println!();
+    println!(
+        "{:<40} {:>8} {:>6} {:>8}",
+        "entry", "children", "depth", "count"
+    );
+    for s in &stats {
+        println!(
+            "{:<40} {:>8} {:>6} {:>8}",
+            s.name, s.child_count, s.max_depth, s.total_count
+        );
+    }
+    if reports.unused_keys {
+        let unused = cfg.unused_keys();
+        println!();
+        println!("unused_keys: {}", unused.len());
+        for name in &unused {
+            println!("  {}", name);
+        }
+    }
+    if let Some(format) = reports.variable_types {
+        let counts = cfg.variable_type_counts();
+        println!();
+        match format {
+            ReportFormat::Table => {
+                println!("{:<10} {:>8}", "type", "count");
+                for (name, count) in [
+                    ("String", counts.string),
+                    ("Int", counts.int),
+                    ("Float", counts.float),
+                    ("Unknown", counts.unknown),
+                ] {
+                    println!("{:<10} {:>8}", name, count);
+                }
+            }
+            ReportFormat::Json => println!("{}", serde_json::to_string(&counts)?),
+        }
+    }
+    if reports.raw_strings {
+        let raw = CfgBin::raw_strings(
+            &data,
+            open_options.string_format,
+            open_options.endian,
+            cfg.encoding,
+        )?;
+        println!();
+        println!("raw_strings: {}", raw.len());
+        for r in &raw {
+            let hex: Vec<String> = r.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            println!("  0x{:06X}  [{}]", r.offset, hex.join(" "));
+            println!("    sjis: {:?}", r.shift_jis);
+            println!("    utf8: {:?}", r.utf8);
+        }
+    }
+    if let Some(path) = &reports.offsets {
+        let offsets = CfgBin::string_offsets(&data, open_options)?;
+        let json = serde_json::to_string_pretty(&offsets)?;
+        fs::write(path, json).context("Failed to write offsets JSON")?;
+        println!();
+        println!(
+            "Written {} ({} string offset record(s))",
+            path.display(),
+            offsets.len()
+        );
+    }
+    Ok(())
+}
+
+fn build_find_matcher(pattern: &str, is_regex: bool, case_sensitive: bool) -> Result<Regex> {
+    let source = if is_regex {
+        pattern.to_string()
+    } else {
+        regex::escape(pattern)
+    };
+    RegexBuilder::new(&source)
+        .case_insensitive(!case_sensitive)
+        .build()
+        .context(format!("Invalid pattern: {}", pattern))
+}
+
+fn find(
+    cfg_path: &Path,
+    matcher: &Regex,
+    mode: Mode,
+    open_options: &OpenOptions,
+    warnings: &mut Warnings,
+) -> Result<()> {
+    warnings.set_file(cfg_path);
+    let data = fs::read(cfg_path).context("Failed to read cfg.bin file")?;
+    let mut matches = 0usize;
+    match mode {
+        Mode::Standard => {
+            let cfg = open_cfg(&data, open_options, warnings)?;
+            for te in cfg.extract_texts() {
+                if matcher.is_match(&te.value) {
+                    println!(
+                        "[{}] {}[{}]: {}",
+                        te.index,
+                        te.entry,
+                        te.variable_index,
+                        normalize_txt_line(&te.value)
+                    );
+                    matches += 1;
+                }
+            }
+        }
+        Mode::Nnk => {
+            let texts = nnk::extract(&data).context("Failed to parse cfg.bin file in nnk mode")?;
+            for (addr, value) in &texts {
+                if matcher.is_match(value) {
+                    println!("[0x{:08X}]: {}", addr, normalize_txt_line(value));
+                    matches += 1;
+                }
+            }
+        }
+    }
+    println!("{} match(es) in {}", matches, cfg_path.display());
+    Ok(())
+}
+
+// Where a shared value appears, as written to the glossary JSON.
+#[derive(Debug, Serialize)]
+struct TextLocation {
+    file: String,
+    index: usize,
+    entry: String,
+    variable_index: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct SharedString {
+    value: String,
+    files: usize,
+    locations: Vec<TextLocation>,
+}
+
+// Non-empty values found in at least `min_files` of the files, most widespread first.
+fn collect_shared_strings(
+    files: &[(String, Vec<TextEntry>)],
+    min_files: usize,
+) -> Vec<SharedString> {
+    let mut by_value: BTreeMap<&str, Vec<TextLocation>> = BTreeMap::new();
+    for (file, texts) in files {
+        for te in texts.iter().filter(|te| !te.value.is_empty()) {
+            by_value.entry(&te.value).or_default().push(TextLocation {
+                file: file.clone(),
+                index: te.index,
+                entry: te.entry.clone(),
+                variable_index: te.variable_index,
+            });
+        }
+    }
+
+    let mut shared: Vec<SharedString> = by_value
+        .into_iter()
+        .map(|(value, locations)| {
+            let files: BTreeSet<&str> = locations.iter().map(|l| l.file.as_str()).collect();
+            SharedString {
+                value: value.to_string(),
+                files: files.len(),
+                locations,
+            }
+        })
+        .filter(|s| s.files >= min_files)
+        .collect();
+    shared.sort_by(|a, b| b.files.cmp(&a.files).then_with(|| a.value.cmp(&b.value)));
+    shared
+}
+
+fn compare_modes(
+    cfg_path: &Path,
+    json: Option<&Path>,
+    open_options: &OpenOptions,
+    warnings: &mut Warnings,
+) -> Result<()> {
+    warnings.set_file(cfg_path);
+    let data = fs::read(cfg_path).context("Failed to read cfg.bin file")?;
+    let divergences = CfgBin::compare_modes(&data, open_options)?;
+    let show = |value: &Option<String>| match value {
+        Some(v) => normalize_txt_line(v),
+        None => "(missing)".to_string(),
+    };
+    for d in &divergences {
+        let slot = match (&d.index, &d.entry, &d.variable_index) {
+            (Some(index), Some(entry), Some(var)) => format!("[{}] {}[{}]", index, entry, var),
+            _ => "-".to_string(),
+        };
+        println!("0x{:08X} standard {}", d.address, slot);
+        println!("  standard: {}", show(&d.standard));
+        println!("  nnk:      {}", show(&d.nnk));
+    }
+    if divergences.is_empty() {
+        println!(
+            "Standard and nnk extraction agree on {}",
+            cfg_path.display()
+        );
+    } else {
+        println!(
+            "{} divergence(s) between standard and nnk extraction in {}",
+            divergences.len(),
+            cfg_path.display()
+        );
+    }
+    if let Some(path) = json {
+        let content = serde_json::to_string_pretty(&divergences)?;
+        fs::write(path, content).context("Failed to write divergence JSON")?;
+        println!("Written {}", path.display());
+    }
+    Ok(())
+}
+
+fn make_patch(
+    cfg_path: &Path,
+    edited_path: &Path,
+    output: Option<PathBuf>,
+    open_options: &OpenOptions,
+    warnings: &mut Warnings,
+) -> Result<()> {
+    warnings.set_file(cfg_path);
+    let data = fs::read(cfg_path).context("Failed to read cfg.bin file")?;
+    let cfg = open_cfg(&data, open_options, warnings)?;
+    let json = fs::read_to_string(edited_path).context("Failed to read edited JSON")?;
+    let edited: Vec<TextEntry> =
+        serde_json::from_str(&json).context("Failed to parse edited JSON (standard mode)")?;
+    let patches = cfg.diff_texts(&edited);
+    let out_path =
+        output.unwrap_or_else(|| PathBuf::from(format!("{}.patch.json", edited_path.display())));
+    let content = serde_json::to_string_pretty(&patches)?;
+    fs::write(&out_path, content).context("Failed to write patch file")?;
+    println!(
+        "Written {} ({} changed slot(s))",
+        out_path.display(),
+        patches.len()
+    );
+    Ok(())
+}
+
+fn apply_patch(
+    cfg_path: &Path,
+    patch_path: &Path,
+    output: Option<PathBuf>,
+    open_options: &OpenOptions,
+    warnings: &mut Warnings,
+) -> Result<()> {
+    warnings.set_file(cfg_path);
+    let data = fs::read(cfg_path).context("Failed to read cfg.bin file")?;
+    let mut cfg = open_cfg(&data, open_options, warnings)?;
+    let json = fs::read_to_string(patch_path).context("Failed to read patch file")?;
+    let patches: Vec<cfgbin::TextPatch> =
+        serde_json::from_str(&json).context("Failed to parse patch file")?;
+    let count = cfg.apply_text_patches(&patches).with_context(|| {
+        format!(
+            "{} does not apply to {}",
+            patch_path.display(),
+            cfg_path.display()
+        )
+    })?;
+    let out_path = output.unwrap_or_else(|| cfg_path.to_path_buf());
+    let output = cfg.save()?;
+    fs::write(&out_path, &output).context("Failed to write cfg.bin file")?;
+    println!(
+        "Written {} ({} patched slot(s)): {}",
+        out_path.display(),
+        count,
+        format_size_change(data.len(), output.len())
+    );
+    Ok(())
+}
+
+fn timestamp(
+    cfg_path: &Path,
+    set: Option<&str>,
+    output: Option<PathBuf>,
+    open_options: &OpenOptions,
+    warnings: &mut Warnings,
+) -> Result<()> {
+    warnings.set_file(cfg_path);
+    let data = fs::read(cfg_path).context("Failed to read cfg.bin file")?;
+    let mut cfg = open_cfg(&data, open_options, warnings)?;
+    let Some(value) = set else {
+        println!("{}", current_timestamp(&cfg)?);
+        return Ok(());
+    };
+    let previous = set_timestamp(&mut cfg, value)?;
+    let out_path = output.unwrap_or_else(|| cfg_path.to_path_buf());
+    let output = cfg.save()?;
+    fs::write(&out_path, &output).context("Failed to write cfg.bin file")?;
+    println!(
+        "Written {} (timestamp {} -> {}): {}",
+        out_path.display(),
+        previous,
+        value,
+        format_size_change(data.len(), output.len())
+    );
+    Ok(())
+}
+
+// The first string value, if it is a build timestamp.
+fn current_timestamp(cfg: &CfgBin) -> Result<String> {
+    match cfg.extract_texts().into_iter().next() {
+        Some(first) if is_datetime_timestamp_line(&first.value) => Ok(first.value),
+        Some(first) => bail!(
+            "The first string value ({:?}) is not a YYYY/MM/DD HH:MM:SS timestamp",
+            first.value
+        ),
+        None => bail!("The file has no string values"),
+    }
+}
+
+// Replaces the timestamp with `value`, returning the old one.
+fn set_timestamp(cfg: &mut CfgBin, value: &str) -> Result<String> {
+    validate_timestamp(value)?;
+    let previous = current_timestamp(cfg)?;
+    let mut texts = cfg.extract_texts();
+    texts.truncate(1);
+    texts[0].value = value.to_string();
+    cfg.update_texts(&texts);
+    Ok(previous)
+}
+
+fn validate_timestamp(value: &str) -> Result<()> {
+    let field = |range: std::ops::Range<usize>| value[range].parse::<u32>().unwrap_or(0);
+    let valid = is_datetime_timestamp_line(value)
+        && (1..=12).contains(&field(5..7))
+        && (1..=31).contains(&field(8..10))
+        && field(11..13) < 24
+        && field(14..16) < 60
+        && field(17..19) < 60;
+    if !valid {
+        bail!(
+            "Invalid timestamp {:?}: expected YYYY/MM/DD HH:MM:SS, e.g. 2024/01/31 12:00:00",
+            value
+        );
+    }
+    Ok(())
+}
+
+struct ReplaceRequest {
+    matcher: Regex,
+    replacement: String,
+    // Insert `replacement` as is instead of expanding `$1`-style groups
+    literal: bool,
+    dry_run: bool,
+}
+
+fn replace(
+    cfg_path: &Path,
+    request: &ReplaceRequest,
+    out_path: &Path,
+    options: &UpdateOptions,
+    warnings: &mut Warnings,
+) -> Result<()> {
+    warnings.set_file(cfg_path);
+    let data = read_cfg_file(cfg_path, options.embedded)?;
+    let mut cfg = open_cfg(&data, &options.open, warnings)?;
+    let (occurrences, values) = replace_texts(&mut cfg, request)?;
+    let counts = format!("{} occurrence(s) in {} value(s)", occurrences, values);
+    if request.dry_run {
+        println!(
+            "Would replace {} in {} (dry run)",
+            counts,
+            cfg_path.display()
+        );
+        return Ok(());
+    }
+    let output = save_for_update(&mut cfg, options, warnings)?;
+    write_update_output(cfg_path, out_path, &output, options)?;
+    println!(
+        "Written {} ({} replaced): {}",
+        out_path.display(),
+        counts,
+        format_size_change(data.len(), output.len())
+    );
+    Ok(())
+}
+
+// Applies the replacement to every string value, returning (occurrences, values changed).
+// Fails if a new value can't be written in the file's encoding.
+fn replace_texts(cfg: &mut CfgBin, request: &ReplaceRequest) -> Result<(usize, usize)> {
+    let encoding = cfg.encoding;
+    let mut occurrences = 0;
+    let mut unencodable = None;
+    let values = cfg.map_texts(|s| {
+        let new_value = if request.literal {
+            request
+                .matcher
+                .replace_all(s, NoExpand(&request.replacement))
+        } else {
+            request.matcher.replace_all(s, request.replacement.as_str())
+        };
+        if new_value == s {
+            return None;
+        }
+        occurrences += request.matcher.find_iter(s).count();
+        if unencodable.is_none() && !cfgbin::can_encode(&new_value, encoding) {
+            unencodable = Some(new_value.to_string());
+        }
+        Some(new_value.into_owned())
+    });
+    if let Some(value) = unencodable {
+        bail!(
+            "Replaced value {:?} can't be encoded as {}; nothing was written",
+            value,
+            encoding_name(encoding)
+        );
+    }
+    Ok((occurrences, values))
+}
+
+// File arguments of multi-file commands; ones containing `*` or `?` are expanded like --input-glob.
+fn expand_cfg_args(patterns: &[String]) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for pattern in patterns {
+        if pattern.contains(['*', '?']) {
+            paths.extend(expand_input_glob(pattern)?);
+        } else {
+            paths.push(PathBuf::from(pattern));
+        }
+    }
+    if paths.is_empty() {
+        bail!("No cfg.bin files match {}", patterns.join(" "));
+    }
+    Ok(paths)
+}
+
+// Parses every file without writing anything; fails if any file doesn't parse (or, with
+// `strict`, parses with warnings). `verify` re-saves each file, keeping the key order if true.
+fn check(
+    patterns: &[String],
+    strict: bool,
+    verify: Option<bool>,
+    open_options: &OpenOptions,
+    warnings: &mut Warnings,
+) -> Result<()> {
+    let paths = expand_cfg_args(patterns)?;
+    let mut failed = 0usize;
+    let mut warned = 0usize;
+    for path in &paths {
+        warnings.set_file(path);
+        let result = fs::read(path)
+            .context("Failed to read cfg.bin file")
+            .and_then(|data| {
+                let cfg = CfgBin::open_with_options(&data, open_options)
+                    .context("Failed to parse cfg.bin file")?;
+                Ok((data, cfg))
+            });
+        match result {
+            Ok((data, cfg)) => {
+                let found = open_warnings(&data, &cfg, open_options);
+                let messages: Vec<&str> = found.iter().map(|w| w.message.as_str()).collect();
+                let lossy = verify.and_then(|preserve_key_order| {
+                    let save_options = standard_save_options(&cfg, preserve_key_order, false);
+                    roundtrip_failure(&cfg, &data, &save_options)
+                });
+                if let Some(report) = lossy {
+                    println!("FAIL  {}: {}", path.display(), report);
+                    if !messages.is_empty() {
+                        println!("      warnings: {}", messages.join("; "));
+                    }
+                    failed += 1;
+                } else if found.is_empty() {
+                    println!("OK    {}", path.display());
+                } else {
+                    let status = if strict { "FAIL" } else { "WARN" };
+                    println!("{}  {}: {}", status, path.display(), messages.join("; "));
+                    failed += usize::from(strict);
+                    warned += usize::from(!strict);
+                }
+                found.into_iter().for_each(|w| warnings.record(w));
+            }
+            Err(e) => {
+                println!("FAIL  {}: {:#}", path.display(), e);
+                failed += 1;
+            }
+        }
+    }
+    let warned_note = match warned {
+        0 => String::new(),
+        n => format!(", {} with warnings", n),
+    };
+    if failed > 0 {
+        bail!(
+            "{} of {} file(s) failed the check{}",
+            failed,
+            paths.len(),
+            warned_note
+        );
+    }
+    println!("{} file(s) OK{}", paths.len() - warned, warned_note);
+    Ok(())
+}
+
+// Why `check --verify` fails a file, with the differing bytes on the lines below; `None` if
+// re-saving it with `save_options` reproduces `data` exactly.
+fn roundtrip_failure(cfg: &CfgBin, data: &[u8], save_options: &SaveOptions) -> Option<String> {
+    let diff = match cfg.verify_roundtrip_with(data, save_options) {
+        Ok(diff) => diff?,
+        Err(e) => return Some(format!("re-saving fails: {:#}", e)),
+    };
+    let window = |label: &str, bytes: &[u8]| {
+        let hex: Vec<String> = bytes
+            .iter()
+            .enumerate()
+            .map(|(i, b)| match diff.context_start + i == diff.offset {
+                true => format!("[{:02X}]", b),
+                false => format!("{:02X}", b),
+            })
+            .collect();
+        let end = if diff.context_start + bytes.len() <= diff.offset {
+            " (end of file)"
+        } else {
+            ""
+        };
+        format!(
+            "      {:<9}0x{:06X}: {}{}",
+            label,
+            diff.context_start,
+            hex.join(" "),
+            end
+        )
+    };
+    Some(format!(
+        "re-saving without edits changes the file from offset 0x{:X} ({} -> {} bytes)\n{}\n{}",
+        diff.offset,
+        diff.original_len,
+        diff.saved_len,
+        window("original", &diff.original),
+        window("saved", &diff.saved)
+    ))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    fn label(self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct AuditFinding {
+    check: &'static str,
+    severity: Severity,
+    message: String,
+}
+
+// Everything `audit` found in one file; it passes unless a finding is an error.
+#[derive(Debug, Serialize)]
+struct AuditReport {
+    file: String,
+    passed: bool,
+    checks: Vec<&'static str>,
+    findings: Vec<AuditFinding>,
+}
+
+impl AuditReport {
+    fn count(&self, severity: Severity) -> usize {
+        self.findings
+            .iter()
+            .filter(|f| f.severity == severity)
+            .count()
+    }
+}
+
+// Runs the read-only diagnostics in order. A file that doesn't parse only gets the header
+// check, since the others need the parsed entries.
+fn audit_report(path: &Path, data: &[u8], open_options: &OpenOptions) -> AuditReport {
+    let mut checks = vec!["header"];
+    let mut findings = Vec::new();
+    let mut add = |check, severity, message: String| {
+        findings.push(AuditFinding {
+            check,
+            severity,
+            message,
+        })
+    };
+    let endian = open_options.endian;
+    let cfg = match CfgBin::open_with_options(data, open_options) {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            add("header", Severity::Error, format!("{:#}", e));
+            return AuditReport {
+                file: path.display().to_string(),
+                passed: false,
+                checks,
+                findings,
+            };
+        }
+    };
+
+    checks.extend([
+        "footer",
+        "encoding",
+        "strings",
+        "keys",
+        "blocks",
+        "round-trip",
+    ]);
+    for warning in open_warnings(data, &cfg, open_options) {
+        let (check, severity) = match warning.category {
+            "unknown-crc" => ("keys", Severity::Error),
+            "unparsed-entries" => ("header", Severity::Warning),
+            "suspect-strings" => ("strings", Severity::Warning),
+            _ => ("encoding", Severity::Warning),
+        };
+        add(check, severity, warning.message);
+    }
+
+    if !cfgbin::has_footer(data, endian) {
+        add(
+            "footer",
+            Severity::Warning,
+            "no footer magic; the encoding was read from 10 bytes before the end of the file"
+                .to_string(),
+        );
+    } else if cfg.encoding == CfgBinEncoding::Utf8 && cfg.footer_encoding > 1 {
+        add(
+            "footer",
+            Severity::Info,
+            format!(
+                "footer encoding is 0x{:04X}, a UTF-8 variant; it is read as UTF-8 and preserved on save",
+                cfg.footer_encoding
+            ),
+        );
+    }
+
+    let texts = cfg.extract_texts();
+    let garbled = texts
+        .iter()
+        .filter(|t| cfgbin::looks_misdecoded(&t.value, cfg.encoding))
+        .count();
+    if garbled > 0 {
+        add(
+            "encoding",
+            Severity::Warning,
+            format!(
+                "{} of {} text slots contain replacement, control or private-use characters",
+                garbled,
+                texts.len()
+            ),
+        );
+    }
+    if let Some(original) = &cfg.original_strings {
+        add(
+            "encoding",
+            Severity::Info,
+            format!(
+                "{} string(s) don't re-encode to their source bytes; unchanged ones keep them on save",
+                original.bytes.len()
+            ),
+        );
+    }
+
+    for key in cfg.rehashed_keys() {
+        add(
+            "keys",
+            Severity::Error,
+            format!(
+                "key {} is stored with CRC 0x{:08X} but hashes to 0x{:08X}",
+                key.name, key.original_crc, key.new_crc
+            ),
+        );
+    }
+    let unused = cfg.unused_keys();
+    if !unused.is_empty() {
+        add(
+            "keys",
+            Severity::Info,
+            format!(
+                "{} key(s) no entry references: {}",
+                unused.len(),
+                unused.join(", ")
+            ),
+        );
+    }
+
+    let unclosed = cfg.unclosed_blocks();
+    if !unclosed.is_empty() {
+        add(
+            "blocks",
+            Severity::Warning,
+            format!(
+                "{} block(s) have no END row: {}",
+                unclosed.len(),
+                unclosed.join(", ")
+            ),
+        );
+    }
+
+    match cfg.verify_roundtrip(data) {
+        Ok(None) => {}
+        Ok(Some(diff)) => add(
+            "round-trip",
+            Severity::Info,
+            format!(
+                "re-saving without edits changes the file from offset 0x{:X} ({} -> {} bytes)",
+                diff.offset, diff.original_len, diff.saved_len
+            ),
+        ),
+        Err(e) => add(
+            "round-trip",
+            Severity::Error,
+            format!("re-saving fails: {:#}", e),
+        ),
+    }
+
+    if endian == Endian::Little && open_options.string_format == StringFormat::NullTerminated {
+        checks.push("modes");
+        match CfgBin::compare_modes(data, open_options) {
+            Ok(divergences) if !divergences.is_empty() => add(
+                "modes",
+                Severity::Warning,
+                format!(
+                    "standard and nnk extraction disagree on {} string(s) (see compare-modes)",
+                    divergences.len()
+                ),
+            ),
+            Ok(_) => {}
+            Err(e) => add("modes", Severity::Warning, format!("{:#}", e)),
+        }
+    }
+
+    findings.sort_by_key(|f| checks.iter().position(|&c| c == f.check));
+    AuditReport {
+        file: path.display().to_string(),
+        passed: findings.iter().all(|f| f.severity != Severity::Error),
+        checks,
+        findings,
+    }
+}
+
+fn audit(
+    cfg_path: &Path,
+    json: Option<&Path>,
+    open_options: &OpenOptions,
+    warnings: &mut Warnings,
+) -> Result<()> {
+    warnings.set_file(cfg_path);
+    let data = fs::read(cfg_path).context("Failed to read cfg.bin file")?;
+    let report = audit_report(cfg_path, &data, open_options);
+
+    println!("file: {}", report.file);
+    println!();
+    println!("{:<12} {:<8} message", "check", "status");
+    for &check in &report.checks {
+        let mut found = report
+            .findings
+            .iter()
+            .filter(|f| f.check == check)
+            .peekable();
+        if found.peek().is_none() {
+            println!("{:<12} ok", check);
+        }
+        for f in found {
+            println!("{:<12} {:<8} {}", check, f.severity.label(), f.message);
+        }
+    }
+    println!();
+    println!(
+        "{}  {} error(s), {} warning(s), {} note(s)",
+        if report.passed { "PASS" } else { "FAIL" },
+        report.count(Severity::Error),
+        report.count(Severity::Warning),
+        report.count(Severity::Info)
+    );
+    if let Some(path) = json {
+        let content = serde_json::to_string_pretty(&report)?;
+        fs::write(path, content).context("Failed to write audit JSON")?;
+        println!("Written {}", path.display());
+    }
+    if !report.passed {
+        bail!("{} failed the audit", cfg_path.display());
+    }
+    Ok(())
+}
+
+struct ValidateOptions {
+    sparse: bool,
+    max_length: Option<usize>,
+    var_type: VarType,
+    select_entry: Option<String>,
+    json5: bool,
+}
+
+fn validate(
+    cfg_path: &Path,
+    json_path: &Path,
+    options: &ValidateOptions,
+    open_options: &OpenOptions,
+    warnings: &mut Warnings,
+) -> Result<()> {
+    warnings.set_file(cfg_path);
+    let data = fs::read(cfg_path).context("Failed to read cfg.bin file")?;
+    let cfg = open_cfg(&data, open_options, warnings)?;
+    let mut texts: Vec<TextEntry> = read_update_json(json_path, options.json5)?
+        .parse()
+        .context("Failed to parse JSON file")?;
+    if let Some(name) = &options.select_entry {
+        // Entries outside the selection are ignored by the update, so they can't be a problem
+        let selected = cfg
+            .value_indices_under(options.var_type, name)
+            .with_context(|| format!("No entry named {} in this file", name))?;
+        texts.retain(|t| selected.binary_search(&t.index).is_ok());
+    }
+    let problems = translation_problems(&cfg, &texts, options);
+    if problems.is_empty() {
+        println!(
+            "PASS  {}: {} entries apply cleanly to {}",
+            json_path.display(),
+            texts.len(),
+            cfg_path.display()
+        );
+        return Ok(());
+    }
+    println!("FAIL  {}:", json_path.display());
+    for problem in &problems {
+        println!("  - {}", problem);
+    }
+    bail!(
+        "{} problem(s) found in {}",
+        problems.len(),
+        json_path.display()
+    );
+}
+
+// Everything that would make an update of `cfg` with `texts` fail or go wrong, one line each.
+fn translation_problems(
+    cfg: &CfgBin,
+    texts: &[TextEntry],
+    options: &ValidateOptions,
+) -> Vec<String> {
+    let slots = cfg.extract_values(options.var_type, false, false);
+    let slot_count = slots.len();
+    let mut problems = index_problems(texts, slot_count);
+    if !options.sparse {
+        let listed: BTreeSet<usize> = texts.iter().map(|t| t.index).collect();
+        let required = match &options.select_entry {
+            Some(name) => cfg
+                .value_indices_under(options.var_type, name)
+                .unwrap_or_default(),
+            None => (0..slot_count).collect(),
+        };
+        let missing: Vec<String> = required
+            .into_iter()
+            .filter(|i| !listed.contains(i))
+            .map(|i| i.to_string())
+            .collect();
+        if !missing.is_empty() {
+            let shown = missing
+                .iter()
+                .take(10)
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(", ");
+            let more = if missing.len() > 10 { ", ..." } else { "" };
+            problems.push(format!(
+                "{} of {} text slots have no entry (indices {}{}); use --sparse if that is intended",
+                missing.len(),
+                slot_count,
+                shown,
+                more
+            ));
+        }
+    }
+    for t in texts {
+        if let Some(slot) = slots.get(t.index) {
+            if (&slot.entry, slot.variable_index) != (&t.entry, t.variable_index) {
+                problems.push(mismatch_message(&SlotMismatch {
+                    index: t.index,
+                    recorded_entry: t.entry.clone(),
+                    recorded_variable_index: t.variable_index,
+                    actual_entry: slot.entry.clone(),
+                    actual_variable_index: slot.variable_index,
+                }));
+            }
+        }
+        if options.var_type != VarType::String {
+            if !options.var_type.accepts(&t.value) {
+                problems.push(format!(
+                    "index {} ({}[{}]) is not a valid {} value: {:?}",
+                    t.index,
+                    t.entry,
+                    t.variable_index,
+                    options.var_type.name(),
+                    t.value
+                ));
+            }
+        } else if !cfgbin::can_encode(&t.value, cfg.encoding) {
+            problems.push(format!(
+                "index {} ({}[{}]) can't be encoded as {}: {:?}",
+                t.index,
+                t.entry,
+                t.variable_index,
+                encoding_name(cfg.encoding),
+                t.value
+            ));
+        }
+        let length = t.value.chars().count();
+        if let Some(max) = options.max_length.filter(|&max| length > max) {
+            problems.push(format!(
+                "index {} ({}[{}]) is {} characters long (max {})",
+                t.index, t.entry, t.variable_index, length, max
+            ));
+        }
+    }
+    problems
+}
+
+fn crc_collisions(names_file: &Path, encoding: CfgBinEncoding) -> Result<()> {
+    let content = fs::read_to_string(names_file).context("Failed to read names file")?;
+    let names: Vec<&str> = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+    let collisions = cfgbin::crc_collisions(names.iter().copied(), encoding);
+    for c in &collisions {
+        println!("0x{:08X}  {}", c.crc, c.names.join(", "));
+    }
+    println!(
+        "{} collision(s) among {} name(s)",
+        collisions.len(),
+        names.len()
+    );
+    Ok(())
+}
+
+// What `--list-modes` / `--list-formats` report; only the requested lists are included.
+#[derive(Serialize)]
+struct Capabilities {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    modes: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    extract_formats: Option<Vec<FormatCapability>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    update_formats: Option<Vec<FormatCapability>>,
+}
+
+#[derive(Serialize)]
+struct FormatCapability {
+    name: String,
+    modes: Vec<String>,
+}
+
+impl Capabilities {
+    fn of_this_build(modes: bool, formats: bool) -> Self {
+        let names = |modes: &[Mode]| modes.iter().map(|m| value_name(*m)).collect();
+        let extract = ExtractFormat::value_variants().iter().map(|&format| {
+            let modes = match format {
+                ExtractFormat::Json | ExtractFormat::Txt => Mode::value_variants(),
+                ExtractFormat::Tree
+                | ExtractFormat::Paths
+                | ExtractFormat::Csv
+                | ExtractFormat::Full => &[Mode::Standard],
+            };
+            FormatCapability {
+                name: value_name(format),
+                modes: names(modes),
+            }
+        });
+        let update = UpdateFormat::value_variants().iter().map(|&format| {
+            let modes = match format {
+                UpdateFormat::Json | UpdateFormat::Txt => Mode::value_variants(),
+                UpdateFormat::Paths | UpdateFormat::Csv | UpdateFormat::Full => &[Mode::Standard],
+            };
+            FormatCapability {
+                name: value_name(format),
+                modes: names(modes),
+            }
+        });
+        Capabilities {
+            modes: modes.then(|| names(Mode::value_variants())),
+            extract_formats: formats.then(|| extract.collect()),
+            update_formats: formats.then(|| update.collect()),
+        }
+    }
+}
+
+fn shared_strings(
+    patterns: &[String],
+    min_files: usize,
+    glossary: Option<&Path>,
+    open_options: &OpenOptions,
+    warnings: &mut Warnings,
+) -> Result<()> {
+    let paths = expand_cfg_args(patterns)?;
+    let mut files = Vec::with_capacity(paths.len());
+    for path in &paths {
+        warnings.set_file(path);
+        let data = fs::read(path).context(format!("Failed to read {}", path.display()))?;
+        let cfg = open_cfg(&data, open_options, warnings)
+            .context(format!("Failed to parse {}", path.display()))?;
+        files.push((path.display().to_string(), cfg.extract_texts()));
+    }
+
+    let shared = collect_shared_strings(&files, min_files);
+    for s in &shared {
+        println!(
+            "{:?} ({} files, {} occurrences)",
+            s.value,
+            s.files,
+            s.locations.len()
+        );
+        for l in &s.locations {
+            println!(
+                "  {} [{}] {}[{}]",
+                l.file, l.index, l.entry, l.variable_index
+            );
+        }
+    }
+    println!(
+        "{} shared value(s) across {} file(s)",
+        shared.len(),
+        files.len()
+    );
+
+    if let Some(path) = glossary {
+        let json = serde_json::to_string_pretty(&shared)?;
+        fs::write(path, json).context("Failed to write glossary JSON")?;
+        println!("Written {}", path.display());
+    }
+    Ok(())
+}
+
+// `*` matches any run of characters, `?` matches exactly one.
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let n: Vec<char> = name.chars().collect();
+    let (mut pi, mut ni) = (0usize, 0usize);
+    let mut star: Option<(usize, usize)> = None;
+
+    while ni < n.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == n[ni]) {
+            pi += 1;
+            ni += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ni));
+            pi += 1;
+        } else if let Some((star_pi, star_ni)) = star {
+            pi = star_pi + 1;
+            ni = star_ni + 1;
+            star = Some((star_pi, star_ni + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+fn expand_input_glob(pattern: &str) -> Result<Vec<PathBuf>> {
+    let pattern_path = Path::new(pattern);
+    let dir = match pattern_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let file_pattern = pattern_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .context(format!("Invalid input glob: {}", pattern))?;
+
+    let mut matches = Vec::new();
+    for dir_entry in
+        fs::read_dir(&dir).context(format!("Failed to read input directory {}", dir.display()))?
+    {
+        let path = dir_entry?.path();
+        let is_match = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| wildcard_match(file_pattern, name));
+        if is_match && path.is_file() {
+            matches.push(path);
+        }
+    }
+    matches.sort();
+    Ok(matches)
+}
+
+// Pairs "<name>.cfg.bin.json" or "<name>.json" with "<cfg_dir>/<name>.cfg.bin".
+fn pair_translation_with_cfg(input_path: &Path, cfg_dir: &Path) -> Result<PathBuf> {
+    let stem = input_path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .context(format!("Invalid input file name: {}", input_path.display()))?;
+    let direct = cfg_dir.join(stem);
+    if direct.is_file() {
+        return Ok(direct);
+    }
+    let with_ext = cfg_dir.join(format!("{}.cfg.bin", stem));
+    if with_ext.is_file() {
+        return Ok(with_ext);
+    }
+    bail!(
+        "No cfg.bin matching {} in {}",
+        input_path.display(),
+        cfg_dir.display()
+    );
+}
+
+// Failures of a batch run, and how many inputs were skipped after the first one under
+// --fail-fast.
+struct BatchOutcome {
+    failures: Vec<(PathBuf, anyhow::Error)>,
+    skipped: usize,
+}
+
+// Runs `process` on each translation input, reporting each failure as it happens.
+fn run_batch(
+    inputs: &[PathBuf],
+    fail_fast: bool,
+    json_events: bool,
+    mut process: impl FnMut(&Path) -> Result<()>,
+) -> BatchOutcome {
+    let mut outcome = BatchOutcome {
+        failures: Vec::new(),
+        skipped: 0,
+    };
+    for (i, input_path) in inputs.iter().enumerate() {
+        if let Err(e) = process(input_path) {
+            if json_events {
+                emit_event(&FileEvent::error(None, &e).with_translation(input_path));
+            } else {
+                eprintln!("FAILED {}: {:#}", input_path.display(), e);
+            }
+            outcome.failures.push((input_path.clone(), e));
+            if fail_fast {
+                outcome.skipped = inputs.len() - i - 1;
+                break;
+            }
+        }
+    }
+    outcome
+}
+
+fn batch_update(
+    cfg_dir: &Path,
+    pattern: &str,
+    out_dir: Option<&Path>,
+    fail_fast: bool,
+    options: &UpdateOptions,
+    warnings: &mut Warnings,
+) -> Result<()> {
+    if !cfg_dir.is_dir() {
+        bail!("-w must be a directory of cfg.bin files when using --input-glob");
+    }
+    let inputs = expand_input_glob(pattern)?;
+    if inputs.is_empty() {
+        bail!("No input files match {}", pattern);
+    }
+    if let Some(dir) = out_dir {
+        fs::create_dir_all(dir).context("Failed to create output directory")?;
+    }
+
+    let BatchOutcome { failures, skipped } =
+        run_batch(&inputs, fail_fast, options.json_events, |input_path| {
+            let cfg_path = pair_translation_with_cfg(input_path, cfg_dir)?;
+            let out_path = match out_dir {
+                Some(dir) => dir.join(cfg_path.file_name().unwrap()),
+                None => cfg_path.clone(),
+            };
+            update(&cfg_path, input_path, &out_path, options, warnings)
+        });
+
+    if !options.json_events {
+        let skipped_note = if skipped > 0 {
+            format!(", {} skipped (--fail-fast)", skipped)
+        } else {
+            String::new()
+        };
+        println!(
+            "Batch update: {} succeeded, {} failed{}",
+            inputs.len() - failures.len() - skipped,
+            failures.len(),
+            skipped_note
+        );
+        for (path, e) in &failures {
+            println!("  {}: {:#}", path.display(), e);
+        }
+    }
+    if skipped > 0 {
+        bail!(
+            "Stopped at the first failure; {} of {} files were not processed",
+            skipped,
+            inputs.len()
+        );
+    }
+    if !failures.is_empty() {
+        bail!(
+            "{} of {} files failed to update",
+            failures.len(),
+            inputs.len()
+        );
+    }
+    Ok(())
+}
+
+fn read_rename_map(path: &Path) -> Result<BTreeMap<String, String>> {
+    let json = fs::read_to_string(path).context("Failed to read rename map")?;
+    serde_json::from_str(&json).with_context(|| {
+        format!(
+            "Failed to parse rename map {} (expected a JSON object of strings)",
+            path.display()
+        )
+    })
+}
+
+// Final step of a standard-mode rebuild: applies `--rename-map` (which may resolve
+// UNKNOWN_0x<crc> placeholders) and the output encoding and footer options, then saves.
+fn save_for_update(
+    cfg: &mut CfgBin,
+    options: &UpdateOptions,
+    warnings: &mut Warnings,
+) -> Result<Vec<u8>> {
+    if let Some(renames) = &options.rename_map {
+        let report = cfg.rename_keys(renames);
+        status_line(options, &format!("Renamed {} key(s)", report.renamed.len()));
+        if !report.unmatched.is_empty() {
+            warnings.emit(Warning::new(
+                "unmatched-rename",
+                format!(
+                    "{} rename-map key(s) not applied (no matching entry, or a block whose END name can't follow): {}",
+                    report.unmatched.len(),
+                    report.unmatched.join(", ")
+                ),
+            ));
+        }
+    }
+    if let Some(form) = options.normalize {
+        let changed = cfg.map_texts(|s| Some(form.apply(s)));
+        report_normalized(options, form, changed);
+    }
+    if options.strip_empty_entries {
+        let removed = cfg.strip_empty_entries();
+        status_line(options, &format!("Removed empty entries: {}", removed));
+    }
+    if let Some(encoding) = options.output_encoding.filter(|&e| e != cfg.encoding) {
+        status_line(
+            options,
+            &format!(
+                "Re-encoding from {} to {}",
+                encoding_name(cfg.encoding),
+                encoding_name(encoding)
+            ),
+        );
+        set_output_encoding(cfg, encoding)?;
+    }
+    if options.reencode_strings {
+        cfg.original_strings = None;
+    }
+    cfg.footer_unk1 = options.footer_unk1.unwrap_or(cfg.footer_unk1);
+    cfg.footer_unk2 = options.footer_unk2.unwrap_or(cfg.footer_unk2);
+    if let Some(footer) = &options.reference_footer {
+        if footer.encoding != cfg.encoding {
+            status_line(
+                options,
+                &format!(
+                    "Re-encoding from {} to {} to match the reference",
+                    encoding_name(cfg.encoding),
+                    encoding_name(footer.encoding)
+                ),
+            );
+        }
+        footer.apply(cfg)?;
+    }
+    let save_options =
+        standard_save_options(cfg, options.preserve_key_order, options.no_dedup_strings);
+    if options.verbose {
+        let keys = cfg.key_table_names(&save_options).len();
+        eprintln!("Note: key table has {} unique key(s)", keys);
+    }
+    let rehashed = cfg.rehashed_keys();
+    if !rehashed.is_empty() {
+        let keys: Vec<String> = rehashed
+            .iter()
+            .map(|k| {
+                format!(
+                    "{} (0x{:08X} -> 0x{:08X})",
+                    k.name, k.original_crc, k.new_crc
+                )
+            })
+            .collect();
+        warnings.emit(Warning::new(
+            "rehashed-keys",
+            format!(
+                "{} key CRC32(s) differ from the source key table and will be rehashed; the game may expect the original CRCs: {}",
+                keys.len(),
+                keys.join(", ")
+            ),
+        ));
+    }
+    cfg.save_with_options(&save_options)
+}
+
+// Writes the rebuilt cfg.bin, spliced into a copy of the container with --in-container.
+fn write_update_output(
+    cfg_path: &Path,
+    out_path: &Path,
+    output: &[u8],
+    options: &UpdateOptions,
+) -> Result<()> {
+    match options.embedded {
+        Some(range) if options.in_container => {
+            let container = fs::read(cfg_path).context("Failed to read cfg.bin file")?;
+            fs::write(out_path, range.splice(&container, output)?)
+        }
+        _ => fs::write(out_path, output),
+    }
+    .context("Failed to write cfg.bin file")
+}
+
+// Switches the encoding `save` writes (strings, key names, key CRCs and the footer), refusing
+// if any string or entry name can't be represented in it.
+fn set_output_encoding(cfg: &mut CfgBin, encoding: CfgBinEncoding) -> Result<()> {
+    let mut unencodable = Vec::new();
+    cfg.walk(|entry, _| {
+        if !cfgbin::can_encode(&entry.name, encoding) {
+            unencodable.push(entry.name.clone());
+        }
+        for var in &entry.variables {
+            if let cfgbin::VarValue::String(Some(value)) = &var.value {
+                if !cfgbin::can_encode(value, encoding) {
+                    unencodable.push(value.clone());
+                }
+            }
+        }
+    });
+    if let Some(first) = unencodable.first() {
+        bail!(
+            "{} value(s) can't be encoded as {}, e.g. {:?}",
+            unencodable.len(),
+            encoding_name(encoding),
+            first
+        );
+    }
+    cfg.encoding = encoding;
+    Ok(())
+}
+
+// The encoding and footer fields of a reference file, for --assume-encoding-from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FooterSettings {
+    encoding: CfgBinEncoding,
+    footer_encoding: u16,
+    footer_unk1: u16,
+    footer_unk2: u16,
+}
+
+impl FooterSettings {
+    fn of(cfg: &CfgBin) -> Self {
+        FooterSettings {
+            encoding: cfg.encoding,
+            footer_encoding: cfg.footer_encoding,
+            footer_unk1: cfg.footer_unk1,
+            footer_unk2: cfg.footer_unk2,
+        }
+    }
+
+    // The reference is opened with the byte order and string layout of the file being
+    // updated, but with the encoding its own footer names.
+    fn read(path: &Path, open_options: &OpenOptions) -> Result<Self> {
+        let data = fs::read(path).context("Failed to read reference cfg.bin file")?;
+        let options = OpenOptions {
+            ignore_unknown_crc: true,
+            encoding: None,
+            auto_encoding: false,
+            ..open_options.clone()
+        };
+        let reference = CfgBin::open_with_options(&data, &options)
+            .with_context(|| format!("Failed to parse reference {}", path.display()))?;
+        Ok(Self::of(&reference))
+    }
+
+    fn apply(&self, cfg: &mut CfgBin) -> Result<()> {
+        if self.encoding != cfg.encoding {
+            set_output_encoding(cfg, self.encoding)?;
+        }
+        cfg.footer_encoding = self.footer_encoding;
+        cfg.footer_unk1 = self.footer_unk1;
+        cfg.footer_unk2 = self.footer_unk2;
+        Ok(())
+    }
+}
+
+// What a standard-mode update saves with; `check --verify` uses it too.
+fn standard_save_options(
+    cfg: &CfgBin,
+    preserve_key_order: bool,
+    no_dedup_strings: bool,
+) -> SaveOptions {
+    SaveOptions {
+        key_order: preserve_key_order.then(|| cfg.key_order.clone()),
+        no_dedup_strings,
+        preserve_key_strings: preserve_key_order,
+    }
+}
+
+// Informational update output; moved to stderr under --json-events so stdout stays NDJSON.
+fn status_line(options: &UpdateOptions, line: &str) {
+    if options.json_events {
+        eprintln!("{}", line);
+    } else {
+        println!("{}", line);
+    }
+}
+
+// A sparse JSON must only name slots that exist, each at most once.
+fn validate_sparse_indices(texts: &[TextEntry], slot_count: usize) -> Result<()> {
+    match index_problems(texts, slot_count).first() {
+        Some(problem) => bail!("Sparse update: {}", problem),
+        None => Ok(()),
+    }
+}
+
+// Indices an update can't apply: past the file's last slot, or listed more than once.
+fn index_problems(texts: &[TextEntry], slot_count: usize) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut seen = BTreeSet::new();
+    for t in texts {
+        if t.index >= slot_count {
+            problems.push(format!(
+                "index {} ({}[{}]) does not exist; the file has {} text slots",
+                t.index, t.entry, t.variable_index, slot_count
+            ));
+        } else if !seen.insert(t.index) {
+            problems.push(format!("index {} is listed more than once", t.index));
+        }
+    }
+    problems
+}
+
+fn mismatch_message(m: &SlotMismatch) -> String {
+    format!(
+        "index {} is recorded as {}[{}] but targets {}[{}]; check that the JSON matches this file",
+        m.index,
+        m.recorded_entry,
+        m.recorded_variable_index,
+        m.actual_entry,
+        m.actual_variable_index
+    )
+}
+
+// Slot indices (of `--var-type`) an update may touch: those under `--select-entry`, or all
+// of them.
+fn update_slots(cfg: &CfgBin, options: &UpdateOptions) -> Result<Vec<usize>> {
+    match &options.select_entry {
+        Some(name) => cfg
+            .value_indices_under(options.var_type, name)
+            .with_context(|| format!("No entry named {} in this file", name)),
+        None => Ok((0..cfg.extract_values(options.var_type, false, false).len()).collect()),
+    }
+}
+
+// Refuses flags the resolved update format would otherwise ignore. A full JSON replaces whole
+// entries, so slot selection and per-slot restoring don't apply to it.
+fn check_update_flags(options: &UpdateOptions, update_format: UpdateFormat) -> Result<()> {
+    if options.var_type != VarType::String
+        && (options.mode, update_format) != (Mode::Standard, UpdateFormat::Json)
+    {
+        bail!("--var-type is only available for standard-mode JSON updates");
+    }
+    if update_format == UpdateFormat::Full {
+        let ignored = [
+            ("--select-entry", options.select_entry.is_some()),
+            ("--sparse", options.sparse),
+            ("--collapse-trailing-ws", options.collapse_trailing_ws),
+        ];
+        if let Some((flag, _)) = ignored.iter().find(|(_, set)| *set) {
+            bail!("{} is not available for full-format updates", flag);
+        }
+    }
+    Ok(())
+}
+
+fn update(
+    cfg_path: &Path,
+    input_path: &Path,
+    out_path: &Path,
+    options: &UpdateOptions,
+    warnings: &mut Warnings,
+) -> Result<()> {
+    warnings.set_file(cfg_path);
+    let data = read_cfg_file(cfg_path, options.embedded)?;
+    if options.verbose {
+        note_footer_encoding(&data, options.open.endian);
+    }
+    let update_format = resolve_update_format(input_path, options.update_format)?;
+    check_update_flags(options, update_format)?;
+    let (output, count) = match (options.mode, update_format) {
+        (Mode::Standard, UpdateFormat::Json | UpdateFormat::Csv) => {
+            let mut texts: Vec<TextEntry> = if update_format == UpdateFormat::Csv {
+                // Read as is: values may hold CRLF line breaks of their own.
+                let content = fs::read_to_string(input_path)
+                    .context("Failed to read CSV file (must be UTF-8)")?;
+                csv::parse_texts(&content).context("Failed to parse CSV file")?
+            } else {
+                read_update_json(input_path, options.json5)?
+                    .parse()
+                    .context("Failed to parse JSON file")?
+            };
+            let mut cfg = open_cfg(&data, &options.open, warnings)?;
+            let originals = cfg.extract_values(options.var_type, false, false);
+            let slot_count = originals.len();
+            if options.collapse_trailing_ws {
+                for t in &mut texts {
+                    if let Some(original) = originals.get(t.index) {
+                        restore_trailing_ws(&mut t.value, &original.value);
+                    }
+                }
+            }
+            if options.sparse {
+                validate_sparse_indices(&texts, slot_count)?;
+            }
+            if options.select_entry.is_some() {
+                let slots = update_slots(&cfg, options)?;
+                let before = texts.len();
+                texts.retain(|t| slots.binary_search(&t.index).is_ok());
+                if texts.len() < before {
+                    eprintln!(
+                        "Note: ignored {} JSON entries outside --select-entry",
+                        before - texts.len()
+                    );
+                }
+            }
+            let text_count = texts.len();
+            let mismatches = cfg.update_values(options.var_type, &texts)?;
+            for m in &mismatches {
+                warnings.emit(
+                    Warning::new("index-mismatch", mismatch_message(m))
+                        .with_entry(&m.actual_entry, m.index),
+                );
+            }
+            if options.sparse {
+                status_line(
+                    options,
+                    &format!("Applied {} of {} slots (sparse)", text_count, slot_count),
+                );
+            }
+            let output = save_for_update(&mut cfg, options, warnings)?;
+            (output, text_count)
+        }
+        (Mode::Standard, UpdateFormat::Paths) => {
+            let json_data = read_update_json(input_path, options.json5)?;
+            let PathTexts(mut texts) = json_data
+                .parse()
+                .context("Paths JSON must be an object: {\"ENTRY_0/CHILD_0/1\": \"text\"}")?;
+            let mut cfg = open_cfg(&data, &options.open, warnings)?;
+            if options.collapse_trailing_ws {
+                let originals: BTreeMap<String, String> =
+                    cfg.extract_texts_by_path()?.into_iter().collect();
+                for (path, value) in &mut texts {
+                    if let Some(original) = originals.get(path) {
+                        restore_trailing_ws(value, original);
+                    }
+                }
+            }
+            let count = cfg.update_texts_by_path(&texts)?;
+            let output = save_for_update(&mut cfg, options, warnings)?;
+            (output, count)
+        }
+        (Mode::Nnk, UpdateFormat::Paths) => {
+            bail!("The paths update format is only available in standard mode");
+        }
+        (Mode::Standard, UpdateFormat::Full) => {
+            let json_data = read_update_json(input_path, options.json5)?;
+            let dumps: Vec<EntryDump> = json_data
+                .parse()
+                .context("Full JSON must be an array of entries with typed variables")?;
+            let mut cfg = open_cfg(&data, &options.open, warnings)?;
+            let count = cfg.apply_all(&dumps)?;
+            let output = save_for_update(&mut cfg, options, warnings)?;
+            (output, count)
+        }
+        (Mode::Nnk, UpdateFormat::Full) => {
+            bail!("The full update format is only available in standard mode");
+        }
+        (Mode::Nnk, UpdateFormat::Csv) => {
+            bail!("The csv update format is only available in standard mode");
+        }
+        (Mode::Standard, UpdateFormat::Txt) if options.sparse => {
+            bail!("--sparse applies to JSON input only; a TXT file always covers every slot");
+        }
+        (Mode::Standard, UpdateFormat::Txt) if options.txt_indexed => {
+            let mut cfg = open_cfg(&data, &options.open, warnings)?;
+            let mut texts = cfg.extract_texts();
+            let slots: BTreeSet<usize> = update_slots(&cfg, options)?.into_iter().collect();
+            let values =
+                parse_indexed_txt(&read_txt_content(input_path)?, input_path, texts.len())?;
+            if let Some(index) = values.keys().find(|i| !slots.contains(i)) {
+                bail!(
+                    "Index {} in {} is outside the --select-entry block",
+                    index,
+                    input_path.display()
+                );
+            }
+            let count = values.len();
+            for (index, mut value) in values {
+                if options.collapse_trailing_ws {
+                    restore_trailing_ws(&mut value, &texts[index].value);
+                }
+                texts[index].value = value;
+            }
+
+            cfg.update_texts(&texts);
+            let output = save_for_update(&mut cfg, options, warnings)?;
+            (output, count)
+        }
+        (Mode::Standard, UpdateFormat::Txt) => {
+            let mut cfg = open_cfg(&data, &options.open, warnings)?;
+            let mut texts = cfg.extract_texts();
+            let slots = update_slots(&cfg, options)?;
+            let expected = slots.len();
+            let (header_lines, lines) = read_txt_input(input_path, options)?;
+            let first_original_line = slots.first().map(|&i| texts[i].value.as_str());
+            let offset = resolve_txt_update_offset(
+                expected,
+                header_lines,
+                &lines,
+                first_original_line,
+                input_path,
+            )?;
+
+            for (&slot, mut line) in slots.iter().skip(offset).zip(lines) {
+                if options.collapse_trailing_ws {
+                    restore_trailing_ws(&mut line, &texts[slot].value);
+                }
+                texts[slot].value = line;
+            }
+
+            cfg.update_texts(&texts);
+            let output = save_for_update(&mut cfg, options, warnings)?;
+            (output, expected)
+        }
+        (Mode::Nnk, UpdateFormat::Json) => {
+            let json_data: serde_json::Value = read_update_json(input_path, options.json5)?
+                .parse()
+                .context("Failed to parse JSON file")?;
+            let mut texts = nnk::parse_json(&json_data.to_string())
+                .context("Failed to parse address-based JSON for nnk mode")?;
+            if options.collapse_trailing_ws {
+                let originals =
+                    nnk::extract(&data).context("Failed to parse cfg.bin file in nnk mode")?;
+                for (address, value) in &mut texts {
+                    if let Some(original) = originals.get(address) {
+                        restore_trailing_ws(value, original);
+                    }
+                }
+            }
+            if let Some(form) = options.normalize {
+                report_normalized(options, form, normalize_values(texts.values_mut(), form));
+            }
+            let text_count = texts.len();
+            let output =
+                nnk::patch(&data, &texts).context("Failed to patch cfg.bin in nnk mode")?;
+            (output, text_count)
+        }
+        (Mode::Nnk, UpdateFormat::Txt) => {
+            let mut texts =
+                nnk::extract(&data).context("Failed to parse cfg.bin file in nnk mode")?;
+            let mut expected = texts.len();
+            if options.txt_indexed {
+                let mut values =
+                    parse_indexed_txt(&read_txt_content(input_path)?, input_path, expected)?;
+                expected = values.len();
+                for (index, value) in texts.values_mut().enumerate() {
+                    if let Some(mut line) = values.remove(&index) {
+                        if options.collapse_trailing_ws {
+                            restore_trailing_ws(&mut line, value);
+                        }
+                        *value = line;
+                    }
+                }
+            } else {
+                let (header_lines, lines) = read_txt_input(input_path, options)?;
+                let first_original_line = texts.values().next().map(String::as_str);
+                let offset = resolve_txt_update_offset(
+                    expected,
+                    header_lines,
+                    &lines,
+                    first_original_line,
+                    input_path,
+                )?;
+
+                for ((_, value), mut line) in texts.iter_mut().skip(offset).zip(lines) {
+                    if options.collapse_trailing_ws {
+                        restore_trailing_ws(&mut line, value);
+                    }
+                    *value = line;
+                }
+            }
+            if let Some(form) = options.normalize {
+                report_normalized(options, form, normalize_values(texts.values_mut(), form));
+            }
+
+            let output =
+                nnk::patch(&data, &texts).context("Failed to patch cfg.bin in nnk mode")?;
+            (output, expected)
+        }
+    };
+    let output = match options.pad_to_original {
+        Some(byte) => {
+            let (padded, added) = pad_to_length(output, data.len(), byte)?;
+            status_line(
+                options,
+                &format!(
+                    "Padded with {} byte(s) of 0x{:02X} to the original size",
+                    added, byte
+                ),
+            );
+            padded
+        }
+        None => output,
+    };
+    // Sized before writing, so a failure here leaves the output file untouched.
+    let input_regions = CfgBin::region_sizes(&data, options.open.endian)?;
+    let output_regions = CfgBin::region_sizes(&output, options.open.endian)?;
+    let changes = if options.show_changes || options.show_changes_json.is_some() {
+        Some(written_changes(
+            &data,
+            &output,
+            &options.open,
+            options.mode,
+        )?)
+    } else {
+        None
+    };
+    write_update_output(cfg_path, out_path, &output, options)?;
+    // nnk patches strings in place, so any size change means the repack didn't fit
+    if options.mode == Mode::Nnk && output.len() != data.len() {
+        warnings.emit(Warning::new(
+            "nnk-size-change",
+            format!(
+                "nnk update changed the file size ({}); the in-place patch did not fit",
+                format_size_change(data.len(), output.len())
+            ),
+        ));
+    }
+    if options.json_events {
+        let mut event = FileEvent::ok(cfg_path, out_path, count)
+            .with_translation(input_path)
+            .with_sizes(data.len(), output.len())
+            .with_regions(input_regions, output_regions);
+        event.changed_slots = changes.as_ref().map(Vec::len);
+        emit_event(&event);
+    } else {
+        println!(
+            "Written {} ({} text entries, mode={}, update={}): {}",
+            out_path.display(),
+            count,
+            value_name(options.mode),
+            value_name(update_format),
+            format_size_change(data.len(), output.len())
+        );
+        println!(
+            "Regions: {}",
+            format_region_changes(&input_regions, &output_regions)
+        );
+    }
+    if let Some(changes) = &changes {
+        report_changes(changes, input_path, options)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usage_examples_cover_every_subcommand() {
+        let cmd = Cli::command();
+        cmd.clone().debug_assert();
+        for sub in cmd.get_subcommands() {
+            let invocation = format!("cfg_bin_text_editor {} ", sub.get_name());
+            assert!(
+                USAGE_EXAMPLES.contains(&invocation),
+                "{} is missing from USAGE_EXAMPLES",
+                sub.get_name()
+            );
+        }
+    }
+
+    #[test]
+    fn shared_strings_reports_values_recurring_across_files() {
+        let text = |index: usize, value: &str| TextEntry {
+            index,
+            entry: "TEXT_INFO".to_string(),
+            variable_index: 1,
+            value: value.to_string(),
+            hash: None,
+        };
+        let files = vec![
+            (
+                "a".to_string(),
+                vec![text(0, "Yes"), text(1, "No"), text(2, "Yes"), text(3, "")],
+            ),
+            (
+                "b".to_string(),
+                vec![text(0, "No"), text(1, "Yes"), text(2, "")],
+            ),
+            ("c".to_string(), vec![text(0, "Yes"), text(1, "Only here")]),
+        ];
+
+        let shared = collect_shared_strings(&files, 2);
+        let summary: Vec<(&str, usize, usize)> = shared
+            .iter()
+            .map(|s| (s.value.as_str(), s.files, s.locations.len()))
+            .collect();
+        assert_eq!(summary, [("Yes", 3, 4), ("No", 2, 2)]);
+        assert_eq!(shared[1].locations[1].file, "b");
+        assert_eq!(shared[1].locations[1].index, 0);
+
+        assert_eq!(collect_shared_strings(&files, 3).len(), 1);
+    }
+
+    #[test]
+    fn replace_counts_occurrences_and_refuses_unencodable_results() -> Result<()> {
+        let request = |from: &str, to: &str, regex: bool| -> Result<ReplaceRequest> {
+            Ok(ReplaceRequest {
+                matcher: build_find_matcher(from, regex, true)?,
+                replacement: to.to_string(),
+                literal: !regex,
+                dry_run: false,
+            })
+        };
+        // 2 blocks of 3 rows: "Text N of block B" each, plus a shared "Shared 0"
+        let mut cfg = cfgbin::synthetic::build(2, 3);
+        assert_eq!(
+            replace_texts(&mut cfg, &request("block", "group", false)?)?,
+            (6, 6)
+        );
+        assert_eq!(
+            replace_texts(&mut cfg, &request(r"Text (\d)", "Line $1$", true)?)?,
+            (6, 6)
+        );
+        assert_eq!(cfg.extract_texts()[0].value, "Line 0$ of group 0");
+        assert_eq!(
+            replace_texts(&mut cfg, &request("$1", "x", false)?)?,
+            (0, 0)
+        );
+
+        cfg.encoding = CfgBinEncoding::ShiftJis;
+        let err = replace_texts(&mut cfg, &request("Shared", "\u{1F600}", false)?).unwrap_err();
+        assert!(err.to_string().contains("shift-jis"), "{}", err);
+        Ok(())
+    }
+
+    #[test]
+    fn replace_refuses_nnk_mode_and_overwriting_the_container() {
+        let replace = ["cfg_bin_text_editor", "replace", "game.bin", "a", "b"];
+        let run_with = |flags: &[&str], mode: Mode| {
+            let mut cli = Cli::try_parse_from([&replace[..], flags].concat()).unwrap();
+            // As set by a config file's mode = "nnk"; --mode can't be given with a subcommand
+            cli.mode = mode;
+            run(cli, &mut Warnings::default()).unwrap_err().to_string()
+        };
+        let err = run_with(&[], Mode::Nnk);
+        assert!(err.contains("only supported in standard mode"), "{}", err);
+        let err = run_with(&["--offset", "0x10", "--length", "0x20"], Mode::Standard);
+        assert!(err.contains("pass -o or --in-container"), "{}", err);
+    }
+
+    #[test]
+    fn verify_marks_the_differing_byte_in_both_windows() -> Result<()> {
+        let data = cfgbin::synthetic::build(1, 2).save()?;
+        let cfg = CfgBin::open(&data)?;
+        let plain = standard_save_options(&cfg, false, false);
+        assert_eq!(roundtrip_failure(&cfg, &data, &plain), None);
+
+        let mut extended = data.clone();
+        extended.push(0xAB);
+        let report = roundtrip_failure(&cfg, &extended, &plain).unwrap();
+        let lines: Vec<&str> = report.lines().collect();
+        assert!(lines[0].contains(&format!("offset 0x{:X}", data.len())));
+        assert!(lines[1].trim_start().starts_with("original"));
+        assert!(lines[1].ends_with(" [AB]"), "{}", lines[1]);
+        assert!(lines[2].ends_with(" (end of file)"), "{}", lines[2]);
+
+        // A file whose key table isn't in the usual order only re-saves identically the way an
+        // update with --preserve-key-order would.
+        let source = cfgbin::synthetic::build(1, 2);
+        let reordered = source.save_with_options(&SaveOptions {
+            key_order: Some(vec!["TEXT_INFO_END".to_string(), "TEXT_INFO".to_string()]),
+            ..SaveOptions::default()
+        })?;
+        let cfg = CfgBin::open(&reordered)?;
+        assert!(roundtrip_failure(&cfg, &reordered, &plain).is_some());
+        let preserved = standard_save_options(&cfg, true, false);
+        assert_eq!(roundtrip_failure(&cfg, &reordered, &preserved), None);
+        Ok(())
+    }
+
+    #[test]
+    fn audit_grades_findings_and_fails_only_on_errors() -> Result<()> {
+        let path = Path::new("a.cfg.bin");
+        let options = OpenOptions::default();
+        let data = cfgbin::synthetic::build(1, 2).save()?;
+        let report = audit_report(path, &data, &options);
+        assert!(report.passed);
+        assert!(report.findings.is_empty(), "{:?}", report.findings);
+        assert_eq!(report.checks.last(), Some(&"modes"));
+
+        let mut unclosed = cfgbin::synthetic::build(1, 2);
+        unclosed.entries[0].end_terminator = false;
+        let report = audit_report(path, &unclosed.save()?, &options);
+        assert!(report.passed);
+        assert_eq!(report.count(Severity::Error), 0);
+        assert_eq!(report.findings[0].check, "blocks");
+        assert!(report.findings[0].message.contains("TEXT_INFO_BEGIN_0"));
+
+        let report = audit_report(path, &data[..12], &options);
+        assert!(!report.passed);
+        assert_eq!(report.checks, ["header"]);
+        assert_eq!(report.findings[0].severity, Severity::Error);
+        Ok(())
+    }
+
+    #[test]
+    fn forced_encoding_reads_a_mislabeled_file_and_fixes_its_footer() -> Result<()> {
+        let mut source = cfgbin::synthetic::build(1, 2);
+        source.encoding = CfgBinEncoding::ShiftJis;
+        source.map_texts(|s| Some(format!("{} \u{3042}", s)));
+        let mut data = source.save()?;
+        let magic = data
+            .windows(4)
+            .rposition(|w| w == 0x62327401u32.to_le_bytes())
+            .unwrap();
+        data[magic + 6..magic + 8].copy_from_slice(&1u16.to_le_bytes());
+        assert_eq!(cfgbin::detect_encoding(&data).0, CfgBinEncoding::Utf8);
+
+        let parse = |encoding: &str| -> Result<Option<CfgBinEncoding>> {
+            let args = [
+                "cfg_bin_text_editor",
+                "--encoding",
+                encoding,
+                "-e",
+                "a.cfg.bin",
+            ];
+            Ok(Cli::try_parse_from(args)?.encoding.forced())
+        };
+        assert_eq!(parse("auto")?, None);
+        assert_eq!(parse("sjis")?, Some(CfgBinEncoding::ShiftJis));
+        let options = OpenOptions {
+            encoding: parse("shiftjis")?,
+            ..OpenOptions::default()
+        };
+        let cfg = CfgBin::open_with_options(&data, &options)?;
+        assert_eq!(cfg.extract_texts()[0].value, "Text 0 of block 0 \u{3042}");
+        assert_eq!(
+            cfgbin::detect_encoding(&cfg.save()?),
+            (CfgBinEncoding::ShiftJis, 0)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn output_encoding_converts_shift_jis_to_utf8_and_checks_encodability() -> Result<()> {
+        let mut source = cfgbin::synthetic::build(1, 2);
+        source.encoding = CfgBinEncoding::ShiftJis;
+        source.map_texts(|s| Some(format!("{} \u{3042}", s)));
+        let sjis = CfgBin::open(&source.save()?)?;
+        assert_eq!(sjis.encoding, CfgBinEncoding::ShiftJis);
+
+        let mut cfg = CfgBin::open(&sjis.save()?)?;
+        set_output_encoding(&mut cfg, CfgBinEncoding::Utf8)?;
+        let utf8 = CfgBin::open(&cfg.save()?)?;
+        assert_eq!(
+            (utf8.encoding, utf8.footer_encoding),
+            (CfgBinEncoding::Utf8, 1)
+        );
+        assert!(utf8.structurally_eq(&sjis));
+
+        let mut back = CfgBin::open(&cfg.save()?)?;
+        back.map_texts(|_| Some("\u{1F600}".to_string()));
+        let err = set_output_encoding(&mut back, CfgBinEncoding::ShiftJis).unwrap_err();
+        assert!(err.to_string().starts_with("4 value(s)"), "{}", err);
+        assert_eq!(back.encoding, CfgBinEncoding::Utf8);
+        Ok(())
+    }
+
+    #[test]
+    fn output_encoding_with_preserved_key_order_rehashes_and_reencodes_keys() -> Result<()> {
+        let mut source = cfgbin::synthetic::build(1, 1);
+        source.encoding = CfgBinEncoding::ShiftJis;
+        let label = cfgbin::Entry {
+            name: "ラベル_0".to_string(),
+            source_name: Some("ラベル".to_string()),
+            variables: vec![cfgbin::Variable {
+                var_type: VarType::Int,
+                value: cfgbin::VarValue::Int(1),
+            }],
+            children: Vec::new(),
+            end_terminator: false,
+        };
+        source.entries.push(cfgbin::Entry {
+            name: "ラベル_BEGIN_0".to_string(),
+            source_name: Some("ラベル_BEGIN".to_string()),
+            variables: Vec::new(),
+            children: vec![label],
+            end_terminator: true,
+        });
+        let order = [
+            "ラベル_END",
+            "ラベル",
+            "ラベル_BEGIN",
+            "TEXT_INFO_END",
+            "TEXT_INFO",
+            "TEXT_INFO_BEGIN",
+        ];
+        let sjis = source.save_with_options(&SaveOptions {
+            key_order: Some(order.map(String::from).to_vec()),
+            ..SaveOptions::default()
+        })?;
+
+        let mut cfg = CfgBin::open(&sjis)?;
+        assert_eq!(cfg.key_order, order);
+        set_output_encoding(&mut cfg, CfgBinEncoding::Utf8)?;
+        let save_options = SaveOptions {
+            key_order: Some(cfg.key_order.clone()),
+            preserve_key_strings: true,
+            ..SaveOptions::default()
+        };
+        let utf8 = CfgBin::open(&cfg.save_with_options(&save_options)?)?;
+        assert_eq!(utf8.encoding, CfgBinEncoding::Utf8);
+        assert_eq!(utf8.key_order, order);
+        assert_eq!(
+            utf8.original_key_crcs["ラベル"],
+            cfg_bin_text_editor::crc32::compute("ラベル".as_bytes())
+        );
+        assert!(utf8.structurally_eq(&CfgBin::open(&sjis)?));
+        Ok(())
+    }
+
+    #[test]
+    fn reference_footer_is_copied_to_the_saved_file() -> Result<()> {
+        let mut reference = cfgbin::synthetic::build(1, 1);
+        reference.footer_encoding = 0x0101;
+        reference.footer_unk1 = 0x1234;
+        reference.footer_unk2 = 7;
+        let reference_data = reference.save()?;
+        let footer = FooterSettings::of(&CfgBin::open(&reference_data)?);
+
+        let mut source = cfgbin::synthetic::build(2, 3);
+        source.encoding = CfgBinEncoding::ShiftJis;
+        let mut cfg = CfgBin::open(&source.save()?)?;
+        footer.apply(&mut cfg)?;
+        let saved = cfg.save()?;
+        let tail = |data: &[u8]| data[data.len() - 16..].to_vec();
+        assert_eq!(tail(&saved), tail(&reference_data));
+        assert_eq!(FooterSettings::of(&CfgBin::open(&saved)?), footer);
+        Ok(())
+    }
+
+    #[test]
+    fn validate_refuses_nnk_mode() {
+        let args = ["cfg_bin_text_editor", "validate", "game.bin", "tr.json"];
+        let mut cli = Cli::try_parse_from(args).unwrap();
+        cli.mode = Mode::Nnk;
+        let err = run(cli, &mut Warnings::default()).unwrap_err().to_string();
+        assert!(
+            err.contains("validate is only supported in standard mode"),
+            "{}",
+            err
+        );
+    }
+
+    fn validate_options(sparse: bool, max_length: Option<usize>) -> ValidateOptions {
+        ValidateOptions {
+            sparse,
+            max_length,
+            var_type: VarType::String,
+            select_entry: None,
+            json5: false,
+        }
+    }
+
+    #[test]
+    fn translation_problems_cover_indices_slots_encoding_and_length() {
+        let mut cfg = cfgbin::synthetic::build(1, 2);
+        cfg.encoding = CfgBinEncoding::ShiftJis;
+        let texts = cfg.extract_texts();
+        assert_eq!(texts.len(), 4);
+        assert!(translation_problems(&cfg, &texts, &validate_options(false, None)).is_empty());
+
+        let mut edited = texts.clone();
+        edited[0].value = "\u{1F600}".to_string();
+        edited[1].variable_index += 1;
+        edited[2].value = "x".repeat(30);
+        edited.pop();
+        edited.push(texts[0].clone());
+        let problems = translation_problems(&cfg, &edited, &validate_options(false, Some(20)));
+        let expected = [
+            "index 0 is listed more than once",
+            "1 of 4 text slots have no entry (indices 3)",
+            "can't be encoded as shift-jis",
+            "index 1 is recorded as",
+            "is 30 characters long (max 20)",
+        ];
+        assert_eq!(problems.len(), expected.len(), "{:?}", problems);
+        for (problem, expected) in problems.iter().zip(expected) {
+            assert!(problem.contains(expected), "{}", problem);
+        }
+        assert_eq!(
+            translation_problems(&cfg, &edited, &validate_options(true, None)).len(),
+            3
+        );
+    }
+
+    #[test]
+    fn translation_problems_follow_var_type_and_select_entry() {
+        let mut cfg = cfgbin::synthetic::build(2, 2);
+        cfg.entries[1].source_name = Some("MENU_BEGIN".to_string());
+        let mut ints = cfg.extract_values(VarType::Int, false, false);
+        ints[3].value = "one".to_string();
+        let mut options = ValidateOptions {
+            var_type: VarType::Int,
+            ..validate_options(false, None)
+        };
+        let problems = translation_problems(&cfg, &ints, &options);
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+        assert!(
+            problems[0].contains("is not a valid int value"),
+            "{}",
+            problems[0]
+        );
+
+        // Only the slots under MENU_BEGIN have to be listed
+        let menu = &ints[2..];
+        assert_eq!(translation_problems(&cfg, menu, &options).len(), 2);
+        options.select_entry = Some("MENU_BEGIN".to_string());
+        assert_eq!(translation_problems(&cfg, menu, &options).len(), 1);
+    }
+
+    fn extract_options() -> ExtractOptions {
+        ExtractOptions {
+            verbose: false,
+            json_events: false,
+            with_hash: false,
+            leaves_only: false,
+            var_type: VarType::String,
+            baseline: None,
+            mode: Mode::Standard,
+            extract_format: ExtractFormat::Json,
+            multiline_txt: false,
+            txt_indexed: false,
+            collapse_trailing_ws: false,
+            header_lines: 0,
+            trailing_newline: false,
+            line_ending: LineEnding::Lf,
+            normalize: None,
+            embedded: None,
+            open: OpenOptions::default(),
+        }
+    }
+
+    #[test]
+    fn trailing_whitespace_collapses_to_a_marker_and_is_restored() -> Result<()> {
+        assert_eq!(
+            collapse_trailing_ws("Name  \t")?.as_deref(),
+            Some("Name[[ws]]")
+        );
+        assert_eq!(collapse_trailing_ws("Name")?, None);
+        assert_eq!(collapse_trailing_ws("\u{3000}")?.as_deref(), Some("[[ws]]"));
+        assert!(collapse_trailing_ws("Odd[[ws]]").is_err());
+
+        let mut source = cfgbin::synthetic::build(1, 2);
+        source.map_texts(|s| Some(format!("{}  \u{3000}", s)));
+        let data = source.save()?;
+        let options = ExtractOptions {
+            collapse_trailing_ws: true,
+            ..extract_options()
+        };
+        let extracted =
+            open_cfg_for_extract(&data, &options, &mut Warnings::default())?.extract_texts();
+        assert_eq!(extracted[0].value, "Text 0 of block 0[[ws]]");
+
+        let originals = CfgBin::open(&data)?.extract_texts();
+        let mut kept = "Texte 0[[ws]]".to_string();
+        restore_trailing_ws(&mut kept, &originals[0].value);
+        assert_eq!(kept, "Texte 0  \u{3000}");
+        let mut changed = "Texte 0 ".to_string();
+        restore_trailing_ws(&mut changed, &originals[0].value);
+        assert_eq!(changed, "Texte 0 ");
+        Ok(())
+    }
+
+    #[test]
+    fn hashes_are_of_the_source_value_before_normalization() -> Result<()> {
+        let mut source = cfgbin::synthetic::build(1, 1);
+        let decomposed = "Cafe\u{301}";
+        source.map_texts(|_| Some(decomposed.to_string()));
+        let data = source.save()?;
+        let options = ExtractOptions {
+            with_hash: true,
+            normalize: Some(Normalization::Nfc),
+            ..extract_options()
+        };
+        let values = extract_json_values(&data, &options, &mut Warnings::default())?;
+        assert_eq!(values[0].value, "Caf\u{E9}");
+        let source_hash = format!(
+            "{:08x}",
+            cfg_bin_text_editor::crc32::compute(decomposed.as_bytes())
+        );
+        assert_eq!(values[0].hash.as_deref(), Some(source_hash.as_str()));
+        Ok(())
+    }
+
+    #[test]
+    fn normalization_composes_or_folds_compatibility_forms() {
+        let decomposed = "Cafe\u{301} \u{30AB}\u{3099}";
+        assert_eq!(Normalization::Nfc.apply(decomposed), "Caf\u{E9} \u{30AC}");
+
+        let half_width = "\u{FF76}\u{FF9E}ABC";
+        assert_eq!(Normalization::Nfc.apply(half_width), half_width);
+        assert_eq!(Normalization::Nfkc.apply(half_width), "\u{30AC}ABC");
+
+        let mut values = [decomposed.to_string(), "plain".to_string()];
+        assert_eq!(normalize_values(values.iter_mut(), Normalization::Nfc), 1);
+        assert_eq!(values[0], "Caf\u{E9} \u{30AC}");
+    }
+
+    #[test]
+    fn pad_to_length_fills_up_to_the_original_size_or_fails() -> Result<()> {
+        assert_eq!(
+            pad_to_length(vec![1, 2], 5, 0xFF)?,
+            (vec![1, 2, 0xFF, 0xFF, 0xFF], 3)
+        );
+        assert_eq!(pad_to_length(vec![1, 2], 2, 0x00)?, (vec![1, 2], 0));
+        assert!(pad_to_length(vec![1, 2, 3], 2, 0xFF).is_err());
+
+        assert_eq!(parse_byte("0xff")?, 0xFF);
+        assert_eq!(parse_byte("0")?, 0);
+        assert!(parse_byte("256").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn file_events_name_the_cfg_bin_and_translation_separately() {
+        let ok = FileEvent::ok(Path::new("cfgs/a.cfg.bin"), Path::new("out/a.cfg.bin"), 5)
+            .with_translation(Path::new("tr/a.json"));
+        assert_eq!(
+            serde_json::to_string(&ok).unwrap(),
+            r#"{"cfg_bin":"cfgs/a.cfg.bin","translation":"tr/a.json","status":"ok","output":"out/a.cfg.bin","entries":5}"#
+        );
+        let error = FileEvent::error(None, &anyhow::anyhow!("unpaired"))
+            .with_translation(Path::new("tr/b.json"));
+        assert_eq!(
+            serde_json::to_string(&error).unwrap(),
+            r#"{"translation":"tr/b.json","status":"error","message":"unpaired"}"#
+        );
+    }
+
+    #[test]
+    fn fail_fast_stops_at_the_first_failure_and_keep_going_runs_everything() {
+        let inputs: Vec<PathBuf> = ["a.json", "bad1.json", "c.json", "bad2.json"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        let process = |path: &Path| {
+            if path.to_string_lossy().starts_with("bad") {
+                bail!("broken");
+            }
+            Ok(())
+        };
+
+        let all = run_batch(&inputs, false, false, process);
+        let failed: Vec<&Path> = all.failures.iter().map(|(p, _)| p.as_path()).collect();
+        assert_eq!(failed, [Path::new("bad1.json"), Path::new("bad2.json")]);
+        assert_eq!(all.skipped, 0);
+
+        let mut seen = Vec::new();
+        let first = run_batch(&inputs, true, false, |path| {
+            seen.push(path.to_path_buf());
+            process(path)
+        });
+        assert_eq!(first.failures.len(), 1);
+        assert_eq!(first.skipped, 2);
+        assert_eq!(seen.len(), 2);
+
+        let batch = [
+            "cfg_bin_text_editor",
+            "-w",
+            "cfgs",
+            "--input-glob",
+            "tr/*.json",
+        ];
+        let parse = |flags: &[&str]| Cli::try_parse_from([&batch[..], flags].concat());
+        assert!(!parse(&["--fail-fast", "--keep-going"]).unwrap().fail_fast);
+        assert!(parse(&["--keep-going", "--fail-fast"]).unwrap().fail_fast);
+    }
+
+    #[test]
+    fn timestamp_is_read_and_set_after_validation() -> Result<()> {
+        let mut cfg = cfgbin::synthetic::build(1, 2);
+        let err = current_timestamp(&cfg).unwrap_err();
+        assert!(err.to_string().contains("not a YYYY/MM/DD"), "{}", err);
+
+        let mut texts = cfg.extract_texts();
+        texts[0].value = "2023/05/04 10:20:30".to_string();
+        cfg.update_texts(&texts);
+        assert_eq!(current_timestamp(&cfg)?, "2023/05/04 10:20:30");
+
+        for bad in [
+            "2024-01-31 12:00:00",
+            "2024/13/01 00:00:00",
+            "2024/01/31 24:00:00",
+        ] {
+            assert!(set_timestamp(&mut cfg, bad).is_err(), "{}", bad);
+        }
+        assert_eq!(
+            set_timestamp(&mut cfg, "2024/01/31 12:00:00")?,
+            "2023/05/04 10:20:30"
+        );
+        let after = cfg.extract_texts();
+        assert_eq!(after[0].value, "2024/01/31 12:00:00");
+        assert_eq!(after[1].value, texts[1].value);
+        Ok(())
+    }
+
+    #[test]
+    fn embedded_range_slices_and_splices_only_same_length_blobs() -> Result<()> {
+        let container = b"HEADcfgbinTAIL";
+        let range = EmbeddedRange {
+            offset: 4,
+            length: 6,
+        };
+        assert_eq!(range.slice(container)?, b"cfgbin");
+        assert_eq!(range.splice(container, b"CFGBIN")?, b"HEADCFGBINTAIL");
+
+        let err = range.splice(container, b"longer!").unwrap_err();
+        assert!(err.to_string().contains("doesn't fit"), "{}", err);
+        let outside = EmbeddedRange {
+            offset: 10,
+            length: 5,
+        };
+        assert!(outside.slice(container).is_err());
+        assert!(EmbeddedRange {
+            offset: usize::MAX,
+            length: 2
+        }
+        .slice(container)
+        .is_err());
+        assert_eq!(parse_size("0x1A00")?, 0x1A00);
+        Ok(())
+    }
+
+    #[test]
+    fn written_changes_list_only_slots_whose_value_changed() -> Result<()> {
+        let mut cfg = cfgbin::synthetic::build(1, 3);
+        let data = cfg.save()?;
+        let mut texts = cfg.extract_texts();
+        let old = std::mem::replace(&mut texts[1].value, "x".repeat(50));
+        cfg.update_texts(&texts);
+
+        let options = OpenOptions::default();
+        let changes = written_changes(&data, &cfg.save()?, &options, Mode::Standard)?;
+        assert_eq!(changes.len(), 1);
+        assert_eq!(
+            format_change(&changes[0]),
+            format!("1/{}: {:?} -> {:?}...", texts[1].entry, old, "x".repeat(40))
+        );
+
+        // nnk output is read back by address, the way it was written.
+        let mut by_address = nnk::extract(&data)?;
+        let (&address, value) = by_address.iter_mut().nth(2).unwrap();
+        *value = "patched".to_string();
+        let patched = nnk::patch(&data, &by_address)?;
+        let changes = written_changes(&data, &patched, &options, Mode::Nnk)?;
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].index, 2);
+        assert_eq!(changes[0].entry, format!("0x{:08X}", address));
+        assert_eq!(changes[0].new, "patched");
+        assert_eq!(
+            ChangesJson::PerInput(PathBuf::from("out")).path_for(Path::new("tr/a.json")),
+            Path::new("out/a.json.changes.json")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn region_changes_list_each_region() {
+        let before = RegionSizes {
+            entries: 1024,
+            string_table: 2048,
+            key_table: 96,
+        };
+        let after = RegionSizes {
+            string_table: 2112,
+            ..before
+        };
+        assert_eq!(
+            format_region_changes(&before, &after),
+            "entries 1,024 → 1,024 bytes (unchanged), string table 2,048 → 2,112 bytes (+64), key table 96 → 96 bytes (unchanged)"
+        );
+    }
+
+    #[test]
+    fn size_change_groups_thousands_and_signs_the_delta() {
+        assert_eq!(
+            format_size_change(12_340, 12_512),
+            "12,340 → 12,512 bytes (+172)"
+        );
+        assert_eq!(
+            format_size_change(1_000_000, 999_990),
+            "1,000,000 → 999,990 bytes (-10)"
+        );
+        assert_eq!(format_size_change(512, 512), "512 → 512 bytes (unchanged)");
+    }
+
+    #[test]
+    fn paths_json_refuses_a_path_given_twice() -> Result<()> {
+        let PathTexts(texts) = parse_update_json(r#"{"A_0/0": "a", "B_0/0": "b"}"#)?;
+        assert_eq!(texts.len(), 2);
+        let duplicate = r#"{"A_0/0": "a", "A_0/0": "b"}"#;
+        let err = parse_update_json::<PathTexts>(duplicate).unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("duplicate path A_0/0"),
+            "{:#}",
+            err
+        );
+        let err = parse_update_json5::<PathTexts>(duplicate).unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("duplicate path A_0/0"),
+            "{:#}",
+            err
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn json_parse_errors_quote_the_offending_line() {
+        let content = "[\n  { \"index\": 0, \"value\": \"Say \"hi\"\" }\n]";
+        let err = parse_update_json::<Vec<TextEntry>>(content).unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("line 2 column 32"), "{}", message);
+        let caret = format!("\n  | {}^", " ".repeat(31));
+        assert!(message.ends_with(&format!(
+            "\n2 |   {{ \"index\": 0, \"value\": \"Say \"hi\"\" }}{}",
+            caret
+        )));
+
+        let err = parse_update_json::<Vec<TextEntry>>("[\n  {\"index\": 0").unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("EOF while parsing"), "{}", message);
+        assert!(
+            message.ends_with("\n2 |   {\"index\": 0\n  |             ^"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn json5_update_input_allows_comments_and_trailing_commas() -> Result<()> {
+        let content = r#"[
+            // TODO: check the shop wording with the team
+            { index: 0, entry: "TEXT_INFO", variable_index: 1, value: "Shop", },
+            /* unchanged */
+            { "index": 1, "entry": "TEXT_INFO", "variable_index": 1, "value": "Inn" },
+        ]"#;
+        assert!(serde_json::from_str::<Vec<TextEntry>>(content).is_err());
+
+        let texts: Vec<TextEntry> = parse_update_json5(content)?;
+        assert_eq!(texts.len(), 2);
+        assert_eq!((texts[0].index, texts[0].value.as_str()), (0, "Shop"));
+        assert_eq!(texts[1].entry, "TEXT_INFO");
+        assert_eq!(texts[1].value, "Inn");
+
+        // Errors point into the JSON5 source, not a re-serialized copy.
+        let missing = "[\n  // note\n  { index: 0, entry: \"TEXT_INFO\", variable_index: 1 },\n]";
+        let err = parse_update_json5::<Vec<TextEntry>>(missing).unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("missing field `value`"), "{}", message);
+        assert!(message.contains(" at line 3 column 3"), "{}", message);
+        assert!(message.ends_with("\n  |   ^"), "{}", message);
+        Ok(())
+    }
+
+    #[test]
+    fn multiline_txt_round_trips_embedded_newlines() -> Result<()> {
+        let values = ["one", "two\nlines", "", "back\\slash"];
+        let content = format_multiline_txt(&values)?;
+        assert_eq!(
+            content,
+            "one\n---\ntwo\nlines\n---\n\n---\nback\\slash\n---"
+        );
+        assert_eq!(split_multiline_txt(&content), values);
+        assert_eq!(split_multiline_txt(&format!("{}\n", content)), values);
+        // Exports without the closing separator still read.
+        assert_eq!(split_multiline_txt("one\n---\nlast\n"), ["one", "last"]);
+        Ok(())
+    }
+
+    #[test]
+    fn multiline_txt_round_trips_empty_first_middle_and_last_values() -> Result<()> {
+        let cases: [&[&str]; 5] = [&["", "a"], &["a", "", "b"], &["a", ""], &[""], &[]];
+        for values in cases {
+            let content = format_multiline_txt(values)?;
+            assert_eq!(split_multiline_txt(&content), values, "{:?}", content);
+            if !values.is_empty() {
+                assert_eq!(split_multiline_txt(&format!("{}\n", content)), values);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn txt_export_keeps_an_empty_last_value() -> Result<()> {
+        let cases: [&[&str]; 3] = [&["a", ""], &["", ""], &[""]];
+        for values in cases {
+            for trailing_newline in [false, true] {
+                let options = ExtractOptions {
+                    extract_format: ExtractFormat::Txt,
+                    trailing_newline,
+                    ..extract_options()
+                };
+                let content = format_txt_export(values, &options)?;
+                assert!(content.ends_with('\n'), "{:?}", content);
+                assert_eq!(parse_txt_lines(&content), values, "{:?}", content);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn txt_export_reads_back_the_same_with_or_without_trailing_newline() -> Result<()> {
+        let values = ["one", "", "two\nlines", "last"];
+        for (multiline_txt, line_ending) in [
+            (false, LineEnding::Lf),
+            (true, LineEnding::Lf),
+            (false, LineEnding::Crlf),
+            (true, LineEnding::Crlf),
+        ] {
+            let mut read_back = Vec::new();
+            for trailing_newline in [false, true] {
+                let options = ExtractOptions {
+                    extract_format: ExtractFormat::Txt,
+                    multiline_txt,
+                    trailing_newline,
+                    line_ending,
+                    ..extract_options()
+                };
+                let content = format_txt_export(&values, &options)?;
+                assert_eq!(content.ends_with('\n'), trailing_newline);
+                let bare_lf = content.replace("\r\n", "").contains('\n');
+                assert_eq!(bare_lf, line_ending == LineEnding::Lf);
+                let content = normalize_line_endings(&content);
+                let lines = if multiline_txt {
+                    split_multiline_txt(&content)
+                } else {
+                    parse_txt_lines(&content)
+                };
+                read_back.push(lines);
+            }
+            assert_eq!(read_back[0], values);
+            assert_eq!(read_back[1], values);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn txt_lines_keep_unknown_escapes_verbatim() {
+        assert_eq!(
+            parse_txt_lines("a\\nb\\\\c\nC:\\q\\"),
+            ["a\nb\\c", "C:\\q\\"]
+        );
+    }
+
+    #[test]
+    fn baseline_keeps_only_new_and_changed_slots() {
+        let text = |index: usize, entry: &str, value: &str| TextEntry {
+            index,
+            entry: entry.to_string(),
+            variable_index: 0,
+            value: value.to_string(),
+            hash: None,
+        };
+        let baseline = [
+            text(0, "ITEM", "Sword"),
+            text(1, "ITEM", "Shield"),
+            text(2, "HELP", "Old"),
+        ];
+        // A new ITEM was inserted in front of HELP, shifting its index, and HELP was reworded.
+        let current = vec![
+            text(0, "ITEM", "Sword"),
+            text(1, "ITEM", "Shield"),
+            text(2, "ITEM", "Bow"),
+            text(3, "HELP", "New"),
+        ];
+        let changed = changed_since_baseline(current, &baseline);
+        let found: Vec<(usize, &str)> = changed
+            .iter()
+            .map(|t| (t.index, t.value.as_str()))
+            .collect();
+        assert_eq!(found, [(2, "Bow"), (3, "New")]);
+    }
+
+    #[test]
+    fn project_config_fills_defaults_that_the_command_line_overrides() -> Result<()> {
+        let config = parse_project_config(
+            "mode = \"addr\"\nextract-format = \"txt\"\nnormalize = \"nfc\"\nline-ending = \"crlf\"\ntxt-indexed = true\n",
+        )?;
+        let args = [
+            "cfg_bin_text_editor",
+            "-e",
+            "a.cfg.bin",
+            "--extract-format",
+            "json",
+        ];
+        let matches = Cli::command().try_get_matches_from(args)?;
+        let mut cli = Cli::from_arg_matches(&matches)?;
+        apply_project_config(&mut cli, &matches, &config)?;
+        assert_eq!(cli.mode, Mode::Nnk);
+        assert_eq!(cli.extract_format, ExtractFormat::Json);
+        assert_eq!(cli.normalize, Some(Normalization::Nfc));
+        assert_eq!(cli.line_ending, LineEnding::Crlf);
+        assert!(cli.txt_indexed);
+
+        let off = [&args[..], &["--no-txt-indexed"]].concat();
+        let matches = Cli::command().try_get_matches_from(off)?;
+        let mut cli = Cli::from_arg_matches(&matches)?;
+        apply_project_config(&mut cli, &matches, &config)?;
+        assert!(!cli.txt_indexed);
+        let keep_off = parse_project_config("preserve-key-order = false\n")?;
+        let on = [&args[..], &["--preserve-key-order"]].concat();
+        let matches = Cli::command().try_get_matches_from(on)?;
+        let mut cli = Cli::from_arg_matches(&matches)?;
+        apply_project_config(&mut cli, &matches, &keep_off)?;
+        assert!(cli.preserve_key_order);
+
+        let nnk = parse_project_config("mode = \"nnk\"\n")?;
+        let find = ["cfg_bin_text_editor", "find", "a.cfg.bin", "x"];
+        for (args, expected) in [
+            (&find[..], Mode::Nnk),
+            (
+                &[&find[..], &["--mode", "standard"]].concat()[..],
+                Mode::Standard,
+            ),
+        ] {
+            let matches = Cli::command().try_get_matches_from(args)?;
+            let mut cli = Cli::from_arg_matches(&matches)?;
+            apply_project_config(&mut cli, &matches, &nnk)?;
+            match cli.command {
+                Some(Command::Find { mode, .. }) => assert_eq!(mode, expected, "{:?}", args),
+                _ => panic!("expected the find subcommand"),
+            }
+        }
+
+        assert!(parse_project_config("modes = \"nnk\"").is_err());
+        let bad_mode = parse_project_config("mode = \"fast\"")?;
+        assert!(apply_project_config(&mut cli, &matches, &bad_mode).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn project_config_encoding_yields_to_encoding_flags() -> Result<()> {
+        let config = parse_project_config("encoding = \"sjis\"\n")?;
+        let apply = |args: &[&str]| -> Result<EncodingArg> {
+            let matches = Cli::command().try_get_matches_from(args)?;
+            let mut cli = Cli::from_arg_matches(&matches)?;
+            apply_project_config(&mut cli, &matches, &config)?;
+            Ok(cli.encoding)
+        };
+        let extract = ["cfg_bin_text_editor", "-e", "a.cfg.bin"];
+        assert_eq!(apply(&extract)?, EncodingArg::ShiftJis);
+        let utf8 = [&extract[..], &["--encoding", "utf8"]].concat();
+        assert_eq!(apply(&utf8)?, EncodingArg::Utf8);
+        let auto = [&extract[..], &["--auto-reencode"]].concat();
+        assert_eq!(apply(&auto)?, EncodingArg::Auto);
+        Ok(())
+    }
+
+    #[test]
+    fn json_only_extract_flags_are_refused_for_other_formats() {
+        for extract_format in [ExtractFormat::Csv, ExtractFormat::Txt] {
+            for (flag, options) in [
+                (
+                    "--leaves-only",
+                    ExtractOptions {
+                        leaves_only: true,
+                        extract_format,
+                        ..extract_options()
+                    },
+                ),
+                (
+                    "--with-hash",
+                    ExtractOptions {
+                        with_hash: true,
+                        extract_format,
+                        ..extract_options()
+                    },
+                ),
+            ] {
+                let err = check_extract_flags(&options).unwrap_err();
+                assert!(err.to_string().starts_with(flag), "{}", err);
+            }
+        }
+        let json = ExtractOptions {
+            leaves_only: true,
+            with_hash: true,
+            ..extract_options()
+        };
+        assert!(check_extract_flags(&json).is_ok());
+    }
+
+    fn update_options() -> UpdateOptions {
+        UpdateOptions {
+            verbose: false,
+            json_events: false,
+            open: OpenOptions::default(),
+            mode: Mode::Standard,
+            update_format: None,
+            multiline_txt: false,
+            txt_indexed: false,
+            collapse_trailing_ws: false,
+            preserve_key_order: false,
+            no_dedup_strings: false,
+            reencode_strings: false,
+            rename_map: None,
+            select_entry: None,
+            var_type: VarType::String,
+            sparse: false,
+            json5: false,
+            pad_to_original: None,
+            strip_empty_entries: false,
+            output_encoding: None,
+            footer_unk1: None,
+            footer_unk2: None,
+            reference_footer: None,
+            show_changes: false,
+            show_changes_json: None,
+            normalize: None,
+            embedded: None,
+            in_container: false,
+        }
+    }
+
+    #[test]
+    fn slot_flags_are_refused_for_full_updates() {
+        for (flag, options) in [
+            (
+                "--select-entry",
+                UpdateOptions {
+                    select_entry: Some("TEXT_INFO_BEGIN".to_string()),
+                    ..update_options()
+                },
+            ),
+            (
+                "--sparse",
+                UpdateOptions {
+                    sparse: true,
+                    ..update_options()
+                },
+            ),
+            (
+                "--collapse-trailing-ws",
+                UpdateOptions {
+                    collapse_trailing_ws: true,
+                    ..update_options()
+                },
+            ),
+        ] {
+            let err = check_update_flags(&options, UpdateFormat::Full).unwrap_err();
+            assert!(err.to_string().starts_with(flag), "{}", err);
+            assert!(check_update_flags(&options, UpdateFormat::Json).is_ok());
+        }
+        // Applied to every string on save, so it works for full updates too
+        let normalize = UpdateOptions {
+            normalize: Some(Normalization::Nfc),
+            ..update_options()
+        };
+        assert!(check_update_flags(&normalize, UpdateFormat::Full).is_ok());
+    }
+
+    #[test]
+    fn txt_header_lines_are_marked_on_export_and_skipped_on_update() -> Result<()> {
+        let values = ["2020/01/02 03:04:05", "Hello", "World"];
+        let options = ExtractOptions {
+            extract_format: ExtractFormat::Txt,
+            header_lines: 1,
+            ..extract_options()
+        };
+        let content = format_txt_export(&values, &options)?;
+        assert_eq!(content, "#cfgbin-header: 2020/01/02 03:04:05\nHello\nWorld");
+
+        let path = Path::new("test.txt");
+        let (header_lines, lines) = strip_txt_header(parse_txt_lines(&content));
+        assert_eq!(header_lines, 1);
+        assert_eq!(lines, ["Hello", "World"]);
+        assert_eq!(
+            resolve_txt_update_offset(3, header_lines, &lines, None, path)?,
+            1
+        );
+        assert!(resolve_txt_update_offset(4, header_lines, &lines, None, path).is_err());
+
+        let too_many = ExtractOptions {
+            header_lines: 4,
+            ..options
+        };
+        assert!(format_txt_export(&values, &too_many).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn indexed_txt_maps_lines_by_index_in_any_order() -> Result<()> {
+        let path = Path::new("test.txt");
+        let values = parse_indexed_txt("2\tc\\td\n0\ta\n", path, 3)?;
+        assert_eq!(values.len(), 2);
+        assert_eq!(values[&0], "a");
+        assert_eq!(values[&2], "c\td");
+
+        let duplicate = parse_indexed_txt("1\ta\n1\tb", path, 3).unwrap_err();
+        assert!(duplicate.to_string().contains("already appeared on line 1"));
+        assert!(parse_indexed_txt("3\ta", path, 3).is_err());
+        assert!(parse_indexed_txt("no index", path, 3).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn multiline_txt_rejects_values_containing_the_separator() {
+        assert!(format_multiline_txt(&["a\n---\nb"]).is_err());
+        assert!(format_multiline_txt(&["---"]).is_err());
+        assert!(format_multiline_txt(&["a --- b"]).is_ok());
+    }
+
+    #[test]
+    fn encoding_lines_report_the_footer_and_resolved_encoding() -> Result<()> {
+        let mut source = cfgbin::synthetic::build(2, 3);
+        source.encoding = CfgBinEncoding::ShiftJis;
+        source.footer_encoding = 0;
+        let data = source.save()?;
+        let cfg = CfgBin::open(&data)?;
+        let lines = encoding_lines(Path::new("a.cfg.bin"), &data, &cfg);
+        assert_eq!(
+            lines,
+            [
+                "file: a.cfg.bin",
+                "footer_encoding: 0x0000",
+                "detected_encoding: shift-jis",
+                "encoding: shift-jis",
+                "entries: 8",
+                "strings: 7",
+            ]
+        );
+
+        // A forced encoding doesn't hide what the footer says.
+        let forced = OpenOptions {
+            encoding: Some(CfgBinEncoding::Utf8),
+            ..OpenOptions::default()
+        };
+        let cfg = CfgBin::open_with_options(&data, &forced)?;
+        let lines = encoding_lines(Path::new("a.cfg.bin"), &data, &cfg);
+        assert_eq!(
+            lines[2..4],
+            ["detected_encoding: shift-jis", "encoding: utf8"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn capabilities_list_only_what_was_asked_for() -> Result<()> {
+        let modes = serde_json::to_value(Capabilities::of_this_build(true, false))?;
+        assert_eq!(modes, serde_json::json!({ "modes": ["standard", "nnk"] }));
+
+        let formats = serde_json::to_value(Capabilities::of_this_build(false, true))?;
+        assert!(formats.get("modes").is_none());
+        assert_eq!(
+            formats["extract_formats"][2],
+            serde_json::json!({ "name": "tree", "modes": ["standard"] })
+        );
+        let update: Vec<&str> = formats["update_formats"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| f["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(update, ["json", "txt", "paths", "csv", "full"]);
+        Ok(())
+    }
+
+    #[test]
+    fn enums_parse_case_insensitively_with_aliases() -> Result<()> {
+        assert_eq!("NNK".parse::<Mode>()?, Mode::Nnk);
+        assert_eq!(" Standard ".parse::<Mode>()?, Mode::Standard);
+        assert_eq!("address".parse::<Mode>()?, Mode::Nnk);
+        assert_eq!("Text".parse::<ExtractFormat>()?, ExtractFormat::Txt);
+        assert_eq!("tree".parse::<ExtractFormat>()?, ExtractFormat::Tree);
+        assert_eq!("JSON".parse::<UpdateFormat>()?, UpdateFormat::Json);
+        assert_eq!("text".parse::<UpdateFormat>()?, UpdateFormat::Txt);
+
+        let err = "tree".parse::<UpdateFormat>().unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "Invalid update format 'tree': expected one of json, txt, paths, csv, full (aliases: text)"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn update_format_is_detected_from_extension_then_content() {
+        assert_eq!(
+            update_format_from_extension(Path::new("a.cfg.bin.json")),
+            Some(UpdateFormat::Json)
+        );
+        assert_eq!(
+            update_format_from_extension(Path::new("a.cfg.bin.TXT")),
+            Some(UpdateFormat::Txt)
+        );
+        assert_eq!(
+            update_format_from_extension(Path::new("a.cfg.bin.paths.json")),
+            Some(UpdateFormat::Paths)
+        );
+        assert_eq!(update_format_from_extension(Path::new("a.cfg.bin")), None);
+        assert_eq!(update_format_from_extension(Path::new("noext")), None);
+
+        assert_eq!(
+            sniff_update_format(b"  \n[{\"index\": 0}]"),
+            UpdateFormat::Json
+        );
+        assert_eq!(
+            sniff_update_format(b"\xEF\xBB\xBF{\"0x10\": \"a\"}"),
+            UpdateFormat::Json
+        );
+        assert_eq!(sniff_update_format(b"Hello\nWorld"), UpdateFormat::Txt);
+        assert_eq!(sniff_update_format(b""), UpdateFormat::Txt);
+
+        let explicit = resolve_update_format(Path::new("a.json"), Some(UpdateFormat::Txt));
+        assert_eq!(explicit.unwrap(), UpdateFormat::Txt);
+    }
+
+    #[test]
+    fn validate_sparse_indices_rejects_missing_and_duplicate_slots() {
+        let text = |index| TextEntry {
+            index,
+            entry: "TEXT".to_string(),
+            variable_index: 0,
+            value: String::new(),
+            hash: None,
+        };
+        assert!(validate_sparse_indices(&[text(4), text(0)], 5).is_ok());
+        assert!(validate_sparse_indices(&[], 0).is_ok());
+
+        let err = validate_sparse_indices(&[text(5)], 5).unwrap_err();
+        assert!(err.to_string().contains("index 5"));
+        assert!(validate_sparse_indices(&[text(1), text(1)], 5).is_err());
+    }
+
+    #[test]
+    fn wildcard_match_handles_star_and_question_mark() {
+        assert!(wildcard_match("*.json", "a.cfg.bin.json"));
+        assert!(wildcard_match("*.json", ".json"));
+        assert!(!wildcard_match("*.json", "a.json.txt"));
+        assert!(wildcard_match("shop_?.txt", "shop_1.txt"));
+        assert!(!wildcard_match("shop_?.txt", "shop_12.txt"));
+        assert!(wildcard_match("*menu*", "main_menu_text"));
+        assert!(wildcard_match("*", ""));
+    }
 }
@@ -0,0 +1,157 @@
+// Warnings raised while processing files. They go to stderr (unless --quiet) and, with
+// --warnings-json or --abort-on-warning, are also kept for the end of the run.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Warning {
+    pub category: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entry: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<u32>,
+}
+
+impl Warning {
+    pub fn new(category: &'static str, message: String) -> Self {
+        Warning {
+            category,
+            message,
+            file: None,
+            entry: None,
+            index: None,
+            offset: None,
+        }
+    }
+
+    pub fn with_entry(mut self, entry: &str, index: usize) -> Self {
+        self.entry = Some(entry.to_string());
+        self.index = Some(index);
+        self
+    }
+
+    pub fn with_offset(mut self, offset: u32) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+}
+
+/// The run's warning log, passed to everything that can warn.
+#[derive(Debug, Default)]
+pub struct Warnings {
+    quiet: bool,
+    collect: bool,
+    file: Option<String>,
+    warnings: Vec<Warning>,
+}
+
+impl Warnings {
+    pub fn new(quiet: bool, collect: bool) -> Self {
+        Warnings {
+            quiet,
+            collect,
+            ..Warnings::default()
+        }
+    }
+
+    /// Attributes subsequent warnings to `path` (one file at a time in batch commands).
+    pub fn set_file(&mut self, path: &Path) {
+        self.file = Some(path.display().to_string());
+    }
+
+    /// Prints the warning to stderr and records it.
+    pub fn emit(&mut self, warning: Warning) {
+        if let Some(line) = self.add(warning, true) {
+            eprintln!("{}", line);
+        }
+    }
+
+    /// Records a warning that the caller already reports in its own output.
+    pub fn record(&mut self, warning: Warning) {
+        self.add(warning, false);
+    }
+
+    /// Warnings collected during the run.
+    pub fn into_collected(self) -> Vec<Warning> {
+        self.warnings
+    }
+
+    // Tags the warning with the current file and keeps it if collecting; returns the line to
+    // print unless quiet (or `print` is off).
+    fn add(&mut self, mut warning: Warning, print: bool) -> Option<String> {
+        let line = (print && !self.quiet).then(|| format!("Warning: {}", warning.message));
+        if self.collect {
+            warning.file = warning.file.or_else(|| self.file.clone());
+            self.warnings.push(warning);
+        }
+        line
+    }
+}
+
+pub fn write_json(path: &Path, warnings: &[Warning]) -> Result<()> {
+    let json = serde_json::to_string_pretty(warnings)?;
+    fs::write(path, json).context("Failed to write warnings JSON")
+}
+
+/// One-line count per category, e.g. "2 index-mismatch, 1 unknown-crc".
+pub fn summary(warnings: &[Warning]) -> String {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for w in warnings {
+        *counts.entry(w.category).or_default() += 1;
+    }
+    let parts: Vec<String> = counts
+        .iter()
+        .map(|(category, n)| format!("{} {}", n, category))
+        .collect();
+    parts.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warnings_are_tagged_with_the_current_file_and_respect_quiet() -> Result<()> {
+        let mut c = Warnings::new(false, false);
+        let line = c.add(Warning::new("test", "not kept".into()), true);
+        assert_eq!(line.as_deref(), Some("Warning: not kept"));
+        assert!(c.warnings.is_empty());
+
+        let mut c = Warnings::new(true, true);
+        c.set_file(Path::new("a.cfg.bin"));
+        let warning = Warning::new("index-mismatch", "moved".into()).with_entry("ITEM", 3);
+        assert_eq!(c.add(warning, true), None);
+        let json = serde_json::to_value(c.into_collected())?;
+        assert_eq!(
+            json,
+            serde_json::json!([{
+                "category": "index-mismatch",
+                "message": "moved",
+                "file": "a.cfg.bin",
+                "entry": "ITEM",
+                "index": 3
+            }])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn summary_counts_warnings_per_category() {
+        let warnings = [
+            Warning::new("unknown-crc", "a".into()),
+            Warning::new("index-mismatch", "b".into()),
+            Warning::new("index-mismatch", "c".into()),
+        ];
+        assert_eq!(summary(&warnings), "2 index-mismatch, 1 unknown-crc");
+    }
+}